@@ -1,9 +1,23 @@
 use chrono::Local;
-use clap::{crate_authors, crate_description, crate_name, crate_version, load_yaml, App};
+use clap::{
+    crate_authors, crate_description, crate_name, crate_version, load_yaml, App, ErrorKind,
+};
 use log::{error, LevelFilter};
+use pwned_rs::subcommands::audit::run_subcommand as run_subcommand_audit;
+use pwned_rs::subcommands::check::run_subcommand as run_subcommand_check;
+use pwned_rs::subcommands::diff::run_subcommand as run_subcommand_diff;
+use pwned_rs::subcommands::exitcode::{get_check_exit_code, get_exit_code, EXIT_USAGE_ERROR};
+use pwned_rs::subcommands::export::run_subcommand as run_subcommand_export;
+use pwned_rs::subcommands::filterlookup::run_subcommand as run_subcommand_filterlookup;
 use pwned_rs::subcommands::lookup::run_subcommand as run_subcommand_lookup;
+use pwned_rs::subcommands::merge::run_subcommand as run_subcommand_merge;
 use pwned_rs::subcommands::optimize::run_subcommand as run_subcommand_optimize;
 use pwned_rs::subcommands::quicklookup::run_subcommand as run_subcommand_quicklookup;
+use pwned_rs::subcommands::serve::run_subcommand as run_subcommand_serve;
+use pwned_rs::subcommands::stats::run_subcommand as run_subcommand_stats;
+use pwned_rs::subcommands::update::run_subcommand as run_subcommand_update;
+use pwned_rs::subcommands::verify::run_subcommand as run_subcommand_verify;
+use std::process::exit;
 
 #[cfg(debug_assertions)]
 const LOGGING_LEVEL: LevelFilter = LevelFilter::Trace;
@@ -24,7 +38,7 @@ fn initialize_logging() {
             ))
         })
         .level(LOGGING_LEVEL)
-        .chain(std::io::stdout())
+        .chain(std::io::stderr())
         .apply();
 
     // ensure the logging framework was successfully initialized
@@ -43,16 +57,50 @@ fn main() {
         .version(crate_version!())
         .name(crate_name!())
         .about(crate_description!())
-        .get_matches();
+        .get_matches_safe();
+
+    // the help and the version are not an error, everything else the parser complains about is
+    // a usage error
+    let matches = match matches {
+        Ok(matches) => matches,
+        Err(error) => match error.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => error.exit(),
+            _ => {
+                eprintln!("{}", error.message);
+                exit(EXIT_USAGE_ERROR);
+            }
+        },
+    };
 
     // check which subcommand should be executed and call it
     if let Some(matches) = matches.subcommand_matches("optimize") {
         run_subcommand_optimize(matches);
     } else if let Some(matches) = matches.subcommand_matches("lookup") {
-        run_subcommand_lookup(matches);
+        exit(get_exit_code(run_subcommand_lookup(matches)));
     } else if let Some(matches) = matches.subcommand_matches("quick-lookup") {
-        run_subcommand_quicklookup(matches);
+        exit(get_exit_code(run_subcommand_quicklookup(matches)));
+    } else if let Some(matches) = matches.subcommand_matches("audit") {
+        run_subcommand_audit(matches);
+    } else if let Some(matches) = matches.subcommand_matches("serve") {
+        run_subcommand_serve(matches);
+    } else if let Some(matches) = matches.subcommand_matches("export") {
+        run_subcommand_export(matches);
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        exit(get_check_exit_code(run_subcommand_verify(matches)));
+    } else if let Some(matches) = matches.subcommand_matches("check") {
+        exit(get_check_exit_code(run_subcommand_check(matches)));
+    } else if let Some(matches) = matches.subcommand_matches("stats") {
+        run_subcommand_stats(matches);
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        run_subcommand_diff(matches);
+    } else if let Some(matches) = matches.subcommand_matches("update") {
+        run_subcommand_update(matches);
+    } else if let Some(matches) = matches.subcommand_matches("merge") {
+        run_subcommand_merge(matches);
+    } else if let Some(matches) = matches.subcommand_matches("filter-lookup") {
+        run_subcommand_filterlookup(matches);
     } else {
         error!("No known subcommand was selected. Please refer to the help for information about how to use this application.");
+        exit(EXIT_USAGE_ERROR);
     }
 }
@@ -0,0 +1,286 @@
+use crate::{HashAlgorithm, HashLineFormatError};
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// The characters used for formatting the digests, the hashes are always written in upper case
+/// (like in the password files of https://haveibeenpwned.com).
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Get the value of a single hexadecimal character (case insensitive).
+fn decode_hex_digit(character: u8) -> Option<u8> {
+    match character {
+        b'0'..=b'9' => Some(character - b'0'),
+        b'a'..=b'f' => Some(character - b'a' + 10),
+        b'A'..=b'F' => Some(character - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode the supplied hexadecimal string into the supplied buffer, the string has to contain
+/// exactly two characters for every byte of the buffer.
+fn decode_hex_into(input: &str, output: &mut [u8]) -> Result<(), HashLineFormatError> {
+    if input.len() != output.len() * 2 {
+        return Err(HashLineFormatError::NotAValidHash);
+    }
+    for (byte, pair) in output.iter_mut().zip(input.as_bytes().chunks(2)) {
+        match (decode_hex_digit(pair[0]), decode_hex_digit(pair[1])) {
+            (Some(high), Some(low)) => *byte = high << 4 | low,
+            _ => return Err(HashLineFormatError::NotAValidHash),
+        }
+    }
+    Ok(())
+}
+
+/// Get the value of the hexadecimal character at the supplied position of the raw bytes.
+// `is_multiple_of` would require a much newer compiler than the rest of the crate
+#[allow(clippy::manual_is_multiple_of)]
+fn get_nibble(bytes: &[u8], index: usize) -> u8 {
+    if index % 2 == 0 {
+        bytes[index / 2] >> 4
+    } else {
+        bytes[index / 2] & 0x0f
+    }
+}
+
+/// Write the raw bytes as upper case hexadecimal characters.
+fn format_hex(bytes: &[u8], f: &mut Formatter) -> FmtResult {
+    let mut buffer = [0u8; 40];
+    for index in 0..bytes.len() * 2 {
+        buffer[index] = HEX_DIGITS[get_nibble(bytes, index) as usize];
+    }
+    // the buffer just contains ASCII characters, so it is always valid UTF-8
+    f.write_str(std::str::from_utf8(&buffer[..bytes.len() * 2]).unwrap_or_default())
+}
+
+/// The raw SHA-1 hash of a password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sha1Digest(pub [u8; 20]);
+
+impl Sha1Digest {
+    /// Get the raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for Sha1Digest {
+    type Err = HashLineFormatError;
+
+    /// Parse the 40 hexadecimal characters of a SHA-1 hash (case insensitive).
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; 20];
+        decode_hex_into(input, &mut bytes)?;
+        Ok(Sha1Digest(bytes))
+    }
+}
+
+impl Display for Sha1Digest {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        format_hex(&self.0, f)
+    }
+}
+
+/// The raw NTLM hash (MD4 of the UTF-16LE encoded password) of a password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NtlmDigest(pub [u8; 16]);
+
+impl NtlmDigest {
+    /// Get the raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for NtlmDigest {
+    type Err = HashLineFormatError;
+
+    /// Parse the 32 hexadecimal characters of a NTLM hash (case insensitive).
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; 16];
+        decode_hex_into(input, &mut bytes)?;
+        Ok(NtlmDigest(bytes))
+    }
+}
+
+impl Display for NtlmDigest {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        format_hex(&self.0, f)
+    }
+}
+
+/// The raw hash of a password, created by one of the supported hash algorithms.
+///
+/// The hashes are parsed case insensitive and compared by their raw bytes, so `5baa61e4...` and
+/// `5BAA61E4...` are the same hash. They are always formatted in upper case.
+///
+/// # Example
+/// ```
+/// use pwned_rs::{HashAlgorithm, HashDigest};
+/// use std::str::FromStr;
+///
+/// let lower_case = HashDigest::from_str("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8").unwrap();
+/// let upper_case = HashDigest::from_str("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8").unwrap();
+/// assert_eq!(lower_case, upper_case);
+/// assert_eq!(HashAlgorithm::Sha1, lower_case.get_algorithm());
+/// assert_eq!("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", lower_case.to_string());
+/// assert_eq!(true, lower_case.starts_with_hex("5baa6"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashDigest {
+    Sha1(Sha1Digest),
+    Ntlm(NtlmDigest),
+}
+
+impl HashDigest {
+    /// Get the digest for the supplied raw bytes. The hash algorithm is determined by the number
+    /// of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<HashDigest> {
+        match bytes.len() {
+            20 => Some(HashDigest::Sha1(Sha1Digest(bytes.try_into().ok()?))),
+            16 => Some(HashDigest::Ntlm(NtlmDigest(bytes.try_into().ok()?))),
+            _ => None,
+        }
+    }
+
+    /// Get the smallest hash of the supplied algorithm which starts with the supplied hexadecimal
+    /// prefix (case insensitive), e.g. for finding the first hash of a range. Returns `None` if
+    /// the prefix is not hexadecimal or longer than the hashes of the algorithm.
+    pub fn from_hex_prefix(prefix: &str, hash_algorithm: HashAlgorithm) -> Option<HashDigest> {
+        let hash_length = hash_algorithm.get_hash_length();
+        if prefix.len() > hash_length {
+            return None;
+        }
+        let mut bytes = [0u8; 20];
+        for (index, character) in prefix.bytes().enumerate() {
+            let shift = if index % 2 == 0 { 4 } else { 0 };
+            bytes[index / 2] |= decode_hex_digit(character)? << shift;
+        }
+        HashDigest::from_bytes(&bytes[..hash_length / 2])
+    }
+
+    /// Get the raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            HashDigest::Sha1(digest) => digest.as_bytes(),
+            HashDigest::Ntlm(digest) => digest.as_bytes(),
+        }
+    }
+
+    /// Get the hash algorithm which created the hash.
+    pub fn get_algorithm(&self) -> HashAlgorithm {
+        match self {
+            HashDigest::Sha1(_) => HashAlgorithm::Sha1,
+            HashDigest::Ntlm(_) => HashAlgorithm::Ntlm,
+        }
+    }
+
+    /// Get the first characters of the (upper case) hexadecimal representation of the hash, or
+    /// `None` if the hash is shorter than the requested prefix.
+    pub fn get_hex_prefix(&self, length: usize) -> Option<String> {
+        let bytes = self.as_bytes();
+        if length > bytes.len() * 2 {
+            return None;
+        }
+        Some(
+            (0..length)
+                .map(|index| HEX_DIGITS[get_nibble(bytes, index) as usize] as char)
+                .collect(),
+        )
+    }
+
+    /// Check if the hexadecimal representation of the hash starts with the supplied prefix. The
+    /// comparison is case insensitive.
+    pub fn starts_with_hex(&self, prefix: &str) -> bool {
+        let bytes = self.as_bytes();
+        prefix.len() <= bytes.len() * 2
+            && prefix.bytes().enumerate().all(|(index, character)| {
+                decode_hex_digit(character) == Some(get_nibble(bytes, index))
+            })
+    }
+}
+
+impl FromStr for HashDigest {
+    type Err = HashLineFormatError;
+
+    /// Parse a hexadecimal hash (case insensitive). The hash algorithm is determined by the length
+    /// of the hash.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match HashAlgorithm::from_hash_length(input.len()) {
+            Some(HashAlgorithm::Sha1) => Ok(HashDigest::Sha1(Sha1Digest::from_str(input)?)),
+            Some(HashAlgorithm::Ntlm) => Ok(HashDigest::Ntlm(NtlmDigest::from_str(input)?)),
+            None => Err(HashLineFormatError::NotAValidHash),
+        }
+    }
+}
+
+impl Display for HashDigest {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        format_hex(self.as_bytes(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_are_parsed_case_insensitive_and_formatted_in_upper_case() {
+        let digest = HashDigest::from_str("5baa61e4C9B93F3F0682250B6CF8331B7EE68FD8").unwrap();
+        assert_eq!(HashAlgorithm::Sha1, digest.get_algorithm());
+        assert_eq!(0x5b, digest.as_bytes()[0]);
+        assert_eq!(
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8",
+            digest.to_string()
+        );
+        assert_eq!("5BAA6", digest.get_hex_prefix(5).unwrap());
+        assert_eq!(None, digest.get_hex_prefix(41));
+        assert_eq!(true, digest.starts_with_hex("5bAa61E"));
+        assert_eq!(false, digest.starts_with_hex("5BAB"));
+        assert_eq!(Some(digest), HashDigest::from_bytes(digest.as_bytes()));
+        assert_eq!(
+            format!("5BAA6{}", "0".repeat(35)),
+            HashDigest::from_hex_prefix("5baa6", HashAlgorithm::Sha1)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "00000000000000000000000000000000",
+            HashDigest::from_hex_prefix("", HashAlgorithm::Ntlm)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            None,
+            HashDigest::from_hex_prefix("5BAX", HashAlgorithm::Sha1)
+        );
+        assert_eq!(
+            None,
+            HashDigest::from_hex_prefix(&"0".repeat(33), HashAlgorithm::Ntlm)
+        );
+
+        let digest = HashDigest::from_str("8846f7eaee8fb117ad06bdd830b7586c").unwrap();
+        assert_eq!(HashAlgorithm::Ntlm, digest.get_algorithm());
+        assert_eq!("8846F7EAEE8FB117AD06BDD830B7586C", digest.to_string());
+
+        assert_eq!(
+            Err(HashLineFormatError::NotAValidHash),
+            HashDigest::from_str("5baa61e4c9b93f3f0682250b6cf8331b7ee68fdx")
+        );
+        assert_eq!(
+            Err(HashLineFormatError::NotAValidHash),
+            HashDigest::from_str("5baa61")
+        );
+    }
+
+    #[test]
+    fn digests_are_ordered_by_their_bytes() {
+        let lower = Sha1Digest::from_str("00000000A8DAE4228F821FB418F59826079BF368").unwrap();
+        let higher = Sha1Digest::from_str("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8").unwrap();
+        assert_eq!(true, lower < higher);
+        assert_eq!(
+            higher,
+            Sha1Digest::from_str("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8").unwrap()
+        );
+    }
+}
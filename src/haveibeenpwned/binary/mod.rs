@@ -0,0 +1,408 @@
+use crate::haveibeenpwned::{CreateInstanceError, FormatErrorKind};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use log::debug;
+use memmap2::Mmap;
+use std::cmp::Ordering;
+use std::convert::{TryFrom, TryInto};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The magic bytes every binary database file starts with.
+const BINARY_DATABASE_MAGIC: &[u8; 4] = b"PWND";
+
+/// The version of the binary format which is written by this version of the tool.
+const BINARY_DATABASE_VERSION: u8 = 1;
+
+/// The size of the header (magic, version, hash length, two reserved bytes and the record count).
+const BINARY_DATABASE_HEADER_SIZE: usize = 16;
+
+/// The number of bytes used for storing the occurrence count of a record.
+const OCCURRENCE_COUNT_SIZE: usize = 4;
+
+/// This class can be used to write the password hashes of a prefix into the compact binary format.
+///
+/// The written file starts with a header of 16 bytes which contains the magic bytes `PWND`, the
+/// version of the format, the length of the stored hashes in bytes, two reserved bytes and the
+/// number of stored records as an unsigned 64 bit integer (little endian). Each record consists of
+/// the raw hash followed by the occurrence count as an unsigned 32 bit integer (little endian).
+/// Entries whose count does not fit into 32 bits are rejected.
+pub struct BinaryDatabaseWriter {
+    output_file: BufWriter<File>,
+    hash_length: u8,
+    record_count: u64,
+}
+
+impl BinaryDatabaseWriter {
+    /// Create a new binary database file for hashes of the supplied algorithm at the supplied path.
+    /// An existing file will be replaced.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file could not be created or the (preliminary)
+    /// header could not be written to it.
+    pub fn create(
+        path_to_file: &Path,
+        algorithm: HashAlgorithm,
+    ) -> Result<BinaryDatabaseWriter, Error> {
+        let file_handle = OpenOptions::new()
+            .write(true)
+            .read(false)
+            .create(true)
+            .truncate(true)
+            .open(path_to_file)?;
+
+        // write a header with zero records, the real number is written as soon as we are finished
+        let hash_length = (algorithm.get_hash_length() / 2) as u8;
+        let mut output_file = BufWriter::new(file_handle);
+        output_file.write_all(&BinaryDatabaseWriter::get_header(hash_length, 0))?;
+
+        Ok(BinaryDatabaseWriter {
+            output_file,
+            hash_length,
+            record_count: 0,
+        })
+    }
+
+    fn get_header(hash_length: u8, record_count: u64) -> [u8; BINARY_DATABASE_HEADER_SIZE] {
+        let mut header = [0u8; BINARY_DATABASE_HEADER_SIZE];
+        header[..4].copy_from_slice(BINARY_DATABASE_MAGIC);
+        header[4] = BINARY_DATABASE_VERSION;
+        header[5] = hash_length;
+        header[8..].copy_from_slice(&record_count.to_le_bytes());
+        header
+    }
+
+    /// Append a single password hash entry to the file.
+    ///
+    /// The entries have to be written in ascending order of their hashes, otherwise the lookup
+    /// in the written file will not work.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the hash of the entry does not match the algorithm
+    /// the file was created for, if its occurrence count does not fit into 32 bits or if the
+    /// record could not be written.
+    pub fn write_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        let digest = entry.get_digest();
+        let raw_hash = match digest.as_bytes() {
+            raw_hash if raw_hash.len() == self.hash_length as usize => raw_hash,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "the hash of the entry does not match the hash algorithm of the file",
+                ))
+            }
+        };
+        let occurrences = match u32::try_from(entry.get_occurrences()) {
+            Ok(occurrences) => occurrences,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "the occurrence count of the entry does not fit into the binary format",
+                ))
+            }
+        };
+
+        self.output_file.write_all(raw_hash)?;
+        self.output_file.write_all(&occurrences.to_le_bytes())?;
+        self.record_count += 1;
+        Ok(())
+    }
+
+    /// Finish writing the file by storing the final record count in the header.
+    ///
+    /// Returns the number of records which were written into the file.
+    pub fn finish(mut self) -> Result<u64, Error> {
+        self.output_file.flush()?;
+        let file_handle = self.output_file.get_mut();
+        file_handle.seek(SeekFrom::Start(0))?;
+        file_handle.write_all(&BinaryDatabaseWriter::get_header(
+            self.hash_length,
+            self.record_count,
+        ))?;
+        file_handle.flush()?;
+        Ok(self.record_count)
+    }
+}
+
+/// This class can be used to look up password hashes in a file written by the
+/// [BinaryDatabaseWriter](struct.BinaryDatabaseWriter.html).
+///
+/// The file is memory-mapped, so just the records which are touched by a lookup are read from
+/// the disk.
+pub struct BinaryDatabaseReader {
+    mapped_file: Mmap,
+    hash_length: usize,
+    record_count: u64,
+}
+
+impl BinaryDatabaseReader {
+    /// Read the binary database stored in the supplied file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    ///  * The file does not exist or cannot be read.
+    ///  * The file does not start with the expected header.
+    ///  * The file was written with an unsupported version of the format.
+    ///  * The file contains less records than announced in the header.
+    pub fn from_file(path_to_file: &Path) -> Result<BinaryDatabaseReader, CreateInstanceError> {
+        let file_handle = match OpenOptions::new()
+            .append(false)
+            .create(false)
+            .read(true)
+            .open(path_to_file)
+        {
+            Ok(file_handle) => file_handle,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        // the file is only read and we expect that it is not modified while we are using it
+        let mapped_file = match unsafe { Mmap::map(&file_handle) } {
+            Ok(mapped_file) => mapped_file,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        debug!(
+            "Mapped {} bytes of the binary password hash file",
+            mapped_file.len()
+        );
+
+        // ensure that the file has a valid header
+        if mapped_file.len() < BINARY_DATABASE_HEADER_SIZE
+            || &mapped_file[..4] != BINARY_DATABASE_MAGIC
+        {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::NotABinaryDatabase,
+            ));
+        }
+        if mapped_file[4] != BINARY_DATABASE_VERSION {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::UnsupportedBinaryVersion,
+            ));
+        }
+        let hash_length = mapped_file[5] as usize;
+        let record_count = u64::from_le_bytes(mapped_file[8..16].try_into().unwrap());
+
+        // ensure that the announced records are really stored in the file
+        let records_size = (mapped_file.len() - BINARY_DATABASE_HEADER_SIZE) as u64;
+        let stride = (hash_length + OCCURRENCE_COUNT_SIZE) as u64;
+        if hash_length == 0 || record_count.checked_mul(stride) != Some(records_size) {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::TruncatedBinaryDatabase,
+            ));
+        }
+
+        Ok(BinaryDatabaseReader {
+            mapped_file,
+            hash_length,
+            record_count,
+        })
+    }
+
+    /// Get the records of the file (without its header).
+    fn get_records(&self) -> &[u8] {
+        &self.mapped_file[BINARY_DATABASE_HEADER_SIZE..]
+    }
+
+    /// Get the number of records stored in the database.
+    pub fn get_record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    /// Get the number of occurrences of the supplied (hexadecimal) password hash. The lookup is
+    /// done by a binary search over the fixed-size records and is case insensitive.
+    pub fn get_password_count(&self, password: String) -> Option<u64> {
+        self.get_digest_count(&HashDigest::from_str(password.as_str()).ok()?)
+    }
+
+    /// Get the number of occurrences of the supplied raw hash. The lookup is done by a binary
+    /// search over the fixed-size records.
+    pub fn get_digest_count(&self, digest: &HashDigest) -> Option<u64> {
+        let seeked_hash = digest.as_bytes();
+        if seeked_hash.len() != self.hash_length {
+            return None;
+        }
+
+        let records = self.get_records();
+        let stride = self.hash_length + OCCURRENCE_COUNT_SIZE;
+        let mut lower_bound = 0;
+        let mut upper_bound = self.record_count as usize;
+        while lower_bound < upper_bound {
+            let mid = lower_bound + (upper_bound - lower_bound) / 2;
+            let record = &records[mid * stride..(mid + 1) * stride];
+            match record[..self.hash_length].cmp(seeked_hash) {
+                Ordering::Equal => {
+                    let count = u32::from_le_bytes(record[self.hash_length..].try_into().unwrap());
+                    return Some(u64::from(count));
+                }
+                Ordering::Less => lower_bound = mid + 1,
+                Ordering::Greater => upper_bound = mid,
+            }
+        }
+        None
+    }
+
+    /// Get all entries whose hashes start with the supplied (hexadecimal) prefix, ordered by their
+    /// hashes. The first entry of the range is found by a binary search and the comparison of the
+    /// hashes is case insensitive.
+    pub fn get_range(&self, prefix: &str) -> Vec<PasswordHashEntry> {
+        let first_hash = match HashAlgorithm::from_hash_length(self.hash_length * 2)
+            .and_then(|algorithm| HashDigest::from_hex_prefix(prefix, algorithm))
+        {
+            Some(first_hash) => first_hash,
+            None => return Vec::new(),
+        };
+
+        // find the first record which is not less than the smallest hash with the prefix
+        let records = self.get_records();
+        let stride = self.hash_length + OCCURRENCE_COUNT_SIZE;
+        let mut lower_bound = 0;
+        let mut upper_bound = self.record_count as usize;
+        while lower_bound < upper_bound {
+            let mid = lower_bound + (upper_bound - lower_bound) / 2;
+            if &records[mid * stride..mid * stride + self.hash_length] < first_hash.as_bytes() {
+                lower_bound = mid + 1;
+            } else {
+                upper_bound = mid;
+            }
+        }
+
+        records[lower_bound * stride..]
+            .chunks(stride)
+            .map_while(|record| {
+                let digest = HashDigest::from_bytes(&record[..self.hash_length])?;
+                if !digest.starts_with_hex(prefix) {
+                    return None;
+                }
+                let occurrences = u32::from_le_bytes(record[self.hash_length..].try_into().ok()?);
+                Some(PasswordHashEntry::from_digest(
+                    digest,
+                    u64::from(occurrences),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use std::str::FromStr;
+
+    #[test]
+    fn written_binary_database_can_be_read_again() {
+        let file_path = TemporaryPath::new("roundtrip.bin");
+        let entries = [
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4",
+            "00000000A8DAE4228F821FB418F59826079BF368:2",
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493",
+        ];
+
+        let mut writer = BinaryDatabaseWriter::create(&file_path, HashAlgorithm::Sha1).unwrap();
+        for entry in entries.iter() {
+            writer
+                .write_entry(&PasswordHashEntry::from_str(entry).unwrap())
+                .unwrap();
+        }
+        assert_eq!(3, writer.finish().unwrap());
+        assert_eq!(
+            (BINARY_DATABASE_HEADER_SIZE + 3 * 24) as u64,
+            std::fs::metadata(&file_path).unwrap().len()
+        );
+
+        let reader = BinaryDatabaseReader::from_file(&file_path).unwrap();
+        assert_eq!(3, reader.get_record_count());
+        assert_eq!(
+            Some(4),
+            reader.get_password_count("000000005AD76BD555C1D6D771DE417A4B87E4B4".to_string())
+        );
+        assert_eq!(
+            Some(3861493),
+            reader.get_password_count("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8".to_string())
+        );
+        assert_eq!(
+            None,
+            reader.get_password_count("00000000A8DAE4228F821FB418F59826079BF369".to_string())
+        );
+        assert_eq!(2, reader.get_range("00000").len());
+        assert_eq!(1, reader.get_range("00000000A").len());
+        assert_eq!(3, reader.get_range("").len());
+        assert_eq!(0, reader.get_range("5BAA7").len());
+        assert_eq!(0, reader.get_range("FFFFF").len());
+        assert_eq!(0, reader.get_range("not a prefix").len());
+        assert_eq!(
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8",
+            reader.get_range("5baa6")[0].get_hash()
+        );
+    }
+
+    #[test]
+    fn ntlm_hashes_are_stored_with_their_own_length() {
+        let file_path = TemporaryPath::new("ntlm.bin");
+
+        let mut writer = BinaryDatabaseWriter::create(&file_path, HashAlgorithm::Ntlm).unwrap();
+        writer
+            .write_entry(
+                &PasswordHashEntry::from_str("8846F7EAEE8FB117AD06BDD830B7586C:7").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            true,
+            writer
+                .write_entry(&PasswordHashEntry::from_password("password"))
+                .is_err()
+        );
+        assert_eq!(1, writer.finish().unwrap());
+
+        let reader = BinaryDatabaseReader::from_file(&file_path).unwrap();
+        assert_eq!(
+            Some(7),
+            reader.get_password_count("8846f7eaee8fb117ad06bdd830b7586c".to_string())
+        );
+    }
+
+    #[test]
+    fn counts_which_do_not_fit_into_the_format_are_rejected() {
+        let file_path = TemporaryPath::new("oversized.bin");
+
+        let mut writer = BinaryDatabaseWriter::create(&file_path, HashAlgorithm::Sha1).unwrap();
+        let maximum_entry =
+            PasswordHashEntry::from_str("000000005AD76BD555C1D6D771DE417A4B87E4B4:4294967295")
+                .unwrap();
+        let oversized_entry =
+            PasswordHashEntry::from_str("00000000A8DAE4228F821FB418F59826079BF368:4294967296")
+                .unwrap();
+        writer.write_entry(&maximum_entry).unwrap();
+        let error = writer.write_entry(&oversized_entry).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        assert_eq!(1, writer.finish().unwrap());
+
+        let reader = BinaryDatabaseReader::from_file(&file_path).unwrap();
+        assert_eq!(
+            Some(u64::from(u32::MAX)),
+            reader.get_digest_count(&maximum_entry.get_digest())
+        );
+    }
+
+    #[test]
+    fn reading_a_file_without_a_valid_header_fails() {
+        let file_path = TemporaryPath::new("invalid.bin");
+        std::fs::write(&file_path, "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3").unwrap();
+
+        let maybe_instance = BinaryDatabaseReader::from_file(&file_path);
+        assert_eq!(true, maybe_instance.is_err());
+        assert_eq!(
+            true,
+            maybe_instance
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("not a binary password database")
+        );
+    }
+}
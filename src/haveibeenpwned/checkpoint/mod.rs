@@ -0,0 +1,352 @@
+use crate::haveibeenpwned::manifest::{DatabaseManifest, ManifestFileEntry};
+use crate::haveibeenpwned::{CreateInstanceError, FormatErrorKind};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The name of the file in which the progress of an optimization run is recorded.
+pub const CHECKPOINT_FILE_NAME: &str = "checkpoint.jsonl";
+
+/// The settings of an optimization run. A run can only be resumed with the same settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    source_file_name: String,
+    source_file_size: u64,
+    layout: DatabaseManifest,
+    chunks: Vec<(u64, u64)>,
+    /// The name and the size of the password file the source file was sorted from, if the
+    /// password file was not ordered by hash.
+    #[serde(default)]
+    original_file: Option<(String, u64)>,
+    /// The number of malformed lines which were skipped while sorting the password file.
+    #[serde(default)]
+    skipped_lines: u64,
+}
+
+/// Get the name of the supplied file (without its folder).
+fn get_file_name(path_to_file: &Path) -> String {
+    match path_to_file.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path_to_file.display().to_string(),
+    }
+}
+
+impl CheckpointHeader {
+    /// Create the header for optimizing the supplied password file into the supplied layout. The
+    /// chunks are the byte ranges of the password file which are optimized by separate threads.
+    pub fn new(
+        source_file: &Path,
+        layout: &DatabaseManifest,
+        chunks: Vec<(u64, u64)>,
+    ) -> Result<CheckpointHeader, Error> {
+        Ok(CheckpointHeader {
+            source_file_name: get_file_name(source_file),
+            source_file_size: std::fs::metadata(source_file)?.len(),
+            layout: layout.clone(),
+            chunks,
+            original_file: None,
+            skipped_lines: 0,
+        })
+    }
+
+    /// Record that the source file was sorted from the supplied password file, while the
+    /// supplied number of malformed lines were skipped.
+    pub fn set_original_file(
+        &mut self,
+        original_file: &Path,
+        skipped_lines: u64,
+    ) -> Result<(), Error> {
+        self.original_file = Some((
+            get_file_name(original_file),
+            std::fs::metadata(original_file)?.len(),
+        ));
+        self.skipped_lines = skipped_lines;
+        Ok(())
+    }
+
+    pub fn get_source_file_name(&self) -> &str {
+        &self.source_file_name
+    }
+
+    pub fn get_chunks(&self) -> &[(u64, u64)] {
+        &self.chunks
+    }
+
+    /// Get the number of malformed lines which were skipped while sorting the password file.
+    pub fn get_skipped_lines(&self) -> u64 {
+        self.skipped_lines
+    }
+
+    /// Check if a run with the supplied header optimizes the same file into the same layout.
+    pub fn is_compatible_with(&self, other: &CheckpointHeader) -> bool {
+        self.source_file_name == other.source_file_name
+            && self.source_file_size == other.source_file_size
+            && self.original_file == other.original_file
+            && self.layout.get_prefix_length() == other.layout.get_prefix_length()
+            && self.layout.is_nested() == other.layout.is_nested()
+            && self.layout.is_binary() == other.layout.is_binary()
+            && self.layout.get_hash_algorithm() == other.layout.get_hash_algorithm()
+    }
+}
+
+/// A file of the optimized database which was completely written (and renamed to its final name).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CompletedFile {
+    chunk: usize,
+    resume_offset: u64,
+    /// The number of malformed lines of the chunk which were skipped in front of the resume offset.
+    #[serde(default)]
+    skipped_lines: u64,
+    file: ManifestFileEntry,
+}
+
+/// The progress of an interrupted optimization run.
+///
+/// The checkpoint is an append-only file: the first line contains the settings of the run and
+/// every following line one file which was completely written, together with the (uncompressed)
+/// byte offset of the password file behind the last entry of the file. A run can be resumed from
+/// this offset, since all files in front of it are complete.
+pub struct Checkpoint {
+    header: CheckpointHeader,
+    completed_files: Vec<CompletedFile>,
+}
+
+impl Checkpoint {
+    /// Read the checkpoint stored in the supplied folder. If the folder does not contain a
+    /// checkpoint, `None` is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the checkpoint exists but could not be read.
+    pub fn from_folder(folder: &Path) -> Result<Option<Checkpoint>, CreateInstanceError> {
+        let checkpoint_file = match OpenOptions::new()
+            .read(true)
+            .open(folder.join(CHECKPOINT_FILE_NAME))
+        {
+            Ok(file_handle) => file_handle,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        let mut lines = BufReader::new(checkpoint_file).lines();
+        let header: CheckpointHeader = match lines.next() {
+            Some(Ok(line)) => match serde_json::from_str(&line) {
+                Ok(header) => header,
+                Err(_) => {
+                    return Err(CreateInstanceError::Format(
+                        FormatErrorKind::InvalidCheckpoint,
+                    ))
+                }
+            },
+            Some(Err(error)) => return Err(CreateInstanceError::Io(error)),
+            None => {
+                return Err(CreateInstanceError::Format(
+                    FormatErrorKind::InvalidCheckpoint,
+                ))
+            }
+        };
+
+        // the last line might be incomplete if the run was interrupted while writing it, the file
+        // of this line will just be written again
+        let mut completed_files = Vec::new();
+        for maybe_line in lines {
+            let line = match maybe_line {
+                Ok(line) => line,
+                Err(error) => return Err(CreateInstanceError::Io(error)),
+            };
+            match serde_json::from_str::<CompletedFile>(&line) {
+                Ok(completed_file) if completed_file.chunk < header.chunks.len() => {
+                    completed_files.push(completed_file)
+                }
+                _ => break,
+            }
+        }
+
+        Ok(Some(Checkpoint {
+            header,
+            completed_files,
+        }))
+    }
+
+    pub fn get_header(&self) -> &CheckpointHeader {
+        &self.header
+    }
+
+    /// Get the byte offset from which the supplied chunk has to be continued, if any file of the
+    /// chunk was already completed.
+    pub fn get_resume_offset(&self, chunk: usize) -> Option<u64> {
+        self.completed_files
+            .iter()
+            .filter(|completed_file| completed_file.chunk == chunk)
+            .map(|completed_file| completed_file.resume_offset)
+            .max()
+    }
+
+    /// Get the number of malformed lines of the supplied chunk which were skipped in front of its
+    /// resume offset.
+    pub fn get_skipped_lines(&self, chunk: usize) -> u64 {
+        self.completed_files
+            .iter()
+            .filter(|completed_file| completed_file.chunk == chunk)
+            .map(|completed_file| completed_file.skipped_lines)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get all completed files of the supplied chunk, in the order in which they were written.
+    pub fn get_completed_files(&self, chunk: usize) -> Vec<ManifestFileEntry> {
+        self.completed_files
+            .iter()
+            .filter(|completed_file| completed_file.chunk == chunk)
+            .map(|completed_file| completed_file.file.clone())
+            .collect()
+    }
+}
+
+/// This class records the progress of an optimization run. It can be shared by all threads which
+/// write files of the optimized database.
+pub struct CheckpointWriter {
+    path: PathBuf,
+    checkpoint_file: Mutex<File>,
+}
+
+impl CheckpointWriter {
+    /// Start a new checkpoint (replacing an existing one) for a run with the supplied settings.
+    pub fn create(folder: &Path, header: &CheckpointHeader) -> Result<CheckpointWriter, Error> {
+        let path = folder.join(CHECKPOINT_FILE_NAME);
+        let mut checkpoint_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        writeln!(checkpoint_file, "{}", serde_json::to_string(header)?)?;
+        checkpoint_file.sync_data()?;
+
+        Ok(CheckpointWriter {
+            path,
+            checkpoint_file: Mutex::new(checkpoint_file),
+        })
+    }
+
+    /// Continue the existing checkpoint in the supplied folder.
+    pub fn append(folder: &Path) -> Result<CheckpointWriter, Error> {
+        let path = folder.join(CHECKPOINT_FILE_NAME);
+        let checkpoint_file = OpenOptions::new().append(true).open(&path)?;
+
+        Ok(CheckpointWriter {
+            path,
+            checkpoint_file: Mutex::new(checkpoint_file),
+        })
+    }
+
+    /// Record that the supplied file of a chunk was completely written and that the chunk can be
+    /// resumed from the supplied byte offset of the password file. The number of malformed lines of
+    /// the chunk which were skipped so far is recorded as well.
+    pub fn add_completed_file(
+        &self,
+        chunk: usize,
+        resume_offset: u64,
+        skipped_lines: u64,
+        file: &ManifestFileEntry,
+    ) -> Result<(), Error> {
+        // the line is written at once, so an interruption can just cut off the last line
+        let mut line = serde_json::to_string(&CompletedFile {
+            chunk,
+            resume_offset,
+            skipped_lines,
+            file: file.clone(),
+        })?;
+        line.push('\n');
+
+        let mut checkpoint_file = match self.checkpoint_file.lock() {
+            Ok(checkpoint_file) => checkpoint_file,
+            Err(_) => return Err(Error::other("the checkpoint is not usable anymore")),
+        };
+        checkpoint_file.write_all(line.as_bytes())?;
+
+        // the file is already renamed to its final name, so the record has to survive a crash
+        checkpoint_file.sync_data()
+    }
+
+    /// Remove the checkpoint after the optimization run was completed.
+    pub fn remove(self) -> Result<(), Error> {
+        drop(self.checkpoint_file);
+        std::fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use crate::HashAlgorithm;
+
+    #[test]
+    fn a_written_checkpoint_can_be_read_again() {
+        let folder = TemporaryPath::new_folder("checkpoint");
+        assert_eq!(true, Checkpoint::from_folder(&folder).unwrap().is_none());
+
+        let source_file = folder.join("source.txt");
+        std::fs::write(
+            &source_file,
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        let shard_file = folder.join("5BA.txt");
+        std::fs::write(
+            &shard_file,
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        let layout = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        let header =
+            CheckpointHeader::new(&source_file, &layout, vec![(0, 100), (100, 200)]).unwrap();
+        let file_entry = ManifestFileEntry::from_file("5BA", &shard_file, 1).unwrap();
+
+        let writer = CheckpointWriter::create(&folder, &header).unwrap();
+        writer.add_completed_file(1, 150, 0, &file_entry).unwrap();
+        drop(writer);
+        let writer = CheckpointWriter::append(&folder).unwrap();
+        writer.add_completed_file(1, 180, 2, &file_entry).unwrap();
+        drop(writer);
+
+        // simulate an interrupted write of the last line
+        let mut checkpoint_file = OpenOptions::new()
+            .append(true)
+            .open(folder.join(CHECKPOINT_FILE_NAME))
+            .unwrap();
+        write!(checkpoint_file, "{{\"chunk\":0,\"resu").unwrap();
+        drop(checkpoint_file);
+
+        let checkpoint = Checkpoint::from_folder(&folder).unwrap().unwrap();
+        assert_eq!(true, checkpoint.get_header().is_compatible_with(&header));
+        assert_eq!(None, checkpoint.get_resume_offset(0));
+        assert_eq!(Some(180), checkpoint.get_resume_offset(1));
+        assert_eq!(0, checkpoint.get_skipped_lines(0));
+        assert_eq!(2, checkpoint.get_skipped_lines(1));
+        assert_eq!(0, checkpoint.get_completed_files(0).len());
+        assert_eq!(2, checkpoint.get_completed_files(1).len());
+
+        CheckpointWriter::append(&folder).unwrap().remove().unwrap();
+        assert_eq!(true, Checkpoint::from_folder(&folder).unwrap().is_none());
+    }
+
+    #[test]
+    fn checkpoints_of_different_runs_are_not_compatible() {
+        let source_file = TemporaryPath::new("checkpoint.txt");
+        std::fs::write(
+            &source_file,
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        let flat_layout = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        let nested_layout = DatabaseManifest::new(3, true, false, HashAlgorithm::Sha1).unwrap();
+
+        let header = CheckpointHeader::new(&source_file, &flat_layout, vec![(0, 49)]).unwrap();
+        let other_header =
+            CheckpointHeader::new(&source_file, &nested_layout, vec![(0, 49)]).unwrap();
+        assert_eq!(false, header.is_compatible_with(&other_header));
+    }
+}
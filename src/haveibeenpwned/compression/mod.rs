@@ -0,0 +1,303 @@
+use flate2::read::MultiGzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use xz2::read::XzDecoder;
+
+/// The size of the chunks which are passed from the thread extracting a 7-Zip archive.
+const EXTRACTION_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The number of chunks which can be extracted from a 7-Zip archive ahead of the reader.
+const EXTRACTION_CHUNKS_AHEAD: usize = 16;
+
+/// The formats in which the password files can be compressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressionFormat {
+    /// The file is not compressed.
+    Plain,
+    Gzip,
+    Zstd,
+    Xz,
+    SevenZip,
+}
+
+impl CompressionFormat {
+    /// Detect the compression format by the magic bytes at the beginning of a file.
+    ///
+    /// # Example
+    /// ```
+    /// use pwned_rs::haveibeenpwned::compression::CompressionFormat;
+    ///
+    /// assert_eq!(CompressionFormat::Gzip, CompressionFormat::from_magic_bytes(&[0x1f, 0x8b, 0x08]));
+    /// assert_eq!(CompressionFormat::Plain, CompressionFormat::from_magic_bytes(b"5BAA61E4"));
+    /// ```
+    pub fn from_magic_bytes(header: &[u8]) -> CompressionFormat {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            return CompressionFormat::Gzip;
+        }
+        if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return CompressionFormat::Zstd;
+        }
+        if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            return CompressionFormat::Xz;
+        }
+        if header.starts_with(&[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c]) {
+            return CompressionFormat::SevenZip;
+        }
+        CompressionFormat::Plain
+    }
+
+    /// Check if the file has to be decompressed while reading it.
+    pub fn is_compressed(self) -> bool {
+        self != CompressionFormat::Plain
+    }
+}
+
+impl Display for CompressionFormat {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            CompressionFormat::Plain => write!(f, "uncompressed"),
+            CompressionFormat::Gzip => write!(f, "gzip"),
+            CompressionFormat::Zstd => write!(f, "zstd"),
+            CompressionFormat::Xz => write!(f, "xz"),
+            CompressionFormat::SevenZip => write!(f, "7-Zip"),
+        }
+    }
+}
+
+/// A reader which counts the bytes which were read from the wrapped reader. The counter is shared,
+/// so the progress can be queried while the reader itself is owned by a decompressor.
+struct CountingReader<R> {
+    inner: R,
+    read_bytes: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let read_bytes = self.inner.read(buffer)?;
+        self.read_bytes
+            .fetch_add(read_bytes as u64, Ordering::Relaxed);
+        Ok(read_bytes)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(position)
+    }
+}
+
+/// A reader for the content which is extracted by a separate thread.
+struct ChannelReader {
+    receiver: Receiver<Result<Vec<u8>, Error>>,
+    current_chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        while self.position >= self.current_chunk.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.current_chunk = chunk;
+                    self.position = 0;
+                }
+                Ok(Err(error)) => return Err(error),
+                // the extracting thread finished, so there is no more content
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let available = &self.current_chunk[self.position..];
+        let read_bytes = available.len().min(buffer.len());
+        buffer[..read_bytes].copy_from_slice(&available[..read_bytes]);
+        self.position += read_bytes;
+        Ok(read_bytes)
+    }
+}
+
+/// Extract the first file of the 7-Zip archive in a separate thread, since the archive can only be
+/// read by a callback for every entry.
+fn extract_seven_zip_archive(
+    archive: CountingReader<File>,
+    archive_size: u64,
+) -> Result<ChannelReader, Error> {
+    let mut seven_zip_reader = match SevenZReader::new(archive, archive_size, Password::empty()) {
+        Ok(reader) => reader,
+        Err(error) => return Err(Error::new(ErrorKind::InvalidData, error.to_string())),
+    };
+
+    let (sender, receiver) = sync_channel(EXTRACTION_CHUNKS_AHEAD);
+    thread::spawn(move || {
+        let result = seven_zip_reader.for_each_entries(|entry, entry_reader| {
+            if entry.is_directory() || !entry.has_stream() {
+                return Ok(true);
+            }
+            loop {
+                let mut chunk = vec![0; EXTRACTION_CHUNK_SIZE];
+                let read_bytes = entry_reader.read(&mut chunk)?;
+                if read_bytes == 0 {
+                    break;
+                }
+                chunk.truncate(read_bytes);
+
+                // if the reader is gone, nobody is interested in the rest of the archive
+                if sender.send(Ok(chunk)).is_err() {
+                    break;
+                }
+            }
+
+            // the password files are the only entries in the archives, so we are done now
+            Ok(false)
+        });
+        if let Err(error) = result {
+            let _ = sender.send(Err(Error::new(ErrorKind::InvalidData, error.to_string())));
+        }
+    });
+
+    Ok(ChannelReader {
+        receiver,
+        current_chunk: Vec::new(),
+        position: 0,
+    })
+}
+
+/// A (possibly compressed) password file which is decompressed while reading it.
+pub(crate) struct SourceReader {
+    pub(crate) reader: Box<dyn Read + Send>,
+    pub(crate) format: CompressionFormat,
+    pub(crate) read_bytes: Arc<AtomicU64>,
+}
+
+impl SourceReader {
+    /// Open the supplied file and detect if it has to be decompressed.
+    pub(crate) fn open(path_to_file: &Path) -> Result<SourceReader, Error> {
+        let mut file_handle = OpenOptions::new().read(true).open(path_to_file)?;
+        let file_size = file_handle.metadata()?.len();
+
+        // determine the compression format by the first bytes of the file
+        let mut header = [0; 6];
+        let mut header_length = 0;
+        while header_length < header.len() {
+            let read_bytes = file_handle.read(&mut header[header_length..])?;
+            if read_bytes == 0 {
+                break;
+            }
+            header_length += read_bytes;
+        }
+        file_handle.seek(SeekFrom::Start(0))?;
+        let format = CompressionFormat::from_magic_bytes(&header[..header_length]);
+
+        let read_bytes = Arc::new(AtomicU64::new(0));
+        let counting_reader = CountingReader {
+            inner: file_handle,
+            read_bytes: Arc::clone(&read_bytes),
+        };
+        let reader: Box<dyn Read + Send> = match format {
+            CompressionFormat::Plain => Box::new(counting_reader),
+            CompressionFormat::Gzip => Box::new(MultiGzDecoder::new(counting_reader)),
+            CompressionFormat::Zstd => Box::new(zstd::stream::read::Decoder::new(counting_reader)?),
+            CompressionFormat::Xz => Box::new(XzDecoder::new_multi_decoder(counting_reader)),
+            CompressionFormat::SevenZip => {
+                Box::new(extract_seven_zip_archive(counting_reader, file_size)?)
+            }
+        };
+
+        Ok(SourceReader {
+            reader,
+            format,
+            read_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use std::io::Write;
+
+    const CONTENT: &str = "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n";
+
+    fn read_source_reader(path_to_file: &Path) -> (CompressionFormat, String) {
+        let mut source_reader = SourceReader::open(path_to_file).unwrap();
+        let mut content = String::new();
+        source_reader.reader.read_to_string(&mut content).unwrap();
+        assert_eq!(
+            std::fs::metadata(path_to_file).unwrap().len(),
+            source_reader.read_bytes.load(Ordering::Relaxed)
+        );
+        (source_reader.format, content)
+    }
+
+    #[test]
+    fn plain_files_are_read_unchanged() {
+        let path_to_file = TemporaryPath::new("compression.txt");
+        std::fs::write(&path_to_file, CONTENT).unwrap();
+        assert_eq!(
+            (CompressionFormat::Plain, CONTENT.to_string()),
+            read_source_reader(&path_to_file)
+        );
+    }
+
+    #[test]
+    fn gzip_files_are_decompressed() {
+        let path_to_file = TemporaryPath::new("compression.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&path_to_file).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(CONTENT.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(
+            (CompressionFormat::Gzip, CONTENT.to_string()),
+            read_source_reader(&path_to_file)
+        );
+    }
+
+    #[test]
+    fn zstd_files_are_decompressed() {
+        let path_to_file = TemporaryPath::new("compression.txt.zst");
+        std::fs::write(
+            &path_to_file,
+            zstd::encode_all(CONTENT.as_bytes(), 3).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            (CompressionFormat::Zstd, CONTENT.to_string()),
+            read_source_reader(&path_to_file)
+        );
+    }
+
+    #[test]
+    fn xz_files_are_decompressed() {
+        let path_to_file = TemporaryPath::new("compression.txt.xz");
+        let mut encoder = xz2::write::XzEncoder::new(File::create(&path_to_file).unwrap(), 6);
+        encoder.write_all(CONTENT.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(
+            (CompressionFormat::Xz, CONTENT.to_string()),
+            read_source_reader(&path_to_file)
+        );
+    }
+
+    #[test]
+    fn seven_zip_archives_are_extracted() {
+        let text_path = TemporaryPath::new("compression-7z.txt");
+        let archive_path = TemporaryPath::new("compression.7z");
+        std::fs::write(&text_path, CONTENT).unwrap();
+        sevenz_rust::compress_to_path(&text_path, &archive_path).unwrap();
+
+        let mut source_reader = SourceReader::open(&archive_path).unwrap();
+        let mut content = String::new();
+        source_reader.reader.read_to_string(&mut content).unwrap();
+        assert_eq!(CompressionFormat::SevenZip, source_reader.format);
+        assert_eq!(CONTENT, content);
+    }
+}
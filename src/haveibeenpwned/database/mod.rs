@@ -0,0 +1,274 @@
+use crate::haveibeenpwned::optimized::OptimizedDatabase;
+use crate::haveibeenpwned::policy::{ThresholdPolicy, Verdict};
+use crate::haveibeenpwned::{CreateInstanceError, SortedHashFile};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use std::collections::BTreeMap;
+
+/// The common interface of all password databases, so the code looking up passwords does not
+/// have to know if the hashes are stored in a sorted password file, in an optimized database or
+/// in memory.
+///
+/// # Example
+/// ```
+/// use pwned_rs::haveibeenpwned::database::{InMemoryDatabase, PasswordDatabase};
+/// use pwned_rs::{HashAlgorithm, HashDigest, PasswordHashEntry};
+///
+/// fn count_leaked_passwords(database: &dyn PasswordDatabase, passwords: &[&str]) -> usize {
+///     let hashes: Vec<HashDigest> = passwords
+///         .iter()
+///         .map(|password| HashAlgorithm::Sha1.hash_password(password))
+///         .collect();
+///     match database.lookup_many(&hashes) {
+///         Ok(counts) => counts.iter().filter(|count| count.is_some()).count(),
+///         Err(error) => panic!("Could not look up the passwords. The error was: {}", error),
+///     }
+/// }
+///
+/// let mut database = InMemoryDatabase::new();
+/// database.insert(&PasswordHashEntry::from_password("password"));
+/// assert_eq!(1, count_leaked_passwords(&database, &["password", "not leaked"]));
+/// ```
+pub trait PasswordDatabase {
+    /// Get the number of occurrences of the supplied password hash (`None` if it was not found in
+    /// the database).
+    ///
+    /// # Errors
+    /// Returns an error if the part of the database which should contain the hash could not be
+    /// read.
+    fn lookup(&self, hash: &HashDigest) -> Result<Option<u64>, CreateInstanceError>;
+
+    /// Get the number of occurrences of every supplied password hash, in the same order as the
+    /// hashes.
+    ///
+    /// # Errors
+    /// Returns the first error which occurred while looking up the hashes.
+    fn lookup_many(&self, hashes: &[HashDigest]) -> Result<Vec<Option<u64>>, CreateInstanceError> {
+        hashes.iter().map(|hash| self.lookup(hash)).collect()
+    }
+
+    /// Get the verdict of the supplied policy for the supplied password hash.
+    ///
+    /// # Errors
+    /// Returns an error if the part of the database which should contain the hash could not be
+    /// read.
+    fn get_verdict(
+        &self,
+        hash: &HashDigest,
+        policy: &ThresholdPolicy,
+    ) -> Result<Verdict, CreateInstanceError> {
+        Ok(policy.get_verdict(self.lookup(hash)?))
+    }
+
+    /// Get all entries whose hashes start with the supplied (hexadecimal) prefix, ordered by their
+    /// hashes. The comparison of the hashes is case insensitive.
+    ///
+    /// # Errors
+    /// Returns an error if the part of the database which contains the prefix could not be read.
+    fn get_range(&self, prefix: &str) -> Result<Vec<PasswordHashEntry>, CreateInstanceError>;
+
+    /// Get the hash algorithm of the hashes stored in the database, if it is known.
+    fn get_hash_algorithm(&self) -> Option<HashAlgorithm>;
+}
+
+impl PasswordDatabase for SortedHashFile {
+    fn lookup(&self, hash: &HashDigest) -> Result<Option<u64>, CreateInstanceError> {
+        Ok(self.get_digest_count(hash))
+    }
+
+    fn get_range(&self, prefix: &str) -> Result<Vec<PasswordHashEntry>, CreateInstanceError> {
+        Ok(SortedHashFile::get_range(self, prefix))
+    }
+
+    fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        SortedHashFile::get_hash_algorithm(self)
+    }
+}
+
+impl PasswordDatabase for OptimizedDatabase {
+    fn lookup(&self, hash: &HashDigest) -> Result<Option<u64>, CreateInstanceError> {
+        self.get_digest_count(hash)
+    }
+
+    fn get_range(&self, prefix: &str) -> Result<Vec<PasswordHashEntry>, CreateInstanceError> {
+        OptimizedDatabase::get_range(self, prefix)
+    }
+
+    fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        OptimizedDatabase::get_hash_algorithm(self)
+    }
+}
+
+/// This class can be used to keep a (small) password database in memory, e.g. a list of
+/// passwords which are forbidden in an organization or the data of a test. The hashes are stored
+/// as raw digests, so the lookups are case insensitive.
+#[derive(Debug, Default)]
+pub struct InMemoryDatabase {
+    password_hashes: BTreeMap<HashDigest, u64>,
+    hash_algorithm: Option<HashAlgorithm>,
+}
+
+impl InMemoryDatabase {
+    /// Create an empty database.
+    pub fn new() -> InMemoryDatabase {
+        InMemoryDatabase::default()
+    }
+
+    /// Create a database which contains all supplied entries.
+    pub fn from_entries<I: IntoIterator<Item = PasswordHashEntry>>(entries: I) -> InMemoryDatabase {
+        let mut database = InMemoryDatabase::new();
+        for entry in entries {
+            database.insert(&entry);
+        }
+        database
+    }
+
+    /// Add the supplied entry to the database. The occurrences of an entry with the same hash are
+    /// replaced. The hash algorithm of the database is taken from the first entry.
+    pub fn insert(&mut self, entry: &PasswordHashEntry) {
+        self.hash_algorithm.get_or_insert(entry.get_algorithm());
+        self.password_hashes
+            .insert(entry.get_digest(), entry.get_occurrences());
+    }
+
+    /// Get the number of entries stored in the database.
+    pub fn len(&self) -> usize {
+        self.password_hashes.len()
+    }
+
+    /// Check if the database does not contain any entry.
+    pub fn is_empty(&self) -> bool {
+        self.password_hashes.is_empty()
+    }
+}
+
+impl PasswordDatabase for InMemoryDatabase {
+    fn lookup(&self, hash: &HashDigest) -> Result<Option<u64>, CreateInstanceError> {
+        Ok(self.password_hashes.get(hash).copied())
+    }
+
+    fn get_range(&self, prefix: &str) -> Result<Vec<PasswordHashEntry>, CreateInstanceError> {
+        // the entries are ordered by their hashes, so the range starts with the smallest hash
+        // which has the prefix
+        let first_hash = match self
+            .hash_algorithm
+            .and_then(|hash_algorithm| HashDigest::from_hex_prefix(prefix, hash_algorithm))
+        {
+            Some(first_hash) => first_hash,
+            None => return Ok(Vec::new()),
+        };
+        Ok(self
+            .password_hashes
+            .range(first_hash..)
+            .take_while(|(hash, _)| hash.starts_with_hex(prefix))
+            .map(|(hash, occurrences)| PasswordHashEntry::from_digest(*hash, *occurrences))
+            .collect())
+    }
+
+    fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.hash_algorithm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::manifest::DatabaseManifest;
+    use crate::testing::TemporaryPath;
+
+    fn get_entry(hash: &str, occurrences: u64) -> PasswordHashEntry {
+        let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+        entry.occurrences = occurrences;
+        entry
+    }
+
+    /// Check that the supplied database contains exactly the entries of the test data.
+    fn check_database(database: &dyn PasswordDatabase) {
+        assert_eq!(
+            vec![Some(4), Some(3861493), None],
+            database
+                .lookup_many(&[
+                    get_entry("000000005ad76bd555c1d6d771de417a4b87e4b4", 0).get_digest(),
+                    HashAlgorithm::Sha1.hash_password("password"),
+                    HashAlgorithm::Sha1.hash_password("not leaked"),
+                ])
+                .unwrap()
+        );
+        assert_eq!(
+            Verdict::Warn(2),
+            database
+                .get_verdict(
+                    &get_entry("00000000A8DAE4228F821FB418F59826079BF368", 0).get_digest(),
+                    &ThresholdPolicy::new(3)
+                )
+                .unwrap()
+        );
+        let range: Vec<(String, u64)> = database
+            .get_range("00000")
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.get_hash(), entry.get_occurrences()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("000000005AD76BD555C1D6D771DE417A4B87E4B4".to_string(), 4),
+                ("00000000A8DAE4228F821FB418F59826079BF368".to_string(), 2),
+            ],
+            range
+        );
+        assert_eq!(Some(HashAlgorithm::Sha1), database.get_hash_algorithm());
+    }
+
+    /// Run the supplied check against every backend, each of them filled with the test data.
+    fn check_all_backends(name: &str, check: fn(&dyn PasswordDatabase)) {
+        let file_path = TemporaryPath::new(&format!("{}.txt", name));
+        std::fs::write(
+            &file_path,
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\r\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n",
+        )
+        .unwrap();
+        check(&SortedHashFile::from_file(&file_path).unwrap());
+
+        let folder = TemporaryPath::new_folder(name);
+        DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1)
+            .unwrap()
+            .write_to_folder(&folder)
+            .unwrap();
+        std::fs::write(
+            folder.join("000.txt"),
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            folder.join("5BA.txt"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        check(&OptimizedDatabase::from_folder(&folder).unwrap());
+
+        let database = InMemoryDatabase::from_entries(vec![
+            get_entry("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8", 3861493),
+            get_entry("00000000A8DAE4228F821FB418F59826079BF368", 2),
+            get_entry("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+        ]);
+        assert_eq!(3, database.len());
+        check(&database);
+    }
+
+    #[test]
+    fn all_backends_return_the_same_results() {
+        check_all_backends("database-backends", check_database);
+    }
+
+    #[test]
+    fn all_backends_ignore_invalid_prefixes() {
+        check_all_backends("database-prefixes", |database| {
+            for prefix in &["ééé", "../x", "5BAA6 ", "-1", &"0".repeat(41)] {
+                assert_eq!(0, database.get_range(prefix).unwrap().len());
+            }
+            assert_eq!(3, database.get_range("").unwrap().len());
+        });
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{HashDigest, PasswordHashEntry};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{create_dir_all, remove_dir_all, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The default amount of memory (in MiB) which is used for sorting the entries.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1024;
+
+/// The size of the buffer of every sorted run while they are merged.
+const RUN_BUFFER_SIZE: usize = 256 * 1024;
+
+/// An entry of a sorted run, ordered by its hash.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct RunEntry {
+    hash: HashDigest,
+    occurrences: u64,
+}
+
+/// A sorted run which was written to a temporary file.
+struct RunReader {
+    reader: BufReader<File>,
+    line: String,
+}
+
+impl RunReader {
+    fn open(path_to_run: &Path) -> Result<RunReader, Error> {
+        Ok(RunReader {
+            reader: BufReader::with_capacity(RUN_BUFFER_SIZE, File::open(path_to_run)?),
+            line: String::new(),
+        })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<RunEntry>, Error> {
+        self.line.clear();
+        if self.reader.read_line(&mut self.line)? == 0 {
+            return Ok(None);
+        }
+
+        // the runs were written by us, so every line has to be valid
+        let invalid_run = || Error::new(ErrorKind::InvalidData, "a sorted run is corrupted");
+        let (hash, occurrences) = self
+            .line
+            .trim_end()
+            .split_once(':')
+            .ok_or_else(invalid_run)?;
+        Ok(Some(RunEntry {
+            hash: HashDigest::from_str(hash).map_err(|_| invalid_run())?,
+            occurrences: occurrences.parse().map_err(|_| invalid_run())?,
+        }))
+    }
+}
+
+/// This class can be used to sort password entries which do not fit into the memory. The entries
+/// are collected until the memory limit is reached, then they are sorted and written to a
+/// temporary file (a so called run). Finally, all runs are merged into a single sorted file.
+///
+/// # Example
+/// ```
+/// use pwned_rs::haveibeenpwned::external_sort::ExternalSorter;
+/// use pwned_rs::PasswordHashEntry;
+///
+/// let temporary_folder = std::env::temp_dir().join("pwned-rs-example-sort");
+/// match ExternalSorter::new(&temporary_folder, 1024) {
+///     Ok(mut sorter) => {
+///         let _ = sorter.add_entry(&PasswordHashEntry::from_password("password"));
+///         let mut sorted_content = Vec::new();
+///         match sorter.write_sorted(&mut sorted_content) {
+///             Ok(entries) => println!("Sorted {} entries", entries),
+///             Err(error) => println!("Could not sort the entries, the error was: {}", error),
+///         }
+///     }
+///     Err(error) => println!("Could not create the temporary folder, the error was: {}", error),
+/// }
+/// ```
+pub struct ExternalSorter {
+    temporary_folder: PathBuf,
+    memory_limit: usize,
+    used_memory: usize,
+    entries: Vec<RunEntry>,
+    runs: Vec<PathBuf>,
+}
+
+impl ExternalSorter {
+    /// Create a new sorter which stores its runs in the supplied folder (which is created if it
+    /// does not exist) and uses about the supplied amount of memory (in MiB) for sorting.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the temporary folder could not be created.
+    pub fn new(temporary_folder: &Path, memory_limit: usize) -> Result<ExternalSorter, Error> {
+        create_dir_all(temporary_folder)?;
+        Ok(ExternalSorter {
+            temporary_folder: temporary_folder.to_path_buf(),
+            memory_limit: memory_limit.max(1) * 1024 * 1024,
+            used_memory: 0,
+            entries: Vec::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    /// Get the number of runs which were written to the temporary folder so far.
+    pub fn get_number_of_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Add an entry which should be sorted. If the memory limit is reached, the collected entries
+    /// are written to a new run.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the run could not be written.
+    pub fn add_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        self.used_memory += size_of::<RunEntry>();
+        self.entries.push(RunEntry {
+            hash: entry.get_digest(),
+            occurrences: entry.get_occurrences(),
+        });
+
+        if self.used_memory >= self.memory_limit {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the collected entries and write them to a new run.
+    fn write_run(&mut self) -> Result<(), Error> {
+        self.entries.sort_unstable();
+
+        let run_path = self
+            .temporary_folder
+            .join(format!("run-{:05}.txt", self.runs.len()));
+        let mut run_writer = BufWriter::new(File::create(&run_path)?);
+        for entry in self.entries.drain(..) {
+            writeln!(run_writer, "{}:{}", entry.hash, entry.occurrences)?;
+        }
+        run_writer.flush()?;
+
+        self.runs.push(run_path);
+        self.used_memory = 0;
+        Ok(())
+    }
+
+    /// Merge all entries into the supplied writer (ordered by their hash) and remove the temporary
+    /// folder. The number of written entries is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a run could not be read or the output could not be
+    /// written.
+    pub fn write_sorted<W: Write>(mut self, output: W) -> Result<u64, Error> {
+        let mut output = BufWriter::new(output);
+        let mut written_entries = 0;
+
+        // if everything fitted into the memory, there is no need for merging
+        if self.runs.is_empty() {
+            self.entries.sort_unstable();
+            for entry in self.entries.drain(..) {
+                writeln!(output, "{}:{}", entry.hash, entry.occurrences)?;
+                written_entries += 1;
+            }
+        } else {
+            if !self.entries.is_empty() {
+                self.write_run()?;
+            }
+
+            // always write the lowest entry of all runs and replace it by the next entry of its run
+            let mut run_readers = Vec::new();
+            let mut lowest_entries = BinaryHeap::new();
+            for (run_index, run_path) in self.runs.iter().enumerate() {
+                let mut run_reader = RunReader::open(run_path)?;
+                if let Some(entry) = run_reader.next_entry()? {
+                    lowest_entries.push(Reverse((entry, run_index)));
+                }
+                run_readers.push(run_reader);
+            }
+            while let Some(Reverse((entry, run_index))) = lowest_entries.pop() {
+                writeln!(output, "{}:{}", entry.hash, entry.occurrences)?;
+                written_entries += 1;
+                if let Some(next_entry) = run_readers[run_index].next_entry()? {
+                    lowest_entries.push(Reverse((next_entry, run_index)));
+                }
+            }
+        }
+        output.flush()?;
+
+        remove_dir_all(&self.temporary_folder)?;
+        Ok(written_entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::DatabaseIterator;
+    use crate::testing::TemporaryPath;
+
+    fn sort_entries(hashes: &[&str], memory_limit: usize, name: &str) -> (usize, String) {
+        let temporary_folder = TemporaryPath::new(name);
+        let mut sorter = ExternalSorter::new(&temporary_folder, memory_limit).unwrap();
+        for (index, hash) in hashes.iter().enumerate() {
+            let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+            entry.occurrences = index as u64;
+            sorter.add_entry(&entry).unwrap();
+        }
+        let number_of_runs = sorter.get_number_of_runs();
+
+        let mut sorted_content = Vec::new();
+        assert_eq!(
+            hashes.len() as u64,
+            sorter.write_sorted(&mut sorted_content).unwrap()
+        );
+        assert_eq!(false, temporary_folder.exists());
+        (number_of_runs, String::from_utf8(sorted_content).unwrap())
+    }
+
+    #[test]
+    fn entries_are_sorted_in_memory() {
+        let (number_of_runs, sorted_content) = sort_entries(
+            &[
+                "7C4A8D09CA3762AF61E59520943DC26494F8941B",
+                "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8",
+                "000000005AD76BD555C1D6D771DE417A4B87E4B4",
+            ],
+            1,
+            "sort-memory",
+        );
+        assert_eq!(0, number_of_runs);
+        assert_eq!(
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:2\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:1\n7C4A8D09CA3762AF61E59520943DC26494F8941B:0\n",
+            sorted_content
+        );
+    }
+
+    #[test]
+    fn runs_are_merged_into_a_sorted_file() {
+        // the entries need about 3 MiB, so several runs have to be written
+        let hashes: Vec<String> = (0..100_000_u64)
+            .map(|index| format!("{:040X}", index.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 8))
+            .collect();
+        let hash_references: Vec<&str> = hashes.iter().map(String::as_str).collect();
+        let (number_of_runs, sorted_content) = sort_entries(&hash_references, 1, "sort-runs");
+        assert_eq!(true, number_of_runs > 1);
+
+        let path_to_file = TemporaryPath::new("sorted.txt");
+        std::fs::write(&path_to_file, &sorted_content).unwrap();
+        let parser = DatabaseIterator::from_file(path_to_file.to_str().unwrap()).unwrap();
+        assert_eq!(
+            crate::haveibeenpwned::EntryOrder::ByHash,
+            parser.get_entry_order()
+        );
+        let sorted_hashes: Vec<String> = parser.map(|entry| entry.unwrap().get_hash()).collect();
+        let mut expected_hashes = hashes.clone();
+        expected_hashes.sort();
+        assert_eq!(expected_hashes, sorted_hashes);
+    }
+}
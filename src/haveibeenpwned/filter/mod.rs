@@ -0,0 +1,415 @@
+use crate::haveibeenpwned::{CreateInstanceError, FormatErrorKind};
+use crate::{HashAlgorithm, PasswordHashEntry};
+use log::debug;
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::OpenOptions;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// The magic bytes every password filter file starts with.
+const FILTER_MAGIC: &[u8; 4] = b"PWBF";
+
+/// The version of the filter format which is written by this version of the tool.
+const FILTER_VERSION: u8 = 1;
+
+/// The size of the header (magic, version, hash length, number of hash functions, one reserved
+/// byte, the number of bits and the number of entries).
+const FILTER_HEADER_SIZE: usize = 24;
+
+/// The highest number of hash functions which is used for a single entry.
+const MAXIMUM_HASH_FUNCTIONS: u64 = 32;
+
+/// The false positive rate which is used if no other one is selected.
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// The possible errors while creating or filling a password filter.
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    /// The false positive rate is not between 0 and 1 (both excluded).
+    FalsePositiveRateOutOfRange,
+    /// The hash of the entry does not match the hash algorithm of the filter.
+    HashAlgorithmMismatch,
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            FilterError::FalsePositiveRateOutOfRange => write!(
+                f,
+                "The false positive rate has to be greater than 0 and less than 1"
+            ),
+            FilterError::HashAlgorithmMismatch => write!(
+                f,
+                "The hash of the entry does not match the hash algorithm of the filter"
+            ),
+        }
+    }
+}
+
+/// This class can be used to check if a password hash is part of a password database without
+/// shipping the whole database. It is a Bloom filter, so a hash which was added to the filter is
+/// always found (there are no false negatives), but a hash which was not added is also reported
+/// as found with the (configurable) false positive rate.
+///
+/// Every hash sets a number of bits in a bit array. Since the password hashes are already
+/// uniformly distributed, the positions of the bits are derived directly from the first 16 bytes
+/// of the hash (double hashing) instead of hashing it again.
+///
+/// The written file starts with a header of 24 bytes which contains the magic bytes `PWBF`, the
+/// version of the format, the length of the stored hashes in bytes, the number of bits which are
+/// set for every hash, one reserved byte, the number of bits of the bit array and the number of
+/// added entries (both as unsigned 64 bit integers, little endian). The bit array follows the
+/// header.
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    number_of_bits: u64,
+    number_of_hash_functions: u8,
+    entry_count: u64,
+    hash_algorithm: HashAlgorithm,
+}
+
+impl BloomFilter {
+    /// Create an empty filter which can store the supplied number of entries with the supplied
+    /// false positive rate.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the false positive rate is not greater than 0 and
+    /// less than 1.
+    ///
+    /// # Example
+    /// ```
+    /// use pwned_rs::haveibeenpwned::filter::BloomFilter;
+    /// use pwned_rs::{HashAlgorithm, PasswordHashEntry};
+    ///
+    /// match BloomFilter::new(1000, 0.001, HashAlgorithm::Sha1) {
+    ///     Ok(mut filter) => {
+    ///         let entry = PasswordHashEntry::from_password("password");
+    ///         match filter.insert(&entry) {
+    ///             Ok(_) => println!("Is the password part of the filter? {}", filter.contains(&entry)),
+    ///             Err(error) => println!("Could not add the password, the error was: {}", error)
+    ///         }
+    ///     }
+    ///     Err(error) => println!("Could not create the filter, the error was: {}", error)
+    /// }
+    /// ```
+    pub fn new(
+        expected_entries: u64,
+        false_positive_rate: f64,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<BloomFilter, FilterError> {
+        if false_positive_rate.is_nan() || false_positive_rate <= 0.0 || false_positive_rate >= 1.0
+        {
+            return Err(FilterError::FalsePositiveRateOutOfRange);
+        }
+
+        // the optimal size of the bit array and number of hash functions for the requested rate
+        let expected_entries = expected_entries.max(1) as f64;
+        let bits_per_entry =
+            -false_positive_rate.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2);
+        let number_of_bytes = ((expected_entries * bits_per_entry) / 8.0).ceil() as u64;
+        let number_of_hash_functions = (bits_per_entry * std::f64::consts::LN_2)
+            .round()
+            .clamp(1.0, MAXIMUM_HASH_FUNCTIONS as f64) as u8;
+
+        Ok(BloomFilter {
+            bits: vec![0; number_of_bytes as usize],
+            number_of_bits: number_of_bytes * 8,
+            number_of_hash_functions,
+            entry_count: 0,
+            hash_algorithm,
+        })
+    }
+
+    /// Get the positions of the bits which belong to the supplied entry, if its hash matches the
+    /// hash algorithm of the filter.
+    fn get_bit_positions(&self, entry: &PasswordHashEntry) -> Option<Vec<u64>> {
+        let digest = entry.get_digest();
+        if digest.get_algorithm() != self.hash_algorithm {
+            return None;
+        }
+        let raw_hash = digest.as_bytes();
+
+        let first_hash = u64::from_le_bytes(raw_hash[..8].try_into().ok()?);
+        let second_hash = u64::from_le_bytes(raw_hash[8..16].try_into().ok()?) | 1;
+        Some(
+            (0..u64::from(self.number_of_hash_functions))
+                .map(|index| {
+                    first_hash.wrapping_add(index.wrapping_mul(second_hash)) % self.number_of_bits
+                })
+                .collect(),
+        )
+    }
+
+    /// Add the hash of the supplied entry to the filter.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the hash of the entry does not match the hash
+    /// algorithm of the filter.
+    pub fn insert(&mut self, entry: &PasswordHashEntry) -> Result<(), FilterError> {
+        let bit_positions = match self.get_bit_positions(entry) {
+            Some(bit_positions) => bit_positions,
+            None => return Err(FilterError::HashAlgorithmMismatch),
+        };
+        for bit_position in bit_positions {
+            self.bits[(bit_position / 8) as usize] |= 1 << (bit_position % 8);
+        }
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    /// Check if the hash of the supplied entry is (probably) part of the filter. If `false` is
+    /// returned, the hash was never added to the filter.
+    pub fn contains(&self, entry: &PasswordHashEntry) -> bool {
+        match self.get_bit_positions(entry) {
+            Some(bit_positions) => bit_positions.iter().all(|&bit_position| {
+                self.bits[(bit_position / 8) as usize] & (1 << (bit_position % 8)) != 0
+            }),
+            None => false,
+        }
+    }
+
+    /// Get the number of entries which were added to the filter.
+    pub fn get_entry_count(&self) -> u64 {
+        self.entry_count
+    }
+
+    /// Get the size of the bit array of the filter in bytes.
+    pub fn get_size_in_bytes(&self) -> u64 {
+        self.bits.len() as u64
+    }
+
+    pub fn get_number_of_hash_functions(&self) -> u8 {
+        self.number_of_hash_functions
+    }
+
+    pub fn get_hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// Get the expected false positive rate for the number of entries which were added to the
+    /// filter.
+    pub fn get_false_positive_rate(&self) -> f64 {
+        let number_of_hash_functions = f64::from(self.number_of_hash_functions);
+        let empty_bit_probability = (-number_of_hash_functions * self.entry_count as f64
+            / self.number_of_bits as f64)
+            .exp();
+        (1.0 - empty_bit_probability).powf(number_of_hash_functions)
+    }
+
+    /// Write the filter into the supplied file. An existing file will be replaced.
+    pub fn write_to_file(&self, path_to_file: &Path) -> Result<(), Error> {
+        let file_handle = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path_to_file)?;
+        let mut output_file = BufWriter::new(file_handle);
+
+        let mut header = [0u8; FILTER_HEADER_SIZE];
+        header[..4].copy_from_slice(FILTER_MAGIC);
+        header[4] = FILTER_VERSION;
+        header[5] = (self.hash_algorithm.get_hash_length() / 2) as u8;
+        header[6] = self.number_of_hash_functions;
+        header[8..16].copy_from_slice(&self.number_of_bits.to_le_bytes());
+        header[16..].copy_from_slice(&self.entry_count.to_le_bytes());
+        output_file.write_all(&header)?;
+        output_file.write_all(&self.bits)?;
+        output_file.flush()
+    }
+
+    /// Read the filter stored in the supplied file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the following situations, but is not
+    /// limited to just these cases:
+    ///
+    ///  * The file does not exist or cannot be read.
+    ///  * The file does not start with the expected header.
+    ///  * The file was written with an unsupported version of the format.
+    ///  * The header announces no (or too many) hash functions.
+    ///  * The file contains less bits than announced in the header.
+    pub fn from_file(path_to_file: &Path) -> Result<BloomFilter, CreateInstanceError> {
+        let file_handle = match OpenOptions::new().read(true).open(path_to_file) {
+            Ok(file_handle) => file_handle,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        let file_size = match file_handle.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        let mut file_reader = BufReader::new(file_handle);
+
+        // ensure that the file has a valid header
+        let mut header = [0u8; FILTER_HEADER_SIZE];
+        if let Err(error) = file_reader.read_exact(&mut header) {
+            return Err(match error.kind() {
+                ErrorKind::UnexpectedEof => {
+                    CreateInstanceError::Format(FormatErrorKind::NotAPasswordFilter)
+                }
+                _ => CreateInstanceError::Io(error),
+            });
+        }
+        if &header[..4] != FILTER_MAGIC {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::NotAPasswordFilter,
+            ));
+        }
+        if header[4] != FILTER_VERSION {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::UnsupportedFilterVersion,
+            ));
+        }
+        let hash_algorithm = match HashAlgorithm::from_hash_length(header[5] as usize * 2) {
+            Some(algorithm) => algorithm,
+            None => {
+                return Err(CreateInstanceError::Format(
+                    FormatErrorKind::NotAPasswordFilter,
+                ))
+            }
+        };
+        let number_of_hash_functions = header[6];
+        if number_of_hash_functions == 0
+            || u64::from(number_of_hash_functions) > MAXIMUM_HASH_FUNCTIONS
+        {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::InvalidFilterHashFunctions,
+            ));
+        }
+        let number_of_bits = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let entry_count = u64::from_le_bytes(header[16..24].try_into().unwrap());
+
+        // ensure that the announced bits are really stored in the file before allocating the
+        // memory for them
+        let number_of_bytes = number_of_bits / 8;
+        if number_of_bits == 0
+            || number_of_bits % 8 != 0
+            || file_size != FILTER_HEADER_SIZE as u64 + number_of_bytes
+        {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::TruncatedPasswordFilter,
+            ));
+        }
+        let mut bits = vec![0; number_of_bytes as usize];
+        match file_reader.read_exact(&mut bits) {
+            Ok(_) => debug!(
+                "Read {} bytes of bits from the password filter file",
+                number_of_bytes
+            ),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        }
+
+        Ok(BloomFilter {
+            bits,
+            number_of_bits,
+            number_of_hash_functions,
+            entry_count,
+            hash_algorithm,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+
+    #[test]
+    fn there_are_no_false_negatives() {
+        let mut filter = BloomFilter::new(10000, 0.01, HashAlgorithm::Sha1).unwrap();
+        for index in 0..10000 {
+            let entry = PasswordHashEntry::from_password(&format!("password{}", index));
+            filter.insert(&entry).unwrap();
+        }
+        assert_eq!(10000, filter.get_entry_count());
+        for index in 0..10000 {
+            let entry = PasswordHashEntry::from_password(&format!("password{}", index));
+            assert_eq!(true, filter.contains(&entry));
+        }
+
+        // the rate of the false positives should be close to the requested one
+        let false_positives = (0..10000)
+            .filter(|index| {
+                filter.contains(&PasswordHashEntry::from_password(&format!(
+                    "not-a-password{}",
+                    index
+                )))
+            })
+            .count();
+        assert_eq!(true, false_positives < 200);
+        assert_eq!(
+            true,
+            (filter.get_false_positive_rate() - 0.01).abs() < 0.002
+        );
+    }
+
+    #[test]
+    fn written_filter_can_be_read_again() {
+        let file_path = TemporaryPath::new("filter.bloom");
+        let mut filter = BloomFilter::new(100, 0.001, HashAlgorithm::Ntlm).unwrap();
+        let entry =
+            PasswordHashEntry::from_password_with_algorithm("password", HashAlgorithm::Ntlm);
+        filter.insert(&entry).unwrap();
+        assert_eq!(
+            Err(FilterError::HashAlgorithmMismatch),
+            filter.insert(&PasswordHashEntry::from_password("password"))
+        );
+        filter.write_to_file(&file_path).unwrap();
+
+        let read_filter = BloomFilter::from_file(&file_path).unwrap();
+        assert_eq!(HashAlgorithm::Ntlm, read_filter.get_hash_algorithm());
+        assert_eq!(1, read_filter.get_entry_count());
+        assert_eq!(
+            filter.get_number_of_hash_functions(),
+            read_filter.get_number_of_hash_functions()
+        );
+        assert_eq!(true, read_filter.contains(&entry));
+        assert_eq!(
+            false,
+            read_filter.contains(&PasswordHashEntry::from_password("password"))
+        );
+
+        // a truncated filter must not be used, it would report false negatives
+        let content = std::fs::read(&file_path).unwrap();
+        std::fs::write(&file_path, &content[..content.len() - 1]).unwrap();
+        assert_eq!(true, BloomFilter::from_file(&file_path).is_err());
+    }
+
+    #[test]
+    fn filters_without_hash_functions_are_rejected() {
+        let file_path = TemporaryPath::new("no-hash-functions.bloom");
+        let filter = BloomFilter::new(100, 0.001, HashAlgorithm::Sha1).unwrap();
+        filter.write_to_file(&file_path).unwrap();
+
+        // a filter without hash functions would contain every hash
+        let mut content = std::fs::read(&file_path).unwrap();
+        content[6] = 0;
+        std::fs::write(&file_path, &content).unwrap();
+        match BloomFilter::from_file(&file_path) {
+            Err(CreateInstanceError::Format(FormatErrorKind::InvalidFilterHashFunctions)) => {}
+            _ => panic!("The filter without hash functions was not rejected"),
+        }
+
+        // a header alone is not a filter
+        std::fs::write(&file_path, &content[..FILTER_HEADER_SIZE - 1]).unwrap();
+        match BloomFilter::from_file(&file_path) {
+            Err(CreateInstanceError::Format(FormatErrorKind::NotAPasswordFilter)) => {}
+            _ => panic!("The incomplete header was not rejected"),
+        }
+    }
+
+    #[test]
+    fn invalid_false_positive_rates_are_rejected() {
+        for rate in &[0.0, 1.0, -0.5, f64::NAN] {
+            assert_eq!(
+                FilterError::FalsePositiveRateOutOfRange,
+                BloomFilter::new(100, *rate, HashAlgorithm::Sha1)
+                    .err()
+                    .unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,613 @@
+use crate::haveibeenpwned::{CreateInstanceError, FormatErrorKind};
+use crate::{HashAlgorithm, PasswordHashEntry};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::OpenOptions;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The name of the file in which the manifest of an optimized database is stored.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// The shortest prefix which can be used for splitting the optimized database.
+pub const MINIMUM_PREFIX_LENGTH: usize = 1;
+
+/// The longest prefix which can be used for splitting the optimized database.
+pub const MAXIMUM_PREFIX_LENGTH: usize = 6;
+
+/// The prefix length which was used by the versions of the tool which did not write a manifest.
+pub const DEFAULT_PREFIX_LENGTH: usize = 3;
+
+/// The number of prefix characters which are used for the directory of a nested layout.
+const NESTED_DIRECTORY_PREFIX_LENGTH: usize = 2;
+
+/// The possible errors if the layout of an optimized database is not valid.
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    /// The prefix length is not in the supported range.
+    PrefixLengthOutOfRange,
+    /// The prefix is too short for splitting it into a directory and a file name.
+    PrefixTooShortForNesting,
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            LayoutError::PrefixLengthOutOfRange => write!(
+                f,
+                "The prefix length has to be between {} and {}",
+                MINIMUM_PREFIX_LENGTH, MAXIMUM_PREFIX_LENGTH
+            ),
+            LayoutError::PrefixTooShortForNesting => write!(
+                f,
+                "The prefix has to be longer than {} characters for a nested layout",
+                NESTED_DIRECTORY_PREFIX_LENGTH
+            ),
+        }
+    }
+}
+
+/// Compute the SHA-256 checksum (lower case hexadecimal) of the content of the supplied file.
+pub fn compute_file_checksum(path_to_file: &Path) -> Result<String, Error> {
+    let mut file_handle = OpenOptions::new().read(true).open(path_to_file)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read_bytes = file_handle.read(&mut buffer)?;
+        if read_bytes == 0 {
+            break;
+        }
+        hasher.input(&buffer[..read_bytes]);
+    }
+    Ok(hasher.result_str())
+}
+
+/// The information about the original password file an optimized database was created from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceFile {
+    file_name: String,
+    file_size: u64,
+    sha256: String,
+}
+
+impl SourceFile {
+    /// Collect the name, the size and the checksum of the supplied password file.
+    pub fn from_file(path_to_file: &Path) -> Result<SourceFile, Error> {
+        let file_name = match path_to_file.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => path_to_file.display().to_string(),
+        };
+        Ok(SourceFile {
+            file_name,
+            file_size: std::fs::metadata(path_to_file)?.len(),
+            sha256: compute_file_checksum(path_to_file)?,
+        })
+    }
+
+    pub fn get_file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn get_file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn get_sha256(&self) -> &str {
+        &self.sha256
+    }
+}
+
+/// The information about a single file (one prefix) of an optimized database.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFileEntry {
+    prefix: String,
+    record_count: u64,
+    file_size: u64,
+    sha256: String,
+}
+
+impl ManifestFileEntry {
+    /// Collect the size and the checksum of the file which was written for the supplied prefix.
+    pub fn from_file(
+        prefix: &str,
+        path_to_file: &Path,
+        record_count: u64,
+    ) -> Result<ManifestFileEntry, Error> {
+        Ok(ManifestFileEntry {
+            prefix: prefix.to_string(),
+            record_count,
+            file_size: std::fs::metadata(path_to_file)?.len(),
+            sha256: compute_file_checksum(path_to_file)?,
+        })
+    }
+
+    pub fn get_prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn get_record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    pub fn get_file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn get_sha256(&self) -> &str {
+        &self.sha256
+    }
+}
+
+/// The information about a delta (the new and changed entries of a newer release) which was
+/// applied to an optimized database by the `update` subcommand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedDelta {
+    version: String,
+    source: SourceFile,
+    record_count: u64,
+}
+
+impl AppliedDelta {
+    pub fn new(version: &str, source: SourceFile, record_count: u64) -> AppliedDelta {
+        AppliedDelta {
+            version: version.to_string(),
+            source,
+            record_count,
+        }
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    /// Get the information about the file the delta was read from.
+    pub fn get_source(&self) -> &SourceFile {
+        &self.source
+    }
+
+    /// Get the number of entries of the delta.
+    pub fn get_record_count(&self) -> u64 {
+        self.record_count
+    }
+}
+
+/// The result of verifying a single file of an optimized database against the manifest.
+#[derive(Debug, PartialEq)]
+pub enum FileVerification {
+    /// The file matches the size and the checksum recorded in the manifest.
+    Valid,
+    /// The file does not exist.
+    Missing,
+    /// The file is smaller than recorded in the manifest.
+    Truncated,
+    /// The file has a different size or content than recorded in the manifest.
+    Corrupted,
+}
+
+/// The manifest of an optimized database. It records how the database was split into files, so
+/// the lookup always uses the same layout which was used while optimizing the database. It also
+/// records where the database came from and the checksums of all files, so the database can be
+/// verified later on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseManifest {
+    #[serde(default)]
+    tool_version: Option<String>,
+    prefix_length: usize,
+    nested: bool,
+    binary: bool,
+    hash_algorithm: HashAlgorithm,
+    #[serde(default)]
+    source: Option<SourceFile>,
+    #[serde(default)]
+    files: Vec<ManifestFileEntry>,
+    #[serde(default)]
+    applied_deltas: Vec<AppliedDelta>,
+}
+
+impl DatabaseManifest {
+    /// Create the manifest for a database which is split by prefixes of the supplied length.
+    ///
+    /// If `nested` is set, the files are stored in a sub-directory named after the first two
+    /// characters of the prefix (e.g. `AB/CDE.txt` instead of `ABCDE.txt`).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the prefix length is not between 1 and 6 or if a
+    /// nested layout was requested for a prefix which is not longer than two characters.
+    pub fn new(
+        prefix_length: usize,
+        nested: bool,
+        binary: bool,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<DatabaseManifest, LayoutError> {
+        let manifest = DatabaseManifest {
+            tool_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            prefix_length,
+            nested,
+            binary,
+            hash_algorithm,
+            source: None,
+            files: Vec::new(),
+            applied_deltas: Vec::new(),
+        };
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), LayoutError> {
+        if self.prefix_length < MINIMUM_PREFIX_LENGTH || self.prefix_length > MAXIMUM_PREFIX_LENGTH
+        {
+            return Err(LayoutError::PrefixLengthOutOfRange);
+        }
+        if self.nested && self.prefix_length <= NESTED_DIRECTORY_PREFIX_LENGTH {
+            return Err(LayoutError::PrefixTooShortForNesting);
+        }
+        Ok(())
+    }
+
+    /// Read the manifest stored in the supplied folder. If the folder does not contain a manifest,
+    /// `None` is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the manifest exists but could not be read or does
+    /// not describe a valid layout.
+    pub fn from_folder(folder: &Path) -> Result<Option<DatabaseManifest>, CreateInstanceError> {
+        let manifest_file = match OpenOptions::new()
+            .read(true)
+            .open(folder.join(MANIFEST_FILE_NAME))
+        {
+            Ok(file_handle) => file_handle,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        let manifest: DatabaseManifest =
+            match serde_json::from_reader(BufReader::new(manifest_file)) {
+                Ok(manifest) => manifest,
+                Err(_) => {
+                    return Err(CreateInstanceError::Format(
+                        FormatErrorKind::InvalidManifest,
+                    ))
+                }
+            };
+        if manifest.validate().is_err() {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::InvalidManifest,
+            ));
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Write the manifest into the supplied folder. The manifest is written under a temporary
+    /// name first and renamed afterwards, so an existing manifest is replaced atomically.
+    pub fn write_to_folder(&self, folder: &Path) -> Result<(), Error> {
+        let temporary_path = folder.join(format!("{}.tmp", MANIFEST_FILE_NAME));
+        let manifest_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temporary_path)?;
+        let mut writer = BufWriter::new(manifest_file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&temporary_path, folder.join(MANIFEST_FILE_NAME))
+    }
+
+    pub fn get_prefix_length(&self) -> usize {
+        self.prefix_length
+    }
+
+    pub fn is_nested(&self) -> bool {
+        self.nested
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    pub fn get_hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// Get the version of the tool which wrote the manifest, if it was recorded.
+    pub fn get_tool_version(&self) -> Option<&str> {
+        self.tool_version.as_deref()
+    }
+
+    /// Get the information about the original password file, if it was recorded.
+    pub fn get_source(&self) -> Option<&SourceFile> {
+        self.source.as_ref()
+    }
+
+    pub fn set_source(&mut self, source: SourceFile) {
+        self.source = Some(source);
+    }
+
+    /// Get the information about all files of the database.
+    pub fn get_files(&self) -> &[ManifestFileEntry] {
+        &self.files
+    }
+
+    pub fn add_file(&mut self, file_entry: ManifestFileEntry) {
+        self.files.push(file_entry);
+    }
+
+    /// Replace the information about the file with the same prefix as the supplied one. If the
+    /// database did not contain a file with this prefix yet, it is added in the order of the
+    /// prefixes.
+    pub fn set_file(&mut self, file_entry: ManifestFileEntry) {
+        match self
+            .files
+            .binary_search_by(|existing_entry| existing_entry.prefix.cmp(&file_entry.prefix))
+        {
+            Ok(index) => self.files[index] = file_entry,
+            Err(index) => self.files.insert(index, file_entry),
+        }
+    }
+
+    /// Get the information about all deltas which were applied to the database (in the order
+    /// they were applied).
+    pub fn get_applied_deltas(&self) -> &[AppliedDelta] {
+        &self.applied_deltas
+    }
+
+    pub fn add_applied_delta(&mut self, applied_delta: AppliedDelta) {
+        self.applied_deltas.push(applied_delta);
+    }
+
+    /// Get the total number of records stored in all files of the database.
+    pub fn get_record_count(&self) -> u64 {
+        self.files.iter().map(|file| file.record_count).sum()
+    }
+
+    /// Check if the file for the supplied entry (stored in the supplied folder) still matches the
+    /// size and the checksum which were recorded while writing the database.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file exists but could not be read.
+    pub fn verify_file(
+        &self,
+        folder: &Path,
+        file_entry: &ManifestFileEntry,
+    ) -> Result<FileVerification, Error> {
+        let path_to_file = self.get_file_path(folder, &file_entry.prefix);
+        let file_size = match std::fs::metadata(&path_to_file) {
+            Ok(meta_data) => meta_data.len(),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                return Ok(FileVerification::Missing)
+            }
+            Err(error) => return Err(error),
+        };
+
+        if file_size < file_entry.file_size {
+            return Ok(FileVerification::Truncated);
+        }
+        if file_size > file_entry.file_size
+            || compute_file_checksum(&path_to_file)? != file_entry.sha256
+        {
+            return Ok(FileVerification::Corrupted);
+        }
+        Ok(FileVerification::Valid)
+    }
+
+    /// Get all files in the supplied folder (and its sub-directories) which are neither the
+    /// manifest itself nor listed in it, e.g. files which were left behind by an interrupted
+    /// optimization. The paths are returned in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the folder or one of its sub-directories could not
+    /// be read.
+    pub fn get_unlisted_files(&self, folder: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut listed_files: HashSet<PathBuf> = self
+            .files
+            .iter()
+            .map(|file| self.get_file_path(folder, &file.prefix))
+            .collect();
+        listed_files.insert(folder.join(MANIFEST_FILE_NAME));
+
+        let mut unlisted_files = Vec::new();
+        let mut pending_folders = vec![folder.to_path_buf()];
+        while let Some(current_folder) = pending_folders.pop() {
+            for directory_entry in std::fs::read_dir(&current_folder)? {
+                let path = directory_entry?.path();
+                if path.is_dir() {
+                    pending_folders.push(path);
+                } else if !listed_files.contains(&path) {
+                    unlisted_files.push(path);
+                }
+            }
+        }
+        unlisted_files.sort();
+        Ok(unlisted_files)
+    }
+
+    /// Get the (upper case) prefix which determines the file an entry is stored in.
+    pub fn get_prefix(&self, entry: &PasswordHashEntry) -> Option<String> {
+        entry.get_dynamic_prefix(self.prefix_length)
+    }
+
+    /// Get the path of the file in which the entries with the supplied prefix are stored.
+    pub fn get_file_path(&self, folder: &Path, prefix: &str) -> PathBuf {
+        let extension = if self.binary { "bin" } else { "txt" };
+        if self.nested {
+            let (directory, file_name) = prefix.split_at(NESTED_DIRECTORY_PREFIX_LENGTH);
+            return folder
+                .join(directory)
+                .join(format!("{}.{}", file_name, extension));
+        }
+        folder.join(format!("{}.{}", prefix, extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+
+    #[test]
+    fn creating_a_manifest_with_an_invalid_layout_fails() {
+        assert_eq!(
+            LayoutError::PrefixLengthOutOfRange,
+            DatabaseManifest::new(0, false, false, HashAlgorithm::Sha1)
+                .err()
+                .unwrap()
+        );
+        assert_eq!(
+            LayoutError::PrefixLengthOutOfRange,
+            DatabaseManifest::new(7, false, false, HashAlgorithm::Sha1)
+                .err()
+                .unwrap()
+        );
+        assert_eq!(
+            LayoutError::PrefixTooShortForNesting,
+            DatabaseManifest::new(2, true, false, HashAlgorithm::Sha1)
+                .err()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn the_file_paths_match_the_layout() {
+        let folder = Path::new("/database");
+        let entry = PasswordHashEntry::from_password("password");
+
+        let flat_manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        let prefix = flat_manifest.get_prefix(&entry).unwrap();
+        assert_eq!("5BA", prefix);
+        assert_eq!(
+            Path::new("/database/5BA.txt"),
+            flat_manifest.get_file_path(folder, &prefix)
+        );
+
+        let nested_manifest = DatabaseManifest::new(5, true, true, HashAlgorithm::Sha1).unwrap();
+        let prefix = nested_manifest.get_prefix(&entry).unwrap();
+        assert_eq!("5BAA6", prefix);
+        assert_eq!(
+            Path::new("/database/5B/AA6.bin"),
+            nested_manifest.get_file_path(folder, &prefix)
+        );
+    }
+
+    #[test]
+    fn a_written_manifest_can_be_read_again() {
+        let folder = TemporaryPath::new_folder("manifest");
+        assert_eq!(None, DatabaseManifest::from_folder(&folder).unwrap());
+
+        let manifest = DatabaseManifest::new(4, true, false, HashAlgorithm::Ntlm).unwrap();
+        manifest.write_to_folder(&folder).unwrap();
+        assert_eq!(
+            Some(manifest),
+            DatabaseManifest::from_folder(&folder).unwrap()
+        );
+    }
+
+    #[test]
+    fn manifests_without_the_file_information_can_be_read() {
+        let folder = TemporaryPath::new_folder("old-manifest");
+        std::fs::write(
+            folder.join(MANIFEST_FILE_NAME),
+            r#"{"prefix_length": 3, "nested": false, "binary": false, "hash_algorithm": "sha1"}"#,
+        )
+        .unwrap();
+
+        let manifest = DatabaseManifest::from_folder(&folder).unwrap().unwrap();
+        assert_eq!(None, manifest.get_tool_version());
+        assert_eq!(None, manifest.get_source());
+        assert_eq!(0, manifest.get_files().len());
+        assert_eq!(0, manifest.get_applied_deltas().len());
+    }
+
+    #[test]
+    fn verifying_the_files_detects_modifications() {
+        let folder = TemporaryPath::new_folder("verify");
+        let mut manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        for prefix in &["5BA", "7C4", "FFF"] {
+            let path_to_file = manifest.get_file_path(&folder, prefix);
+            std::fs::write(
+                &path_to_file,
+                format!("{}00000000000000000000000000000000000:1\n", prefix),
+            )
+            .unwrap();
+            manifest.add_file(ManifestFileEntry::from_file(prefix, &path_to_file, 1).unwrap());
+        }
+        assert_eq!(3, manifest.get_record_count());
+
+        // modify the files after they were recorded in the manifest
+        std::fs::remove_file(manifest.get_file_path(&folder, "5BA")).unwrap();
+        std::fs::write(manifest.get_file_path(&folder, "7C4"), "7C4").unwrap();
+        let files = manifest.get_files().to_vec();
+        assert_eq!(
+            FileVerification::Missing,
+            manifest.verify_file(&folder, &files[0]).unwrap()
+        );
+        assert_eq!(
+            FileVerification::Truncated,
+            manifest.verify_file(&folder, &files[1]).unwrap()
+        );
+        assert_eq!(
+            FileVerification::Valid,
+            manifest.verify_file(&folder, &files[2]).unwrap()
+        );
+
+        std::fs::write(
+            manifest.get_file_path(&folder, "FFF"),
+            "FFF00000000000000000000000000000000000:2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            FileVerification::Corrupted,
+            manifest.verify_file(&folder, &files[2]).unwrap()
+        );
+    }
+
+    #[test]
+    fn updated_files_and_applied_deltas_are_recorded() {
+        let folder = TemporaryPath::new_folder("update");
+        let mut manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        for (prefix, content) in &[("5BA", "5BA:1\n"), ("FFF", "FFF:1\n"), ("7C4", "7C4:1\n")] {
+            let path_to_file = manifest.get_file_path(&folder, prefix);
+            std::fs::write(&path_to_file, content).unwrap();
+            manifest.set_file(ManifestFileEntry::from_file(prefix, &path_to_file, 1).unwrap());
+        }
+        let path_to_file = manifest.get_file_path(&folder, "5BA");
+        std::fs::write(&path_to_file, "5BA:1\n5BB:2\n").unwrap();
+        manifest.set_file(ManifestFileEntry::from_file("5BA", &path_to_file, 2).unwrap());
+
+        let prefixes: Vec<&str> = manifest
+            .get_files()
+            .iter()
+            .map(|file_entry| file_entry.get_prefix())
+            .collect();
+        assert_eq!(vec!["5BA", "7C4", "FFF"], prefixes);
+        assert_eq!(4, manifest.get_record_count());
+
+        let source = SourceFile::from_file(&path_to_file).unwrap();
+        manifest.add_applied_delta(AppliedDelta::new("2024-01", source, 2));
+        manifest.write_to_folder(&folder).unwrap();
+        let read_manifest = DatabaseManifest::from_folder(&folder).unwrap().unwrap();
+        assert_eq!(1, read_manifest.get_applied_deltas().len());
+        assert_eq!(
+            "2024-01",
+            read_manifest.get_applied_deltas()[0].get_version()
+        );
+        assert_eq!(false, folder.join("manifest.json.tmp").exists());
+    }
+
+    #[test]
+    fn the_checksum_of_a_file_is_computed() {
+        let path_to_file = TemporaryPath::new("checksum");
+        std::fs::write(&path_to_file, "abc").unwrap();
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            compute_file_checksum(&path_to_file).unwrap()
+        );
+    }
+}
@@ -1,10 +1,29 @@
-use crate::PasswordHashEntry;
-use log::{debug, error};
+use crate::haveibeenpwned::compression::{CompressionFormat, SourceReader};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use log::debug;
+use std::cmp::min;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Error, Read};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Error, Read, Seek, SeekFrom};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub mod binary;
+pub mod checkpoint;
+pub mod compression;
+pub mod database;
+pub mod external_sort;
+pub mod filter;
+pub mod manifest;
+pub mod optimized;
+pub mod ordered;
+pub mod policy;
+mod sorted;
+
+pub use self::sorted::SortedHashFile;
 
 /// The possible errors which can occur on instantiation of the [HaveIBeenPwnedParser](struct.HaveIBeenPwnedParser.html) class.
 #[derive(Debug)]
@@ -22,6 +41,26 @@ pub enum FormatErrorKind {
     NotATextFile,
     /// It seems that the format of at least one of the lines in the file is invalid.
     LineFormatNotCorrect,
+    /// It seems that the file is not a binary password database.
+    NotABinaryDatabase,
+    /// It seems that the binary password database was written in an unsupported version.
+    UnsupportedBinaryVersion,
+    /// It seems that the binary password database contains less records than expected.
+    TruncatedBinaryDatabase,
+    /// The manifest of the optimized database could not be parsed or describes an invalid layout.
+    InvalidManifest,
+    /// The checkpoint of an interrupted optimization could not be parsed.
+    InvalidCheckpoint,
+    /// The entries of the password file are not ordered by their hash.
+    NotOrderedByHash,
+    /// It seems that the file is not a password filter.
+    NotAPasswordFilter,
+    /// It seems that the password filter was written in an unsupported version.
+    UnsupportedFilterVersion,
+    /// It seems that the password filter contains less bits than expected.
+    TruncatedPasswordFilter,
+    /// The password filter announces a number of hash functions which can not be used.
+    InvalidFilterHashFunctions,
 }
 
 impl FormatErrorKind {
@@ -31,6 +70,26 @@ impl FormatErrorKind {
             FormatErrorKind::LineFormatNotCorrect => {
                 "format of lines does not match the required format"
             }
+            FormatErrorKind::NotABinaryDatabase => "not a binary password database",
+            FormatErrorKind::UnsupportedBinaryVersion => {
+                "binary password database was written in an unsupported version"
+            }
+            FormatErrorKind::TruncatedBinaryDatabase => {
+                "binary password database seems to be truncated"
+            }
+            FormatErrorKind::InvalidManifest => "manifest of the optimized database is not valid",
+            FormatErrorKind::InvalidCheckpoint => {
+                "checkpoint of the interrupted optimization is not valid"
+            }
+            FormatErrorKind::NotOrderedByHash => "entries of the file are not ordered by hash",
+            FormatErrorKind::NotAPasswordFilter => "not a password filter",
+            FormatErrorKind::UnsupportedFilterVersion => {
+                "password filter was written in an unsupported version"
+            }
+            FormatErrorKind::TruncatedPasswordFilter => "password filter seems to be truncated",
+            FormatErrorKind::InvalidFilterHashFunctions => {
+                "password filter uses an invalid number of hash functions"
+            }
         }
     }
 }
@@ -46,10 +105,127 @@ impl Display for CreateInstanceError {
     }
 }
 
+/// The reason why a line of a password file could not be parsed.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The line could not be read from the file.
+    Io(Error),
+    /// The line is not valid UTF-8 text.
+    NotValidText,
+    /// The line does not contain an occurrence count behind the hash.
+    NoOccurrenceCountFound,
+    /// The occurrence count of the line is not a number.
+    InvalidOccurrenceCount,
+    /// The hash of the line is neither a SHA-1 nor a NTLM hash.
+    InvalidHash,
+    /// The hash of the line is not greater than the hash of the previous line, although the file
+    /// has to be ordered by hash.
+    NotOrderedByHash {
+        hash: HashDigest,
+        previous_hash: HashDigest,
+    },
+}
+
+/// The error if a line of a password file could not be parsed.
+#[derive(Debug)]
+pub struct ParseError {
+    line_number: u64,
+    byte_offset: u64,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(line_number: u64, byte_offset: u64, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line_number,
+            byte_offset,
+            kind,
+        }
+    }
+
+    /// Get the number (starting with 1) of the line which could not be parsed.
+    pub fn get_line_number(&self) -> u64 {
+        self.line_number
+    }
+
+    /// Get the offset of the start of the line in the (decompressed) password file.
+    pub fn get_byte_offset(&self) -> u64 {
+        self.byte_offset
+    }
+
+    pub fn get_kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Add the supplied number of lines to the line number, e.g. if the line was read by an
+    /// iterator which did not start at the beginning of the file.
+    pub(crate) fn add_preceding_lines(&mut self, preceding_lines: u64) {
+        self.line_number += preceding_lines;
+    }
+
+    /// Check if the file can not be read any further. If this is not the case, just the line
+    /// itself is malformed and the next lines can still be read.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self.kind, ParseErrorKind::Io(_))
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "Line {} (byte offset {}): ",
+            self.line_number, self.byte_offset
+        )?;
+        match self.kind {
+            ParseErrorKind::Io(ref err) => write!(f, "could not be read ({})", err),
+            ParseErrorKind::NotValidText => write!(f, "is not valid UTF-8 text"),
+            ParseErrorKind::NoOccurrenceCountFound => {
+                write!(f, "does not contain an occurrence count")
+            }
+            ParseErrorKind::InvalidOccurrenceCount => {
+                write!(f, "the occurrence count is not a number")
+            }
+            ParseErrorKind::InvalidHash => write!(f, "is neither a SHA-1 nor a NTLM hash"),
+            ParseErrorKind::NotOrderedByHash {
+                hash,
+                previous_hash,
+            } => write!(
+                f,
+                "is not ordered by hash ({} follows {})",
+                hash, previous_hash
+            ),
+        }
+    }
+}
+
+/// The order of the entries in a password file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryOrder {
+    /// The entries are ordered by their hash (the "ordered by hash" download).
+    ByHash,
+    /// The entries are not ordered by their hash, e.g. the "ordered by prevalence" download.
+    Unordered,
+}
+
 /// This class can be used to parse the password files provided by https://haveibeenpwned.com.
+///
+/// The iterator returns an error for every line which could not be parsed, so the caller can
+/// decide if the malformed lines should be skipped or if the processing should be stopped.
+///
+/// The password files can also be read directly from gzip, zstd, xz or 7-Zip archives, they are
+/// decompressed while they are read.
 pub struct DatabaseIterator {
     file_size: u64,
-    password_file: Option<BufReader<File>>,
+    password_file: Option<BufReader<Box<dyn Read + Send>>>,
+    hash_algorithm: Option<HashAlgorithm>,
+    entry_order: EntryOrder,
+    compression_format: CompressionFormat,
+    read_compressed_bytes: Arc<AtomicU64>,
+    processed_bytes: u64,
+    read_lines: u64,
+    crlf_lines: u64,
+    lf_lines: u64,
 }
 
 impl DatabaseIterator {
@@ -81,14 +257,21 @@ impl DatabaseIterator {
             Err(error) => return Err(CreateInstanceError::Io(error)),
         };
 
-        // try to figure our how many entries are stored in the file
-        let file_reader = match OpenOptions::new()
-            .append(false)
-            .create(false)
-            .read(true)
-            .open(&path_to_file)
-        {
-            Ok(file_handle) => BufReader::with_capacity(1024 * 1024 * 128, file_handle),
+        // open the file and decompress it on the fly if it is an archive
+        let source_reader = match SourceReader::open(Path::new(path_to_file)) {
+            Ok(reader) => reader,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        debug!(
+            "The password file is {} ({})",
+            source_reader.format, path_to_file
+        );
+        let mut file_reader = BufReader::with_capacity(1024 * 1024 * 128, source_reader.reader);
+
+        // determine the type of the data set by looking at the first line of the file and the
+        // order of the entries by looking at the first lines
+        let (hash_algorithm, entry_order) = match file_reader.fill_buf() {
+            Ok(buffer) => (detect_hash_algorithm(buffer), detect_entry_order(buffer)),
             Err(error) => return Err(CreateInstanceError::Io(error)),
         };
 
@@ -96,10 +279,148 @@ impl DatabaseIterator {
         Ok(DatabaseIterator {
             password_file: Some(file_reader),
             file_size: file_meta_data.len(),
+            hash_algorithm,
+            entry_order,
+            compression_format: source_reader.format,
+            read_compressed_bytes: source_reader.read_bytes,
+            processed_bytes: 0,
+            read_lines: 0,
+            crlf_lines: 0,
+            lf_lines: 0,
         })
     }
 
-    /// Get the size of the original password file.
+    /// Get a new instance which just parses the supplied byte range of an uncompressed password
+    /// file. The range has to start at the beginning of a line and end behind a line break (or at
+    /// the end of the file).
+    ///
+    /// The byte offsets of the [errors](struct.ParseError.html) are relative to the start of the
+    /// file, but the line numbers are counted from the start of the range.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file could not be opened or if the start of the
+    /// range could not be found.
+    pub fn from_file_range(
+        path_to_file: &str,
+        start_offset: u64,
+        end_offset: u64,
+    ) -> Result<DatabaseIterator, CreateInstanceError> {
+        let mut file_handle = match OpenOptions::new().read(true).open(path_to_file) {
+            Ok(file_handle) => file_handle,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        let file_size = match file_handle.metadata() {
+            Ok(meta_data) => meta_data.len(),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+        if let Err(error) = file_handle.seek(SeekFrom::Start(start_offset)) {
+            return Err(CreateInstanceError::Io(error));
+        }
+
+        // several ranges are usually read at the same time, so we use a smaller buffer for each
+        let range_reader: Box<dyn Read + Send> =
+            Box::new(file_handle.take(end_offset.saturating_sub(start_offset)));
+        let mut file_reader = BufReader::with_capacity(1024 * 1024 * 8, range_reader);
+        let (hash_algorithm, entry_order) = match file_reader.fill_buf() {
+            Ok(buffer) => (detect_hash_algorithm(buffer), detect_entry_order(buffer)),
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        Ok(DatabaseIterator {
+            password_file: Some(file_reader),
+            file_size,
+            hash_algorithm,
+            entry_order,
+            compression_format: CompressionFormat::Plain,
+            read_compressed_bytes: Arc::new(AtomicU64::new(0)),
+            processed_bytes: start_offset,
+            read_lines: 0,
+            crlf_lines: 0,
+            lf_lines: 0,
+        })
+    }
+
+    /// Get the hash algorithm which was used for the hashes in the password file. The algorithm is
+    /// detected by the length of the hash in the first line of the file.
+    ///
+    /// # Example
+    /// ```
+    /// use pwned_rs::haveibeenpwned::DatabaseIterator;
+    ///
+    /// match DatabaseIterator::from_file("/path/to/the/hash/file.txt") {
+    ///     Ok(instance) => match instance.get_hash_algorithm() {
+    ///         Some(algorithm) => println!("The file contains {} hashes", algorithm),
+    ///         None => println!("Could not detect the type of the hashes in the file"),
+    ///     },
+    ///     Err(error) => println!("Could not get an instance, the error was: {}", error)
+    /// }
+    /// ```
+    pub fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.hash_algorithm
+    }
+
+    /// Get the order of the entries in the password file. The order is detected by the lines at
+    /// the beginning of the file, so a file which is just partially ordered might not be detected.
+    ///
+    /// # Example
+    /// ```
+    /// use pwned_rs::haveibeenpwned::{DatabaseIterator, EntryOrder};
+    ///
+    /// match DatabaseIterator::from_file("/path/to/the/hash/file.txt") {
+    ///     Ok(instance) if instance.get_entry_order() == EntryOrder::Unordered => {
+    ///         println!("The file has to be sorted before it can be searched")
+    ///     }
+    ///     Ok(_) => println!("The file is ordered by hash"),
+    ///     Err(error) => println!("Could not get an instance, the error was: {}", error)
+    /// }
+    /// ```
+    pub fn get_entry_order(&self) -> EntryOrder {
+        self.entry_order
+    }
+
+    /// Get the format in which the original password file is compressed.
+    pub fn get_compression_format(&self) -> CompressionFormat {
+        self.compression_format
+    }
+
+    /// Get the number of bytes of the original password file which were already processed. For
+    /// compressed files, this is the number of compressed bytes which were consumed, so it can be
+    /// compared to the [file size](#method.get_file_size) for showing the progress.
+    pub fn get_processed_bytes(&self) -> u64 {
+        if self.compression_format.is_compressed() {
+            return min(
+                self.read_compressed_bytes.load(Ordering::Relaxed),
+                self.file_size,
+            );
+        }
+        self.processed_bytes
+    }
+
+    /// Get the offset (in the uncompressed content of the password file) behind the last line
+    /// which was read. In contrast to the [processed bytes](#method.get_processed_bytes), this
+    /// is independent of the compression of the file.
+    pub fn get_byte_offset(&self) -> u64 {
+        self.processed_bytes
+    }
+
+    /// Get the number of lines (including empty and malformed ones) which were read so far. After
+    /// an entry was returned, this is the number of its line.
+    pub fn get_line_count(&self) -> u64 {
+        self.read_lines
+    }
+
+    /// Get the number of lines read so far which ended with a Windows line break (CR LF).
+    pub fn get_crlf_line_count(&self) -> u64 {
+        self.crlf_lines
+    }
+
+    /// Get the number of lines read so far which ended with a Unix line break (LF only).
+    pub fn get_lf_line_count(&self) -> u64 {
+        self.lf_lines
+    }
+
+    /// Get the size of the original password file (the compressed size for archives).
     ///
     /// # Example
     /// ```
@@ -124,61 +445,129 @@ impl DatabaseIterator {
     }
 }
 
-impl Iterator for DatabaseIterator {
-    type Item = PasswordHashEntry;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl DatabaseIterator {
+    /// Read the next line (including the line break) from the password file.
+    fn read_next_line(&mut self) -> Option<Result<Vec<u8>, Error>> {
         // be sure that we are running in file mode, otherwise we can return immediately
         let password_file_reader = match &mut self.password_file {
             Some(reader) => reader,
             None => return None,
         };
 
-        // get the next line from the file
-        let mut entry_line = String::new();
-        let line_length = match password_file_reader.read_line(&mut entry_line) {
-            Ok(length) => length,
-            Err(_) => return None,
-        };
+        let mut raw_line = Vec::new();
+        match password_file_reader.read_until(b'\n', &mut raw_line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(raw_line)),
+            Err(error) => {
+                // we can not tell where the next line starts, so the iteration ends here
+                self.password_file = None;
+                Some(Err(error))
+            }
+        }
+    }
+}
 
-        //
-        let mut entry_splitted = entry_line.trim().split(':');
+impl Iterator for DatabaseIterator {
+    type Item = Result<PasswordHashEntry, ParseError>;
 
-        //
-        let password_hash = match entry_splitted.next() {
-            Some(key_text) => key_text.to_string(),
-            None => {
-                error!("Could not get the password hash part of the entry!");
-                return None;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // get the next line from the file
+            let line_number = self.read_lines + 1;
+            let byte_offset = self.processed_bytes;
+            let raw_line = match self.read_next_line()? {
+                Ok(raw_line) => raw_line,
+                Err(error) => {
+                    return Some(Err(ParseError::new(
+                        line_number,
+                        byte_offset,
+                        ParseErrorKind::Io(error),
+                    )))
+                }
+            };
+            self.read_lines = line_number;
+            self.processed_bytes += raw_line.len() as u64;
+            if raw_line.ends_with(b"\r\n") {
+                self.crlf_lines += 1;
+            } else if raw_line.ends_with(b"\n") {
+                self.lf_lines += 1;
             }
-        };
+            let parse_error =
+                |kind: ParseErrorKind| Some(Err(ParseError::new(line_number, byte_offset, kind)));
 
-        // try to get the number of occurrences of the password hash
-        let occurrences = match entry_splitted.next() {
-            Some(value_text) => match value_text.parse::<u64>() {
-                Ok(value_as_int) => value_as_int,
-                Err(_) => {
-                    error!("Could not parse the number of occurrences of the password. Maybe \"{}\" not a number.", value_text);
-                    return None;
-                }
-            },
-            None => {
-                error!("Could not get the occurrence count.");
-                return None;
+            // empty lines (e.g. at the end of the file) do not contain an entry
+            let entry_line = match std::str::from_utf8(&raw_line) {
+                Ok(entry_line) => entry_line.trim(),
+                Err(_) => return parse_error(ParseErrorKind::NotValidText),
+            };
+            if entry_line.is_empty() {
+                continue;
             }
-        };
 
-        // return the parsed password entry
-        Some(PasswordHashEntry {
-            hash: password_hash,
-            occurrences,
-            entry_size: line_length as u64,
-        })
+            // split the line into the hash and the number of occurrences of the password hash
+            let mut entry_splitted = entry_line.split(':');
+            let password_hash = entry_splitted.next().unwrap_or_default();
+            let occurrences = match entry_splitted.next() {
+                Some(value_text) => match value_text.parse::<u64>() {
+                    Ok(value_as_int) => value_as_int,
+                    Err(_) => return parse_error(ParseErrorKind::InvalidOccurrenceCount),
+                },
+                None => return parse_error(ParseErrorKind::NoOccurrenceCountFound),
+            };
+
+            // the type of the hash is determined by its length
+            let mut password_hash_entry = match PasswordHashEntry::from_hash(password_hash) {
+                Ok(entry) => entry,
+                Err(_) => return parse_error(ParseErrorKind::InvalidHash),
+            };
+            password_hash_entry.occurrences = occurrences;
+            password_hash_entry.entry_size = raw_line.len() as u64;
+
+            // return the parsed password entry
+            return Some(Ok(password_hash_entry));
+        }
+    }
+}
+
+/// Detect the hash algorithm by the length of the hash in the first line of the supplied content.
+pub(crate) fn detect_hash_algorithm(content: &[u8]) -> Option<HashAlgorithm> {
+    let hash_length = content
+        .iter()
+        .position(|&character| character == b':' || character == b'\n')?;
+    HashAlgorithm::from_hash_length(hash_length)
+}
+
+/// Detect the order of the entries by the complete lines of the supplied content. The content is
+/// considered to be ordered by hash as long as no hash is lower than the hash in front of it.
+pub(crate) fn detect_entry_order(content: &[u8]) -> EntryOrder {
+    // the last line might be cut off, so it is not used for the detection
+    let complete_length = match content.iter().rposition(|&character| character == b'\n') {
+        Some(position) => position,
+        None => return EntryOrder::ByHash,
+    };
+
+    let mut last_hash: Option<&[u8]> = None;
+    for line in content[..complete_length].split(|&character| character == b'\n') {
+        let hash = match line.iter().position(|&character| character == b':') {
+            Some(position) => &line[..position],
+            None => continue,
+        };
+        if let Some(last_hash) = last_hash {
+            let is_lower = hash
+                .iter()
+                .map(u8::to_ascii_uppercase)
+                .lt(last_hash.iter().map(u8::to_ascii_uppercase));
+            if is_lower {
+                return EntryOrder::Unordered;
+            }
+        }
+        last_hash = Some(hash);
     }
+    EntryOrder::ByHash
 }
 
 pub struct DatabaseReader {
-    password_hashes: HashMap<String, u64>,
+    password_hashes: HashMap<HashDigest, u64>,
 }
 
 impl DatabaseReader {
@@ -194,7 +583,7 @@ impl DatabaseReader {
             .append(false)
             .create(false)
             .read(true)
-            .open(&path_to_file)
+            .open(path_to_file)
         {
             Ok(file_handle) => BufReader::new(file_handle),
             Err(error) => return Err(CreateInstanceError::Io(error)),
@@ -227,9 +616,9 @@ impl DatabaseReader {
             let mut splitted_line = current_hash.split(':');
 
             //
-            let key = match splitted_line.next() {
-                Some(value) => value.to_string(),
-                None => {
+            let key = match splitted_line.next().map(HashDigest::from_str) {
+                Some(Ok(value)) => value,
+                _ => {
                     return Err(CreateInstanceError::Format(
                         FormatErrorKind::LineFormatNotCorrect,
                     ))
@@ -264,16 +653,32 @@ impl DatabaseReader {
     }
 
     pub fn get_password_count(&self, password: String) -> Option<u64> {
-        match self.password_hashes.get(password.to_uppercase().as_str()) {
-            Some(value) => Some(*value),
-            None => None,
-        }
+        self.get_digest_count(&HashDigest::from_str(password.as_str()).ok()?)
+    }
+
+    /// Get the number of occurrences of the supplied raw hash.
+    pub fn get_digest_count(&self, digest: &HashDigest) -> Option<u64> {
+        self.password_hashes.get(digest).copied()
+    }
+
+    /// Get all entries whose hashes start with the supplied (hexadecimal) prefix, ordered by their
+    /// hashes. The comparison of the hashes is case insensitive.
+    pub fn get_range(&self, prefix: &str) -> Vec<PasswordHashEntry> {
+        let mut entries: Vec<PasswordHashEntry> = self
+            .password_hashes
+            .iter()
+            .filter(|(hash, _)| hash.starts_with_hex(prefix))
+            .map(|(hash, occurrences)| PasswordHashEntry::from_digest(*hash, *occurrences))
+            .collect();
+        entries.sort();
+        entries
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TemporaryPath;
 
     #[test]
     fn creating_instance_with_invalid_path_fails() {
@@ -284,14 +689,49 @@ mod tests {
         assert_eq!(true, error.to_string().contains("IO error:"));
     }
 
+    #[test]
+    fn the_hash_algorithm_is_detected_by_the_first_line() {
+        assert_eq!(
+            Some(HashAlgorithm::Sha1),
+            detect_hash_algorithm(b"5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3\r\n")
+        );
+        assert_eq!(
+            Some(HashAlgorithm::Ntlm),
+            detect_hash_algorithm(b"8846F7EAEE8FB117AD06BDD830B7586C:3\r\nAB")
+        );
+        assert_eq!(None, detect_hash_algorithm(b"5BAA61E4:3\r\n"));
+        assert_eq!(None, detect_hash_algorithm(b""));
+    }
+
+    #[test]
+    fn files_ordered_by_prevalence_are_detected() {
+        assert_eq!(
+            EntryOrder::ByHash,
+            detect_entry_order(b"000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8:3861493\r\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD9:1\r\n00")
+        );
+        assert_eq!(
+            EntryOrder::Unordered,
+            detect_entry_order(b"7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\r\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n")
+        );
+
+        // the last line might be incomplete, so it is not used
+        assert_eq!(
+            EntryOrder::ByHash,
+            detect_entry_order(
+                b"7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\r\n5BAA61E4C9B9"
+            )
+        );
+        assert_eq!(EntryOrder::ByHash, detect_entry_order(b""));
+    }
+
     #[test]
     fn ensure_get_password_count_is_case_insensitive() {
         let mut fake_reader = DatabaseReader {
             password_hashes: HashMap::new(),
         };
         fake_reader.password_hashes.insert(
-            "0000000A1D4B746FAA3FD526FF6D5BC8052FDB38".to_string(),
-            1 as u64,
+            HashDigest::from_str("0000000A1D4B746FAA3FD526FF6D5BC8052FDB38").unwrap(),
+            1_u64,
         );
 
         let lower_case_input =
@@ -304,4 +744,91 @@ mod tests {
         assert_eq!(true, upper_case_input.is_some());
         assert_eq!(1, upper_case_input.unwrap());
     }
+
+    #[test]
+    fn ensure_get_range_returns_the_sorted_entries_of_the_prefix() {
+        let mut fake_reader = DatabaseReader {
+            password_hashes: HashMap::new(),
+        };
+        fake_reader.password_hashes.insert(
+            HashDigest::from_str("0000000A1D4B746FAA3FD526FF6D5BC8052FDB38").unwrap(),
+            1_u64,
+        );
+        fake_reader.password_hashes.insert(
+            HashDigest::from_str("000000005AD76BD555C1D6D771DE417A4B87E4B4").unwrap(),
+            4_u64,
+        );
+        fake_reader.password_hashes.insert(
+            HashDigest::from_str("0000100A8DAE4228F821FB418F59826079BF3681").unwrap(),
+            7_u64,
+        );
+
+        let range = fake_reader.get_range("00000");
+        assert_eq!(2, range.len());
+        assert_eq!(
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4",
+            range[0].get_hash()
+        );
+        assert_eq!(4, range[0].get_occurrences());
+        assert_eq!(1, range[1].get_occurrences());
+        assert_eq!(1, fake_reader.get_range("00001").len());
+    }
+
+    #[test]
+    fn line_endings_are_counted() {
+        let path_to_file = TemporaryPath::new("line-endings.txt");
+        std::fs::write(
+            &path_to_file,
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\r\n00000000A8DAE4228F821FB418F59826079BF368:2\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493",
+        )
+        .unwrap();
+
+        let mut parser = DatabaseIterator::from_file(path_to_file.to_str().unwrap()).unwrap();
+        assert_eq!(true, parser.next().unwrap().is_ok());
+        assert_eq!(1, parser.get_line_count());
+        assert_eq!(true, parser.next().unwrap().is_ok());
+        assert_eq!(3, parser.get_line_count());
+        assert_eq!(true, parser.next().unwrap().is_ok());
+        assert_eq!(true, parser.next().is_none());
+        assert_eq!(4, parser.get_line_count());
+        assert_eq!(2, parser.get_crlf_line_count());
+        assert_eq!(1, parser.get_lf_line_count());
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_position() {
+        let path_to_file = TemporaryPath::new("malformed.txt");
+        std::fs::write(
+            &path_to_file,
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n00000000A8DAE4228F821FB418F59826079BF368\r\n\r\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\nNOTAHASH:1\r\n",
+        )
+        .unwrap();
+
+        let entries: Vec<_> = DatabaseIterator::from_file(path_to_file.to_str().unwrap())
+            .unwrap()
+            .collect();
+        assert_eq!(4, entries.len());
+        assert_eq!(4, entries[0].as_ref().unwrap().get_occurrences());
+
+        let missing_count = entries[1].as_ref().err().unwrap();
+        assert_eq!(2, missing_count.get_line_number());
+        assert_eq!(44, missing_count.get_byte_offset());
+        assert_eq!(false, missing_count.is_fatal());
+        assert_eq!(
+            true,
+            matches!(
+                missing_count.get_kind(),
+                ParseErrorKind::NoOccurrenceCountFound
+            )
+        );
+
+        // the empty line is skipped, but still counted
+        assert_eq!(3861493, entries[2].as_ref().unwrap().get_occurrences());
+        let invalid_hash = entries[3].as_ref().err().unwrap();
+        assert_eq!(5, invalid_hash.get_line_number());
+        assert_eq!(
+            true,
+            matches!(invalid_hash.get_kind(), ParseErrorKind::InvalidHash)
+        );
+    }
 }
@@ -0,0 +1,581 @@
+use crate::haveibeenpwned::binary::{BinaryDatabaseReader, BinaryDatabaseWriter};
+use crate::haveibeenpwned::manifest::{DatabaseManifest, DEFAULT_PREFIX_LENGTH};
+use crate::haveibeenpwned::{CreateInstanceError, DatabaseReader};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use log::debug;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File, Metadata, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// The reader for a single file (one prefix) of an optimized database.
+pub enum ShardReader {
+    Text(DatabaseReader),
+    Binary(BinaryDatabaseReader),
+}
+
+impl ShardReader {
+    /// Read the supplied file of an optimized database. Files with the extension `bin` are read
+    /// as binary databases, all other files as text files.
+    pub fn from_file(path_to_file: &Path) -> Result<ShardReader, CreateInstanceError> {
+        debug!("Reading the database file {}...", path_to_file.display());
+        match path_to_file.extension() {
+            Some(extension) if extension == "bin" => Ok(ShardReader::Binary(
+                BinaryDatabaseReader::from_file(path_to_file)?,
+            )),
+            _ => Ok(ShardReader::Text(DatabaseReader::from_file(path_to_file)?)),
+        }
+    }
+
+    pub fn get_password_count(&self, password_entry: &PasswordHashEntry) -> Option<u64> {
+        self.get_digest_count(&password_entry.get_digest())
+    }
+
+    pub fn get_digest_count(&self, digest: &HashDigest) -> Option<u64> {
+        match self {
+            ShardReader::Text(reader) => reader.get_digest_count(digest),
+            ShardReader::Binary(reader) => reader.get_digest_count(digest),
+        }
+    }
+
+    pub fn get_range(&self, prefix: &str) -> Vec<PasswordHashEntry> {
+        match self {
+            ShardReader::Text(reader) => reader.get_range(prefix),
+            ShardReader::Binary(reader) => reader.get_range(prefix),
+        }
+    }
+}
+
+/// The writer for a single file (one prefix) of the optimized database.
+pub enum ShardWriter {
+    Text(BufWriter<File>),
+    Binary(BinaryDatabaseWriter),
+}
+
+impl ShardWriter {
+    /// Create the file for the supplied prefix (and its folder, if needed). An existing file will
+    /// be replaced.
+    pub fn create(
+        output_file_name: &Path,
+        algorithm: HashAlgorithm,
+        binary: bool,
+    ) -> Result<ShardWriter, Error> {
+        // files of a nested layout are stored in a sub-directory which might not exist yet
+        if let Some(parent_folder) = output_file_name.parent() {
+            create_dir_all(parent_folder)?;
+        }
+
+        if binary {
+            return Ok(ShardWriter::Binary(BinaryDatabaseWriter::create(
+                output_file_name,
+                algorithm,
+            )?));
+        }
+
+        let file_handle = OpenOptions::new()
+            .write(true)
+            .append(false)
+            .read(false)
+            .create(true)
+            .truncate(true)
+            .open(output_file_name)?;
+        Ok(ShardWriter::Text(BufWriter::new(file_handle)))
+    }
+
+    /// Append a single entry, the entries have to be written in ascending order of their hashes.
+    pub fn write_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        match self {
+            ShardWriter::Text(writer) => {
+                writeln!(writer, "{}:{}", entry.get_digest(), entry.get_occurrences())
+            }
+            ShardWriter::Binary(writer) => writer.write_entry(entry),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            ShardWriter::Text(mut writer) => writer.flush(),
+            ShardWriter::Binary(writer) => writer.finish().map(|_| ()),
+        }
+    }
+}
+
+/// Get the name under which a file of the optimized database is written until it is complete.
+pub fn get_temporary_path(path: &Path) -> PathBuf {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    PathBuf::from(temporary_path)
+}
+
+#[cfg(unix)]
+fn get_inode(metadata: &Metadata) -> u64 {
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn get_inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// The identity of a file, which changes as soon as the file is replaced (e.g. when the `update`
+/// subcommand renames a new version of it into place).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FileIdentity {
+    inode: u64,
+    length: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileIdentity {
+    fn from_file(path_to_file: &Path) -> Option<FileIdentity> {
+        let metadata = std::fs::metadata(path_to_file).ok()?;
+        Some(FileIdentity {
+            inode: get_inode(&metadata),
+            length: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// The readers of the most recently used files of an optimized database. If the cache is full,
+/// the reader which was used least recently is dropped. A reader is also dropped as soon as its
+/// file was replaced, so the new version of the file is read.
+struct ShardCache {
+    capacity: usize,
+    readers: HashMap<String, (Arc<ShardReader>, FileIdentity, u64)>,
+    lookups: u64,
+}
+
+impl ShardCache {
+    fn new(capacity: usize) -> ShardCache {
+        ShardCache {
+            capacity,
+            readers: HashMap::new(),
+            lookups: 0,
+        }
+    }
+
+    fn get(&mut self, prefix: &str, identity: FileIdentity) -> Option<Arc<ShardReader>> {
+        self.lookups += 1;
+        let (reader, cached_identity, last_lookup) = self.readers.get_mut(prefix)?;
+        if *cached_identity != identity {
+            debug!("The file for the prefix {} was replaced", prefix);
+            self.readers.remove(prefix);
+            return None;
+        }
+        *last_lookup = self.lookups;
+        Some(Arc::clone(reader))
+    }
+
+    fn insert(&mut self, prefix: String, reader: Arc<ShardReader>, identity: FileIdentity) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.readers.len() >= self.capacity && !self.readers.contains_key(&prefix) {
+            let least_recently_used = self
+                .readers
+                .iter()
+                .min_by_key(|(_, (_, _, last_lookup))| *last_lookup)
+                .map(|(prefix, _)| prefix.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                self.readers.remove(&least_recently_used);
+            }
+        }
+        self.readers
+            .insert(prefix, (reader, identity, self.lookups));
+    }
+}
+
+/// This class can be used to look up password hashes in a folder which was written by the
+/// `optimize` subcommand.
+///
+/// The layout of the folder (prefix length, nesting and file format) is taken from the manifest
+/// stored in the folder. Folders without a manifest are expected to use the layout of the older
+/// versions of the tool (3-character prefixes in a flat folder).
+///
+/// Every lookup opens the file of its prefix, unless the [cache](#method.with_shard_cache) for
+/// the opened files is enabled.
+pub struct OptimizedDatabase {
+    folder: PathBuf,
+    manifest: Option<DatabaseManifest>,
+    shard_cache: Option<Mutex<ShardCache>>,
+}
+
+impl OptimizedDatabase {
+    /// Get a new instance for the optimized database in the supplied folder.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the folder does not exist or if the manifest of the
+    /// database could not be read.
+    pub fn from_folder(folder: &Path) -> Result<OptimizedDatabase, CreateInstanceError> {
+        if !folder.is_dir() {
+            return Err(CreateInstanceError::Io(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not a folder", folder.display()),
+            )));
+        }
+
+        Ok(OptimizedDatabase {
+            folder: folder.to_path_buf(),
+            manifest: DatabaseManifest::from_folder(folder)?,
+            shard_cache: None,
+        })
+    }
+
+    /// Keep the readers of the supplied number of most recently used files open, so repeated
+    /// lookups in the same files (e.g. by a server) do not have to read them again. Files which
+    /// are replaced in the meantime (e.g. by the `update` subcommand) are read again.
+    pub fn with_shard_cache(mut self, capacity: usize) -> OptimizedDatabase {
+        self.shard_cache = Some(Mutex::new(ShardCache::new(capacity)));
+        self
+    }
+
+    /// Get the manifest of the database, if the folder contains one.
+    pub fn get_manifest(&self) -> Option<&DatabaseManifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Get the hash algorithm of the stored hashes, if it was recorded in the manifest.
+    pub fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.manifest
+            .as_ref()
+            .map(|manifest| manifest.get_hash_algorithm())
+    }
+
+    /// Get the length of the prefixes which are used for splitting the database into files.
+    pub fn get_prefix_length(&self) -> usize {
+        match &self.manifest {
+            Some(manifest) => manifest.get_prefix_length(),
+            None => DEFAULT_PREFIX_LENGTH,
+        }
+    }
+
+    /// Get the path of the file in which the entries with the supplied prefix are stored.
+    pub fn get_file_path(&self, prefix: &str) -> PathBuf {
+        let prefix = prefix.to_uppercase();
+        if let Some(manifest) = &self.manifest {
+            return manifest.get_file_path(&self.folder, &prefix);
+        }
+
+        // if there is a file in the compact binary format for the prefix, we prefer it over the text file
+        let binary_file_path = self.folder.join(format!("{}.bin", prefix));
+        if binary_file_path.exists() {
+            return binary_file_path;
+        }
+        self.folder.join(format!("{}.txt", prefix))
+    }
+
+    /// Open the file in which the entries with the supplied prefix are stored. If there is no file
+    /// for the prefix (no hash with this prefix is known or the prefix is not a hexadecimal prefix
+    /// of the length used for splitting the database), `None` is returned.
+    pub fn open_file(&self, prefix: &str) -> Result<Option<ShardReader>, CreateInstanceError> {
+        // anything else could point to a file outside of the database folder
+        if prefix.len() != self.get_prefix_length()
+            || !prefix
+                .bytes()
+                .all(|character| character.is_ascii_hexdigit())
+        {
+            return Ok(None);
+        }
+
+        match ShardReader::from_file(&self.get_file_path(prefix)) {
+            Ok(reader) => Ok(Some(reader)),
+            Err(CreateInstanceError::Io(ref error)) if error.kind() == ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Get the reader for the file of the supplied prefix, from the cache of the opened files if
+    /// it is enabled.
+    fn get_reader(&self, prefix: &str) -> Result<Option<Arc<ShardReader>>, CreateInstanceError> {
+        let shard_cache = match &self.shard_cache {
+            Some(shard_cache) => shard_cache,
+            None => return Ok(self.open_file(prefix)?.map(Arc::new)),
+        };
+        let prefix = prefix.to_uppercase();
+
+        // the identity is checked before the file is opened, so a file which is replaced in the
+        // meantime is read again by the next lookup
+        let identity = match FileIdentity::from_file(&self.get_file_path(&prefix)) {
+            Some(identity) => identity,
+            None => return Ok(self.open_file(&prefix)?.map(Arc::new)),
+        };
+        if let Some(reader) = shard_cache
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&prefix, identity))
+        {
+            return Ok(Some(reader));
+        }
+
+        // the file is read without holding the lock, so other lookups do not have to wait
+        let reader = match self.open_file(&prefix)? {
+            Some(reader) => Arc::new(reader),
+            None => return Ok(None),
+        };
+        if let Ok(mut cache) = shard_cache.lock() {
+            cache.insert(prefix, Arc::clone(&reader), identity);
+        }
+        Ok(Some(reader))
+    }
+
+    /// Get the prefix of the file in which the supplied entry would be stored.
+    pub fn get_prefix(&self, password_entry: &PasswordHashEntry) -> Option<String> {
+        password_entry.get_dynamic_prefix(self.get_prefix_length())
+    }
+
+    /// Get the number of occurrences of the supplied password hash entry in the database.
+    pub fn get_password_count(
+        &self,
+        password_entry: &PasswordHashEntry,
+    ) -> Result<Option<u64>, CreateInstanceError> {
+        self.get_digest_count(&password_entry.get_digest())
+    }
+
+    /// Get the number of occurrences of the supplied password hash in the database.
+    pub fn get_digest_count(
+        &self,
+        digest: &HashDigest,
+    ) -> Result<Option<u64>, CreateInstanceError> {
+        let prefix = match digest.get_hex_prefix(self.get_prefix_length()) {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+        Ok(self
+            .get_reader(&prefix)?
+            .and_then(|reader| reader.get_digest_count(digest)))
+    }
+
+    /// Get all entries whose hashes start with the supplied (hexadecimal) prefix, ordered by their
+    /// hashes. If the prefix is shorter than the one used for splitting the database, all files
+    /// which start with the prefix are read. Prefixes which are not hexadecimal or longer than the
+    /// hashes of the database do not match any entry.
+    pub fn get_range(&self, prefix: &str) -> Result<Vec<PasswordHashEntry>, CreateInstanceError> {
+        let hash_algorithm = self.get_hash_algorithm().unwrap_or(HashAlgorithm::Sha1);
+        if HashDigest::from_hex_prefix(prefix, hash_algorithm).is_none() {
+            return Ok(Vec::new());
+        }
+
+        let prefix_length = self.get_prefix_length();
+        if prefix.len() >= prefix_length {
+            return Ok(match self.get_reader(&prefix[..prefix_length])? {
+                Some(reader) => reader.get_range(prefix),
+                None => Vec::new(),
+            });
+        }
+
+        let mut entries = Vec::new();
+        for file_prefix in self.get_file_prefixes(prefix) {
+            if let Some(reader) = self.get_reader(&file_prefix)? {
+                entries.append(&mut reader.get_range(prefix));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Get the (ascending) prefixes of all files which might contain entries with the supplied
+    /// (shorter) prefix. The files which are listed in the manifest are used if possible, so not
+    /// every possible prefix has to be tried.
+    fn get_file_prefixes(&self, prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_uppercase();
+        if let Some(manifest) = &self.manifest {
+            if !manifest.get_files().is_empty() {
+                let mut file_prefixes: Vec<String> = manifest
+                    .get_files()
+                    .iter()
+                    .map(|file| file.get_prefix().to_uppercase())
+                    .filter(|file_prefix| file_prefix.starts_with(&prefix))
+                    .collect();
+                file_prefixes.sort();
+                return file_prefixes;
+            }
+        }
+
+        let missing_characters = self.get_prefix_length() - prefix.len();
+        (0..(1u32 << (4 * missing_characters)))
+            .map(|suffix| format!("{}{:0width$X}", prefix, suffix, width = missing_characters))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::manifest::ManifestFileEntry;
+    use crate::testing::TemporaryPath;
+    use std::fs::create_dir_all;
+
+    #[test]
+    fn folders_without_a_manifest_use_the_legacy_layout() {
+        let folder = TemporaryPath::new_folder("optimized-legacy");
+        std::fs::write(
+            folder.join("5BA.txt"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+
+        let database = OptimizedDatabase::from_folder(&folder).unwrap();
+        assert_eq!(3, database.get_prefix_length());
+        assert_eq!(
+            Some(3861493),
+            database
+                .get_password_count(&PasswordHashEntry::from_password("password"))
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            database
+                .get_password_count(&PasswordHashEntry::from_password("sample_password"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn folders_with_a_manifest_use_the_recorded_layout() {
+        let folder = TemporaryPath::new_folder("optimized-nested");
+        let manifest = DatabaseManifest::new(4, true, false, HashAlgorithm::Sha1).unwrap();
+        manifest.write_to_folder(&folder).unwrap();
+        create_dir_all(folder.join("5B")).unwrap();
+        std::fs::write(
+            folder.join("5B").join("AA.txt"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        std::fs::write(
+            folder.join("5B").join("AB.txt"),
+            "5BAB61E4C9B93F3F0682250B6CF8331B7EE68FD8:3\n",
+        )
+        .unwrap();
+
+        let database = OptimizedDatabase::from_folder(&folder).unwrap();
+        assert_eq!(Some(HashAlgorithm::Sha1), database.get_hash_algorithm());
+        assert_eq!(
+            Some(3861493),
+            database
+                .get_password_count(&PasswordHashEntry::from_password("password"))
+                .unwrap()
+        );
+        assert_eq!(1, database.get_range("5BAA6").unwrap().len());
+        assert_eq!(2, database.get_range("5BA").unwrap().len());
+        assert_eq!(0, database.get_range("FFFFF").unwrap().len());
+    }
+
+    #[test]
+    fn ranges_are_just_read_for_hexadecimal_prefixes() {
+        let folder = TemporaryPath::new_folder("optimized-prefixes");
+        let mut manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        for hash in &[
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8",
+            "7C4A8D09CA3762AF61E59520943DC26494F8941B",
+        ] {
+            let path_to_file = manifest.get_file_path(&folder, &hash[..3]);
+            std::fs::write(&path_to_file, format!("{}:1\n", hash)).unwrap();
+            manifest.add_file(ManifestFileEntry::from_file(&hash[..3], &path_to_file, 1).unwrap());
+        }
+        manifest.write_to_folder(&folder).unwrap();
+
+        let database = OptimizedDatabase::from_folder(&folder).unwrap();
+        assert_eq!(0, database.get_range("ééé").unwrap().len());
+        assert_eq!(0, database.get_range("../x").unwrap().len());
+        assert_eq!(0, database.get_range(&"5".repeat(41)).unwrap().len());
+        assert_eq!(true, database.open_file("../").unwrap().is_none());
+
+        // an empty prefix matches all entries, just the listed files are read for it
+        let entries = database.get_range("").unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            "7C4A8D09CA3762AF61E59520943DC26494F8941B",
+            entries[1].get_hash()
+        );
+        assert_eq!(1, database.get_range("5b").unwrap().len());
+    }
+
+    #[test]
+    fn cached_readers_are_reused_until_they_are_least_recently_used() {
+        let folder = TemporaryPath::new_folder("optimized-cache");
+        let manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        manifest.write_to_folder(&folder).unwrap();
+        for prefix in &["000", "5BA", "7C4"] {
+            std::fs::write(
+                folder.join(format!("{}.txt", prefix)),
+                format!("{}A61E4C9B93F3F0682250B6CF8331B7EE68FD8:3\n", prefix),
+            )
+            .unwrap();
+        }
+
+        let database = OptimizedDatabase::from_folder(&folder)
+            .unwrap()
+            .with_shard_cache(2);
+        let first_reader = database.get_reader("000").unwrap().unwrap();
+        assert_eq!(
+            true,
+            Arc::ptr_eq(&first_reader, &database.get_reader("000").unwrap().unwrap())
+        );
+        database.get_reader("5ba").unwrap().unwrap();
+        assert_eq!(
+            true,
+            Arc::ptr_eq(&first_reader, &database.get_reader("000").unwrap().unwrap())
+        );
+
+        // the reader for 5BA was used least recently, so it is dropped instead of the one for 000
+        database.get_reader("7C4").unwrap().unwrap();
+        assert_eq!(
+            true,
+            Arc::ptr_eq(&first_reader, &database.get_reader("000").unwrap().unwrap())
+        );
+        let cache = database.shard_cache.as_ref().unwrap().lock().unwrap();
+        assert_eq!(false, cache.readers.contains_key("5BA"));
+        assert_eq!(true, cache.readers.contains_key("7C4"));
+        drop(cache);
+        assert_eq!(None, database.get_reader("FFF").unwrap().map(|_| ()));
+        assert_eq!(1, database.get_range("5BAA6").unwrap().len());
+    }
+
+    #[test]
+    fn cached_readers_of_replaced_files_are_dropped() {
+        let folder = TemporaryPath::new_folder("optimized-cache-update");
+        let manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        manifest.write_to_folder(&folder).unwrap();
+        std::fs::write(
+            folder.join("5BA.txt"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3\n",
+        )
+        .unwrap();
+
+        let database = OptimizedDatabase::from_folder(&folder)
+            .unwrap()
+            .with_shard_cache(2);
+        let password_entry = PasswordHashEntry::from_password("password");
+        assert_eq!(
+            Some(3),
+            database.get_password_count(&password_entry).unwrap()
+        );
+
+        // the new file is renamed into place, like it is done by the update subcommand
+        let temporary_path = get_temporary_path(&folder.join("5BA.txt"));
+        std::fs::write(
+            &temporary_path,
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        std::fs::rename(&temporary_path, folder.join("5BA.txt")).unwrap();
+        assert_eq!(
+            Some(3861493),
+            database.get_password_count(&password_entry).unwrap()
+        );
+    }
+
+    #[test]
+    fn opening_a_missing_folder_fails() {
+        let maybe_instance = OptimizedDatabase::from_folder(Path::new("/this/does/not/exist"));
+        assert_eq!(true, maybe_instance.is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::haveibeenpwned::{
+    CreateInstanceError, DatabaseIterator, EntryOrder, FormatErrorKind, ParseError, ParseErrorKind,
+};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+
+/// This class can be used to walk through the entries of a password file which has to be ordered
+/// by hash, e.g. for comparing or merging several files at the same time. Walking through such
+/// files would not be correct for malformed lines or lines in a different order, so an error is
+/// returned for such a line and the iteration stops afterwards.
+///
+/// # Example
+/// ```
+/// use pwned_rs::haveibeenpwned::ordered::SortedEntries;
+///
+/// match SortedEntries::from_file("/path/to/the/hash/file.txt") {
+///     Ok(entries) => {
+///         for entry in entries {
+///             match entry {
+///                 Ok(entry) => println!("{}:{}", entry.get_hash(), entry.get_occurrences()),
+///                 Err(error) => println!("The file can not be used, the error was: {}", error),
+///             }
+///         }
+///     }
+///     Err(error) => println!("Could not get an instance, the error was: {}", error)
+/// }
+/// ```
+pub struct SortedEntries {
+    parser: DatabaseIterator,
+    last_hash: Option<HashDigest>,
+    failed: bool,
+}
+
+impl SortedEntries {
+    /// Get a new instance for the password file at the supplied path.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the password file could not be opened or if the
+    /// lines at its beginning are not ordered by hash.
+    pub fn from_file(path_to_file: &str) -> Result<SortedEntries, CreateInstanceError> {
+        let parser = DatabaseIterator::from_file(path_to_file)?;
+        if parser.get_entry_order() == EntryOrder::Unordered {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::NotOrderedByHash,
+            ));
+        }
+
+        Ok(SortedEntries {
+            parser,
+            last_hash: None,
+            failed: false,
+        })
+    }
+
+    pub fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.parser.get_hash_algorithm()
+    }
+
+    /// Get the size of the password file (the compressed size for archives).
+    pub fn get_file_size(&self) -> Option<u64> {
+        self.parser.get_file_size()
+    }
+
+    /// Get the number of bytes of the password file which were already processed, so it can be
+    /// compared to the [file size](#method.get_file_size) for showing the progress.
+    pub fn get_processed_bytes(&self) -> u64 {
+        self.parser.get_processed_bytes()
+    }
+}
+
+impl Iterator for SortedEntries {
+    type Item = Result<PasswordHashEntry, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let byte_offset = self.parser.get_byte_offset();
+        let entry = match self.parser.next()? {
+            Ok(entry) => entry,
+            Err(error) => {
+                self.failed = true;
+                return Some(Err(error));
+            }
+        };
+        let hash = entry.get_digest();
+        if let Some(previous_hash) = self.last_hash {
+            if hash <= previous_hash {
+                self.failed = true;
+                return Some(Err(ParseError::new(
+                    self.parser.get_line_count(),
+                    byte_offset,
+                    ParseErrorKind::NotOrderedByHash {
+                        hash,
+                        previous_hash,
+                    },
+                )));
+            }
+        }
+
+        self.last_hash = Some(hash);
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+
+    fn read_entries(name: &str, content: &str) -> Result<Vec<String>, String> {
+        let file_path = TemporaryPath::new(name);
+        std::fs::write(&file_path, content).unwrap();
+        match SortedEntries::from_file(file_path.to_str().unwrap()) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.get_hash()))
+                .collect::<Result<Vec<String>, ParseError>>()
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    #[test]
+    fn entries_ordered_by_hash_are_returned() {
+        let entries = read_entries(
+            "ordered-entries.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n",
+        )
+        .unwrap();
+        assert_eq!(3, entries.len());
+        assert_eq!("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", entries[2]);
+    }
+
+    #[test]
+    fn entries_in_a_different_order_stop_the_iteration() {
+        let error = read_entries(
+            "misordered-entries.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n\
+             7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\n",
+        )
+        .unwrap_err();
+        assert_eq!(true, error.starts_with("Line 4 "));
+        assert_eq!(true, error.contains("is not ordered by hash"));
+
+        let error = read_entries(
+            "malformed-entries.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\nnot a hash\n",
+        )
+        .unwrap_err();
+        assert_eq!(true, error.starts_with("Line 2 "));
+    }
+}
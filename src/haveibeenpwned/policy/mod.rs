@@ -0,0 +1,114 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The number of occurrences from which on a password is rejected, if no other threshold is
+/// selected. Every password which was found in any breach is rejected.
+pub const DEFAULT_REJECT_THRESHOLD: u64 = 1;
+
+/// The verdict for a password which was looked up in a password database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// The password was not found in any breach.
+    NotFound,
+    /// The password was found, but less often than the threshold for rejecting it.
+    Warn(u64),
+    /// The password was found at least as often as the threshold for rejecting it.
+    Reject(u64),
+}
+
+impl Verdict {
+    /// Get the number of occurrences of the password, if it was found.
+    pub fn get_occurrences(self) -> Option<u64> {
+        match self {
+            Verdict::NotFound => None,
+            Verdict::Warn(occurrences) | Verdict::Reject(occurrences) => Some(occurrences),
+        }
+    }
+
+    /// Check if the password should be rejected.
+    pub fn is_rejected(self) -> bool {
+        matches!(self, Verdict::Reject(_))
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Verdict::NotFound => write!(f, "not found"),
+            Verdict::Warn(occurrences) => write!(f, "warn ({} occurrences)", occurrences),
+            Verdict::Reject(occurrences) => write!(f, "reject ({} occurrences)", occurrences),
+        }
+    }
+}
+
+/// This class can be used to decide what should happen with a password, based on the number of
+/// times it was found in password breaches. A password which was found at least as often as the
+/// threshold is rejected, a password which was found less often just causes a warning.
+///
+/// # Example
+/// ```
+/// use pwned_rs::haveibeenpwned::policy::{ThresholdPolicy, Verdict};
+///
+/// let policy = ThresholdPolicy::new(10);
+/// assert_eq!(Verdict::NotFound, policy.get_verdict(None));
+/// assert_eq!(Verdict::Warn(3), policy.get_verdict(Some(3)));
+/// assert_eq!(Verdict::Reject(3861493), policy.get_verdict(Some(3861493)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdPolicy {
+    reject_threshold: u64,
+}
+
+impl ThresholdPolicy {
+    /// Create a policy which rejects passwords which were found at least as often as the supplied
+    /// threshold. A threshold of 0 is treated like 1, so a password which was not found is never
+    /// rejected.
+    pub fn new(reject_threshold: u64) -> ThresholdPolicy {
+        ThresholdPolicy {
+            reject_threshold: reject_threshold.max(1),
+        }
+    }
+
+    pub fn get_reject_threshold(&self) -> u64 {
+        self.reject_threshold
+    }
+
+    /// Get the verdict for a password with the supplied number of occurrences (`None` if the
+    /// password was not found).
+    pub fn get_verdict(&self, occurrences: Option<u64>) -> Verdict {
+        match occurrences {
+            None | Some(0) => Verdict::NotFound,
+            Some(count) if count >= self.reject_threshold => Verdict::Reject(count),
+            Some(count) => Verdict::Warn(count),
+        }
+    }
+}
+
+impl Default for ThresholdPolicy {
+    fn default() -> Self {
+        ThresholdPolicy::new(DEFAULT_REJECT_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_verdict_depends_on_the_threshold() {
+        let policy = ThresholdPolicy::new(100);
+        assert_eq!(Verdict::NotFound, policy.get_verdict(None));
+        assert_eq!(Verdict::NotFound, policy.get_verdict(Some(0)));
+        assert_eq!(Verdict::Warn(1), policy.get_verdict(Some(1)));
+        assert_eq!(Verdict::Warn(99), policy.get_verdict(Some(99)));
+        assert_eq!(Verdict::Reject(100), policy.get_verdict(Some(100)));
+        assert_eq!(true, policy.get_verdict(Some(100)).is_rejected());
+        assert_eq!(Some(99), policy.get_verdict(Some(99)).get_occurrences());
+
+        // by default every password which was found is rejected
+        assert_eq!(
+            Verdict::Reject(1),
+            ThresholdPolicy::default().get_verdict(Some(1))
+        );
+        assert_eq!(1, ThresholdPolicy::new(0).get_reject_threshold());
+    }
+}
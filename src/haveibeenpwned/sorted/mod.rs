@@ -0,0 +1,424 @@
+use crate::haveibeenpwned::compression::CompressionFormat;
+use crate::haveibeenpwned::{
+    detect_entry_order, detect_hash_algorithm, CreateInstanceError, EntryOrder, FormatErrorKind,
+};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use log::error;
+use memmap2::Mmap;
+use std::cmp::min;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The number of bytes at the beginning of the file which are used for detecting its order.
+const ORDER_DETECTION_LENGTH: usize = 1024 * 1024;
+
+/// This class can be used to look up password hashes directly in the password file provided by
+/// https://haveibeenpwned.com, as long as it is ordered by the hash of the passwords.
+///
+/// The file is memory-mapped and every lookup is done through an iterative binary search on the
+/// raw bytes of the file, so no additional memory is needed and the lines are not parsed unless
+/// they are required. An instance does not carry any state between two lookups, so it can be
+/// used for an unlimited number of lookups and can be shared between threads.
+pub struct SortedHashFile {
+    mapped_file: Mmap,
+}
+
+impl SortedHashFile {
+    /// Get a new instance for the password file at the provided path.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file does not exist, the user does not have the
+    /// access rights to read it, if the file could not be mapped into memory or if the file is
+    /// compressed.
+    ///
+    /// # Example
+    /// ```
+    /// use pwned_rs::haveibeenpwned::SortedHashFile;
+    /// use pwned_rs::PasswordHashEntry;
+    /// use std::path::Path;
+    ///
+    /// match SortedHashFile::from_file(Path::new("/path/to/the/hash/file.txt")) {
+    ///     Ok(instance) => {
+    ///         let entry = PasswordHashEntry::from_password("password");
+    ///         match instance.get_password_count(&entry) {
+    ///             Some(count) => println!("The password was found {} times", count),
+    ///             None => println!("The password was not found"),
+    ///         }
+    ///     }
+    ///     Err(error) => println!("Could not get an instance, the error was: {}", error)
+    /// }
+    /// ```
+    pub fn from_file(path_to_file: &Path) -> Result<SortedHashFile, CreateInstanceError> {
+        let file_handle = match OpenOptions::new()
+            .append(false)
+            .write(false)
+            .read(true)
+            .open(path_to_file)
+        {
+            Ok(handle) => handle,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        // the file is only read and we expect that it is not modified while we are using it
+        let mapped_file = match unsafe { Mmap::map(&file_handle) } {
+            Ok(mapped_file) => mapped_file,
+            Err(error) => return Err(CreateInstanceError::Io(error)),
+        };
+
+        // compressed files can not be searched without extracting them first
+        if CompressionFormat::from_magic_bytes(&mapped_file[..]).is_compressed() {
+            return Err(CreateInstanceError::Format(FormatErrorKind::NotATextFile));
+        }
+
+        // the binary search just works if the entries are ordered by their hash
+        let detection_length = min(mapped_file.len(), ORDER_DETECTION_LENGTH);
+        if detect_entry_order(&mapped_file[..detection_length]) == EntryOrder::Unordered {
+            return Err(CreateInstanceError::Format(
+                FormatErrorKind::NotOrderedByHash,
+            ));
+        }
+
+        Ok(SortedHashFile { mapped_file })
+    }
+
+    /// Get the size of the mapped password file in bytes.
+    pub fn get_file_size(&self) -> u64 {
+        self.mapped_file.len() as u64
+    }
+
+    /// Get the hash algorithm which was used for the hashes in the file. The algorithm is detected
+    /// by the length of the hash in the first line of the file.
+    pub fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        detect_hash_algorithm(&self.mapped_file[..])
+    }
+
+    /// Get the number of occurrences of the supplied password hash entry in the file.
+    pub fn get_password_count(&self, seeked_password_hash: &PasswordHashEntry) -> Option<u64> {
+        self.get_digest_count(&seeked_password_hash.get_digest())
+    }
+
+    /// Get the number of occurrences of the supplied (hexadecimal) hash in the file. The
+    /// comparison of the hashes is case insensitive.
+    pub fn get_hash_count(&self, seeked_hash: &str) -> Option<u64> {
+        self.get_digest_count(&HashDigest::from_str(seeked_hash).ok()?)
+    }
+
+    /// Get the number of occurrences of the supplied hash in the file.
+    pub fn get_digest_count(&self, seeked_digest: &HashDigest) -> Option<u64> {
+        let (digest_at_line, occurrences_at_line) = self.get_lines_from(seeked_digest).next()?;
+        if digest_at_line != Some(*seeked_digest) {
+            return None;
+        }
+        parse_occurrences(occurrences_at_line?)
+    }
+
+    /// Get all entries whose hashes start with the supplied (hexadecimal) prefix, ordered by their
+    /// hashes. The comparison of the hashes is case insensitive.
+    pub fn get_range(&self, prefix: &str) -> Vec<PasswordHashEntry> {
+        // the range starts with the smallest hash which has the prefix
+        let first_digest = match self
+            .get_hash_algorithm()
+            .and_then(|hash_algorithm| HashDigest::from_hex_prefix(prefix, hash_algorithm))
+        {
+            Some(first_digest) => first_digest,
+            None => return Vec::new(),
+        };
+        self.get_lines_from(&first_digest)
+            .take_while(|(digest_at_line, _)| match digest_at_line {
+                Some(digest) => digest.starts_with_hex(prefix),
+                None => true,
+            })
+            .filter_map(|(digest_at_line, occurrences_at_line)| {
+                Some(PasswordHashEntry::from_digest(
+                    digest_at_line?,
+                    parse_occurrences(occurrences_at_line?)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Get an iterator over the lines of the file (split into the parsed hash and the occurrence
+    /// count), starting with the first line whose hash is not less than the supplied one. The hash
+    /// of a malformed line is `None`.
+    fn get_lines_from(
+        &self,
+        seeked_digest: &HashDigest,
+    ) -> impl Iterator<Item = (Option<HashDigest>, Option<&[u8]>)> {
+        let content = &self.mapped_file[..];
+
+        // the lower bound is always the start of a line and the upper bound always the end of one
+        let mut lower_bound = 0;
+        let mut upper_bound = content.len();
+        while lower_bound < upper_bound {
+            let mid = lower_bound + (upper_bound - lower_bound) / 2;
+
+            // determine the boundaries of the line the middle position is part of
+            let line_start = match content[..mid].iter().rposition(|&byte| byte == b'\n') {
+                Some(position) => position + 1,
+                None => 0,
+            };
+            let line_end = match content[line_start..].iter().position(|&byte| byte == b'\n') {
+                Some(position) => line_start + position,
+                None => content.len(),
+            };
+
+            // determine in which block we should continue our search, malformed lines are treated
+            // like smaller hashes
+            let (digest_at_current_line, _) = split_line(&content[line_start..line_end]);
+            let is_less = match digest_at_current_line {
+                Some(digest) => digest < *seeked_digest,
+                None => true,
+            };
+            if is_less {
+                lower_bound = line_end + 1;
+            } else {
+                upper_bound = line_start;
+            }
+        }
+
+        content[lower_bound.min(content.len())..]
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(split_line)
+    }
+}
+
+/// Split a line into the (parsed) hash in front of the separating colon and the occurrence count
+/// behind it.
+fn split_line(line: &[u8]) -> (Option<HashDigest>, Option<&[u8]>) {
+    let (raw_hash, raw_occurrences) = match line.iter().position(|&byte| byte == b':') {
+        Some(separator) => (&line[..separator], Some(&line[separator + 1..])),
+        None => (line, None),
+    };
+    let digest = std::str::from_utf8(raw_hash)
+        .ok()
+        .and_then(|hash| HashDigest::from_str(hash.trim()).ok());
+    (digest, raw_occurrences)
+}
+
+/// Parse the occurrence count stored behind the separator of a line.
+fn parse_occurrences(raw_occurrences: &[u8]) -> Option<u64> {
+    let occurrences = match std::str::from_utf8(raw_occurrences) {
+        Ok(occurrences) => occurrences.trim(),
+        Err(_) => {
+            error!("The occurrence count of the found password hash is not valid text.");
+            return None;
+        }
+    };
+    match occurrences.parse::<u64>() {
+        Ok(count) => Some(count),
+        Err(_) => {
+            error!(
+                "Could not parse the number of occurrences of the password. Maybe \"{}\" not a number.",
+                occurrences
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::database::PasswordDatabase;
+    use crate::haveibeenpwned::policy::{ThresholdPolicy, Verdict};
+    use crate::testing::TemporaryPath;
+
+    fn get_sorted_hash_file(name: &str, content: &str) -> (TemporaryPath, SortedHashFile) {
+        let file_path = TemporaryPath::new(name);
+        std::fs::write(&file_path, content).unwrap();
+        let instance = SortedHashFile::from_file(&file_path).unwrap();
+        (file_path, instance)
+    }
+
+    #[test]
+    fn creating_instance_with_invalid_path_fails() {
+        let maybe_instance = SortedHashFile::from_file(Path::new("/this/file/does/not/exist.txt"));
+
+        assert_eq!(true, maybe_instance.is_err());
+        let error = maybe_instance.err().unwrap();
+        assert_eq!(true, error.to_string().contains("IO error:"));
+    }
+
+    #[test]
+    fn multiple_lookups_with_the_same_instance_work() {
+        let (_file_path, instance) = get_sorted_hash_file(
+            "multiple-lookups.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\r\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n\
+             FDC625010C4BEB998E590924DF39B7E59298612D:1\r\n",
+        );
+
+        assert_eq!(
+            Some(4),
+            instance.get_hash_count("000000005AD76BD555C1D6D771DE417A4B87E4B4")
+        );
+        assert_eq!(
+            Some(2),
+            instance.get_hash_count("00000000A8DAE4228F821FB418F59826079BF368")
+        );
+        assert_eq!(
+            Some(3861493),
+            instance.get_password_count(&PasswordHashEntry::from_password("password"))
+        );
+        assert_eq!(
+            Some(1),
+            instance.get_password_count(&PasswordHashEntry::from_password("sample_password"))
+        );
+        assert_eq!(
+            None,
+            instance.get_hash_count("00000000A8DAE4228F821FB418F59826079BF369")
+        );
+        assert_eq!(
+            None,
+            instance.get_hash_count("0000000000000000000000000000000000000000")
+        );
+        assert_eq!(
+            None,
+            instance.get_hash_count("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF")
+        );
+        assert_eq!(None, instance.get_hash_count("not a hash"));
+    }
+
+    #[test]
+    fn the_verdict_follows_the_threshold_policy() {
+        let (_file_path, instance) = get_sorted_hash_file(
+            "verdicts.txt",
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n\
+             FDC625010C4BEB998E590924DF39B7E59298612D:1\r\n",
+        );
+        let policy = ThresholdPolicy::new(10);
+
+        assert_eq!(
+            Verdict::Reject(3861493),
+            instance
+                .get_verdict(&HashAlgorithm::Sha1.hash_password("password"), &policy)
+                .unwrap()
+        );
+        assert_eq!(
+            Verdict::Warn(1),
+            instance
+                .get_verdict(
+                    &HashAlgorithm::Sha1.hash_password("sample_password"),
+                    &policy
+                )
+                .unwrap()
+        );
+        assert_eq!(
+            Verdict::NotFound,
+            instance
+                .get_verdict(
+                    &HashAlgorithm::Sha1.hash_password("not in the file"),
+                    &policy
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn every_entry_of_a_larger_file_can_be_found() {
+        let mut hashes: Vec<String> = (0..1000)
+            .map(|index| PasswordHashEntry::from_password(&format!("password{}", index)).get_hash())
+            .collect();
+        hashes.sort();
+        let content: String = hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hash)| format!("{}:{}\r\n", hash.to_uppercase(), index + 1))
+            .collect();
+        let (_file_path, instance) = get_sorted_hash_file("larger-file.txt", &content);
+
+        for (index, hash) in hashes.iter().enumerate() {
+            assert_eq!(Some(index as u64 + 1), instance.get_hash_count(hash));
+        }
+    }
+
+    #[test]
+    fn lookups_in_files_without_trailing_new_line_work() {
+        let (_file_path, instance) = get_sorted_hash_file(
+            "no-trailing-new-line.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493",
+        );
+
+        assert_eq!(
+            Some(3861493),
+            instance.get_hash_count("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8")
+        );
+        assert_eq!(
+            Some(4),
+            instance.get_hash_count("000000005ad76bd555c1d6d771de417a4b87e4b4")
+        );
+    }
+
+    #[test]
+    fn all_entries_of_a_range_are_returned() {
+        let (_file_path, instance) = get_sorted_hash_file(
+            "range.txt",
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\r\n\
+             0000100A8DAE4228F821FB418F59826079BF3681:7\r\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n",
+        );
+
+        let range = instance.get_range("00000");
+        assert_eq!(2, range.len());
+        assert_eq!(
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4",
+            range[0].get_hash()
+        );
+        assert_eq!(4, range[0].get_occurrences());
+        assert_eq!(2, range[1].get_occurrences());
+        assert_eq!(1, instance.get_range("5baa6").len());
+        assert_eq!(0, instance.get_range("5BAA7").len());
+        assert_eq!(0, instance.get_range("FFFFF").len());
+        assert_eq!(0, instance.get_range("5BAX").len());
+        assert_eq!(4, instance.get_range("").len());
+    }
+
+    #[test]
+    fn lookups_in_a_ntlm_file_work() {
+        let (_file_path, instance) = get_sorted_hash_file(
+            "ntlm.txt",
+            "00000000ECBB14E1CDE42EB3B2E3E8CC:3\r\n\
+             8846F7EAEE8FB117AD06BDD830B7586C:2540811\r\n",
+        );
+
+        assert_eq!(Some(HashAlgorithm::Ntlm), instance.get_hash_algorithm());
+        assert_eq!(
+            Some(2540811),
+            instance.get_password_count(&PasswordHashEntry::from_password_with_algorithm(
+                "password",
+                HashAlgorithm::Ntlm
+            ))
+        );
+    }
+
+    #[test]
+    fn lookups_in_an_empty_file_do_not_find_anything() {
+        let (_file_path, instance) = get_sorted_hash_file("empty.txt", "");
+
+        assert_eq!(0, instance.get_file_size());
+        assert_eq!(
+            None,
+            instance.get_hash_count("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8")
+        );
+    }
+
+    #[test]
+    fn files_ordered_by_prevalence_are_rejected() {
+        let file_path = TemporaryPath::new("ordered-by-count.txt");
+        std::fs::write(
+            &file_path,
+            "7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\r\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n",
+        )
+        .unwrap();
+
+        let error = SortedHashFile::from_file(&file_path).err().unwrap();
+        assert_eq!(true, error.to_string().contains("not ordered by hash"));
+    }
+}
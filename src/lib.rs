@@ -1,26 +1,36 @@
 use crypto::digest::Digest;
 use crypto::sha1::Sha1;
+use md4::{Digest as Md4Digest, Md4};
+use serde::de::Error as DeserializeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
 use std::fmt::Result as FmtResult;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
+pub mod digest;
 pub mod haveibeenpwned;
+pub mod secret;
 pub mod subcommands;
+#[cfg(test)]
+mod testing;
+
+pub use self::digest::{HashDigest, NtlmDigest, Sha1Digest};
+pub use self::secret::SecretString;
 
 #[derive(Debug, PartialEq)]
 pub enum HashLineFormatError {
     NoOccurrenceCountFound,
-    NotAValidSha1Hash,
+    NotAValidHash,
     MultipleHashLines,
 }
 
 impl Display for HashLineFormatError {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         match *self {
-            HashLineFormatError::NotAValidSha1Hash => write!(
+            HashLineFormatError::NotAValidHash => write!(
                 f,
-                "It seems that the supplied hash string is not a valid SHA-1 hash"
+                "It seems that the supplied hash string is neither a valid SHA-1 nor a valid NTLM hash"
             ),
             HashLineFormatError::MultipleHashLines => write!(
                 f,
@@ -34,11 +44,131 @@ impl Display for HashLineFormatError {
     }
 }
 
+/// The hash algorithms which are used for the password hash files provided by https://haveibeenpwned.com.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    /// The SHA-1 hash of the UTF-8 encoded password.
+    Sha1,
+    /// The NTLM hash (MD4 of the UTF-16LE encoded password) as used by Windows and Active Directory.
+    Ntlm,
+}
+
+impl HashAlgorithm {
+    /// Get the name of the algorithm as it is used on the command line, in the manifests and in
+    /// the results of the subcommands.
+    pub fn get_name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Ntlm => "ntlm",
+        }
+    }
+
+    /// Get the algorithm for the supplied name (`sha1` or `ntlm`, case insensitive).
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        match name.to_lowercase().as_str() {
+            "sha1" | "sha-1" => Some(HashAlgorithm::Sha1),
+            "ntlm" => Some(HashAlgorithm::Ntlm),
+            _ => None,
+        }
+    }
+
+    /// Get the algorithm which produces hexadecimal hashes of the supplied length.
+    pub fn from_hash_length(length: usize) -> Option<HashAlgorithm> {
+        match length {
+            40 => Some(HashAlgorithm::Sha1),
+            32 => Some(HashAlgorithm::Ntlm),
+            _ => None,
+        }
+    }
+
+    /// Get the number of hexadecimal characters of a hash created by this algorithm.
+    pub fn get_hash_length(self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 40,
+            HashAlgorithm::Ntlm => 32,
+        }
+    }
+
+    /// Get the hash of the supplied password.
+    pub fn hash_password(self, password: &str) -> HashDigest {
+        match self {
+            HashAlgorithm::Sha1 => {
+                let mut hasher = Sha1::new();
+                hasher.input_str(password);
+                let mut digest = [0u8; 20];
+                hasher.result(&mut digest);
+                HashDigest::Sha1(Sha1Digest(digest))
+            }
+            HashAlgorithm::Ntlm => {
+                let mut hasher = Md4::new();
+                for character in password.encode_utf16() {
+                    Md4Digest::update(&mut hasher, character.to_le_bytes());
+                }
+                let mut digest = [0u8; 16];
+                digest.copy_from_slice(&hasher.finalize());
+                HashDigest::Ntlm(NtlmDigest(digest))
+            }
+        }
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Ntlm => write!(f, "NTLM"),
+        }
+    }
+}
+
+impl Serialize for HashAlgorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.get_name())
+    }
+}
+
+impl<'de> Deserialize<'de> for HashAlgorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        HashAlgorithm::from_name(&name)
+            .ok_or_else(|| D::Error::unknown_variant(&name, &["sha1", "ntlm"]))
+    }
+}
+
+/// The case of the letters of a hexadecimal hash, as it was written in the password file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LetterCase {
+    /// All letters of the hash are upper case letters.
+    Upper,
+    /// All letters of the hash are lower case letters.
+    Lower,
+    /// The hash contains upper and lower case letters.
+    Mixed,
+    /// The hash does not contain any letter, so it can be used with both cases.
+    NoLetters,
+}
+
+impl LetterCase {
+    /// Get the case of the letters of the supplied hexadecimal hash.
+    pub fn from_hash(hash: &str) -> LetterCase {
+        let has_upper_case = hash.bytes().any(|character| character.is_ascii_uppercase());
+        let has_lower_case = hash.bytes().any(|character| character.is_ascii_lowercase());
+        match (has_upper_case, has_lower_case) {
+            (true, true) => LetterCase::Mixed,
+            (true, false) => LetterCase::Upper,
+            (false, true) => LetterCase::Lower,
+            (false, false) => LetterCase::NoLetters,
+        }
+    }
+}
+
 /// This struct is used to represent a single password hash entry.
+#[derive(Debug, Clone)]
 pub struct PasswordHashEntry {
-    hash: String,
+    hash: HashDigest,
     occurrences: u64,
     entry_size: u64,
+    letter_case: LetterCase,
 }
 
 impl PasswordHashEntry {
@@ -47,43 +177,84 @@ impl PasswordHashEntry {
     }
 
     pub fn get_prefix(&self) -> String {
-        let cloned_hash = self.hash.clone();
-        cloned_hash[..3].to_string()
+        self.hash.get_hex_prefix(3).unwrap_or_default()
     }
 
+    /// Get the (upper case) hexadecimal prefix of the hash with the supplied length.
     pub fn get_dynamic_prefix(&self, length: usize) -> Option<String> {
-        if length > self.hash.len() {
-            return None;
-        }
-        let cloned_hash = self.hash.clone();
-        Some(cloned_hash[..length].to_string())
+        self.hash.get_hex_prefix(length)
     }
 
     pub fn get_occurrences(&self) -> u64 {
         self.occurrences
     }
 
+    /// Get the (upper case) hexadecimal representation of the hash.
     pub fn get_hash(&self) -> String {
-        self.hash.clone()
+        self.hash.to_string()
+    }
+
+    /// Get the raw hash of the entry.
+    pub fn get_digest(&self) -> HashDigest {
+        self.hash
+    }
+
+    pub fn get_algorithm(&self) -> HashAlgorithm {
+        self.hash.get_algorithm()
+    }
+
+    /// Get the case of the letters of the hash, as it was written in the password file. Entries
+    /// which were created from a password use upper case letters, like the formatted hash.
+    pub fn get_letter_case(&self) -> LetterCase {
+        self.letter_case
     }
 
     pub fn get_line_to_write(&self) -> String {
         format!("{}:{}\n", self.hash, self.occurrences)
     }
 
-    pub fn from_password(password: &str) -> PasswordHashEntry {
-        // hash the input password
-        let mut hasher = Sha1::new();
-        hasher.input_str(password);
-        let hashed_password = hasher.result_str();
+    /// Create an entry for an already hashed password. The type of the hash is determined by its
+    /// length.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the supplied string is neither a hexadecimal SHA-1
+    /// nor a hexadecimal NTLM hash.
+    pub fn from_hash(hash: &str) -> Result<PasswordHashEntry, HashLineFormatError> {
+        Ok(PasswordHashEntry {
+            hash: HashDigest::from_str(hash)?,
+            occurrences: 0,
+            entry_size: 2 + hash.len() as u64,
+            letter_case: LetterCase::from_hash(hash),
+        })
+    }
 
-        // return the created object
+    /// Create an entry for the supplied raw hash.
+    pub fn from_digest(hash: HashDigest, occurrences: u64) -> PasswordHashEntry {
         PasswordHashEntry {
-            hash: hashed_password.clone(),
-            occurrences: 0,
-            entry_size: 2 + hashed_password.len() as u64,
+            entry_size: 2 + 2 * hash.as_bytes().len() as u64,
+            hash,
+            occurrences,
+            letter_case: LetterCase::Upper,
         }
     }
+
+    pub fn from_password(password: &str) -> PasswordHashEntry {
+        PasswordHashEntry::from_password_with_algorithm(password, HashAlgorithm::Sha1)
+    }
+
+    pub fn from_password_with_algorithm(
+        password: &str,
+        algorithm: HashAlgorithm,
+    ) -> PasswordHashEntry {
+        PasswordHashEntry::from_digest(algorithm.hash_password(password), 0)
+    }
+
+    /// Create an entry for a password which was entered by the user. The password is hashed
+    /// without copying it.
+    pub fn from_secret(password: &SecretString, algorithm: HashAlgorithm) -> PasswordHashEntry {
+        PasswordHashEntry::from_password_with_algorithm(password.as_str(), algorithm)
+    }
 }
 
 impl PartialEq for PasswordHashEntry {
@@ -92,9 +263,17 @@ impl PartialEq for PasswordHashEntry {
     }
 }
 
+impl Eq for PasswordHashEntry {}
+
 impl PartialOrd for PasswordHashEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.hash.partial_cmp(&other.hash)
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PasswordHashEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hash.cmp(&other.hash)
     }
 }
 
@@ -140,7 +319,7 @@ impl FromStr for PasswordHashEntry {
 
         // get the hash for the current entry
         let hash = match entry_splitted.next() {
-            Some(key_text) => key_text.to_string(),
+            Some(key_text) => key_text,
             None => return Err(HashLineFormatError::NoOccurrenceCountFound),
         };
 
@@ -153,16 +332,15 @@ impl FromStr for PasswordHashEntry {
             None => return Err(HashLineFormatError::NoOccurrenceCountFound),
         };
 
-        // a SHA-1 hash has to be 40 hexadecimal characters
-        if hash.len() != 40 {
-            return Err(HashLineFormatError::NotAValidSha1Hash);
-        }
+        // a SHA-1 hash has to be 40 and a NTLM hash 32 hexadecimal characters
+        let digest = HashDigest::from_str(hash)?;
 
         // return the created entry
         Ok(PasswordHashEntry {
-            hash,
+            hash: digest,
             occurrences,
             entry_size: input_str.len() as u64,
+            letter_case: LetterCase::from_hash(hash),
         })
     }
 }
@@ -182,8 +360,9 @@ mod tests {
         let instance = maybe_instance.unwrap();
 
         assert_eq!(43, instance.get_size_in_bytes());
-        assert_eq!(input_hash[..3].to_string(), instance.get_prefix());
-        assert_eq!(input_hash, instance.get_hash());
+        assert_eq!(input_hash[..3].to_uppercase(), instance.get_prefix());
+        assert_eq!(input_hash.to_uppercase(), instance.get_hash());
+        assert_eq!(LetterCase::Lower, instance.get_letter_case());
         assert_eq!(input_occurrences, instance.get_occurrences());
     }
 
@@ -208,7 +387,7 @@ mod tests {
         let maybe_instance = PasswordHashEntry::from_str(input_string.as_str());
         assert_eq!(true, maybe_instance.is_err());
         assert_eq!(
-            HashLineFormatError::NotAValidSha1Hash,
+            HashLineFormatError::NotAValidHash,
             maybe_instance.err().unwrap()
         );
     }
@@ -236,12 +415,77 @@ mod tests {
         assert_eq!(0, instance.get_occurrences());
         assert_eq!(42, instance.get_size_in_bytes());
         assert_eq!(
-            "fdc625010c4beb998e590924df39b7e59298612d",
+            "FDC625010C4BEB998E590924DF39B7E59298612D",
             instance.get_hash()
         );
-        assert_eq!("fdc", instance.get_prefix());
-        assert_eq!("fdc", instance.get_dynamic_prefix(3).unwrap());
-        assert_eq!("fdc62", instance.get_dynamic_prefix(5).unwrap());
+        assert_eq!("FDC", instance.get_prefix());
+        assert_eq!("FDC", instance.get_dynamic_prefix(3).unwrap());
+        assert_eq!("FDC62", instance.get_dynamic_prefix(5).unwrap());
+    }
+
+    #[test]
+    fn creating_a_ntlm_password_hash_entry_from_a_plaintext_password_works() {
+        let instance =
+            PasswordHashEntry::from_password_with_algorithm("password", HashAlgorithm::Ntlm);
+        assert_eq!(HashAlgorithm::Ntlm, instance.get_algorithm());
+        assert_eq!("8846F7EAEE8FB117AD06BDD830B7586C", instance.get_hash());
+        assert_eq!("884", instance.get_prefix());
+    }
+
+    #[test]
+    fn creating_a_ntlm_password_hash_entry_from_valid_input_works_as_intended() {
+        let maybe_instance = PasswordHashEntry::from_str("8846F7EAEE8FB117AD06BDD830B7586C:42");
+        assert_eq!(false, maybe_instance.is_err());
+        let instance = maybe_instance.unwrap();
+
+        assert_eq!(HashAlgorithm::Ntlm, instance.get_algorithm());
+        assert_eq!(42, instance.get_occurrences());
+    }
+
+    #[test]
+    fn creating_a_password_hash_entry_from_a_hash_works() {
+        let instance = PasswordHashEntry::from_hash("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8");
+        assert_eq!(false, instance.is_err());
+        assert_eq!(HashAlgorithm::Sha1, instance.unwrap().get_algorithm());
+
+        let instance = PasswordHashEntry::from_hash("8846F7EAEE8FB117AD06BDD830B7586C");
+        assert_eq!(false, instance.is_err());
+        assert_eq!(HashAlgorithm::Ntlm, instance.unwrap().get_algorithm());
+
+        let instance = PasswordHashEntry::from_hash("5baa61e4c9b93f3f0682250b6cf8331b7ee68fdx");
+        assert_eq!(HashLineFormatError::NotAValidHash, instance.err().unwrap());
+    }
+
+    #[test]
+    fn hash_algorithms_can_be_determined_by_name_and_hash_length() {
+        assert_eq!(Some(HashAlgorithm::Sha1), HashAlgorithm::from_name("SHA1"));
+        assert_eq!(Some(HashAlgorithm::Ntlm), HashAlgorithm::from_name("ntlm"));
+        assert_eq!(None, HashAlgorithm::from_name("md5"));
+        for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Ntlm] {
+            assert_eq!(
+                Some(algorithm),
+                HashAlgorithm::from_name(algorithm.get_name())
+            );
+            let serialized = serde_json::to_string(&algorithm).unwrap();
+            assert_eq!(format!("\"{}\"", algorithm.get_name()), serialized);
+            assert_eq!(
+                algorithm,
+                serde_json::from_str::<HashAlgorithm>(&serialized).unwrap()
+            );
+        }
+        assert_eq!(
+            true,
+            serde_json::from_str::<HashAlgorithm>("\"md5\"").is_err()
+        );
+        assert_eq!(
+            Some(HashAlgorithm::Sha1),
+            HashAlgorithm::from_hash_length(40)
+        );
+        assert_eq!(
+            Some(HashAlgorithm::Ntlm),
+            HashAlgorithm::from_hash_length(32)
+        );
+        assert_eq!(None, HashAlgorithm::from_hash_length(64));
     }
 
     #[test]
@@ -259,23 +503,17 @@ mod tests {
         let exact_prefix = instance.get_dynamic_prefix(40);
         assert_eq!(true, exact_prefix.is_some());
         assert_eq!(
-            "fdc625010c4beb998e590924df39b7e59298612d",
+            "FDC625010C4BEB998E590924DF39B7E59298612D",
             exact_prefix.unwrap()
         );
     }
 
     #[test]
     fn ensure_hash_comparison_works_as_intended() {
-        let hash_one = PasswordHashEntry {
-            hash: "000000".to_string(),
-            entry_size: 0,
-            occurrences: 0,
-        };
-        let hash_two = PasswordHashEntry {
-            hash: "00000F".to_string(),
-            entry_size: 0,
-            occurrences: 0,
-        };
+        let hash_one =
+            PasswordHashEntry::from_hash("0000000000000000000000000000000000000000").unwrap();
+        let hash_two =
+            PasswordHashEntry::from_hash("00000f0000000000000000000000000000000000").unwrap();
 
         assert_eq!(true, hash_one < hash_two);
         assert_eq!(true, hash_one <= hash_two);
@@ -284,4 +522,21 @@ mod tests {
         assert_eq!(false, hash_one > hash_two);
         assert_eq!(false, hash_one >= hash_two);
     }
+
+    #[test]
+    fn hashes_with_a_different_case_are_the_same() {
+        let upper_case =
+            PasswordHashEntry::from_hash("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8").unwrap();
+        let lower_case =
+            PasswordHashEntry::from_hash("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8").unwrap();
+
+        assert_eq!(true, upper_case == lower_case);
+        assert_eq!(Some(Ordering::Equal), upper_case.partial_cmp(&lower_case));
+        assert_eq!(LetterCase::Upper, upper_case.get_letter_case());
+        assert_eq!(LetterCase::Lower, lower_case.get_letter_case());
+        assert_eq!(
+            true,
+            PasswordHashEntry::from_password("password") == lower_case
+        );
+    }
 }
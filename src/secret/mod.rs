@@ -0,0 +1,142 @@
+use log::debug;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use zeroize::Zeroize;
+
+/// Lock the supplied memory, so it is not written to the swap space. Returns `false` if the
+/// operating system refused it (e.g. because the limit of locked memory was reached).
+#[cfg(unix)]
+fn lock_memory(pointer: *const u8, length: usize) -> bool {
+    length > 0 && unsafe { libc::mlock(pointer as *const libc::c_void, length) } == 0
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_pointer: *const u8, _length: usize) -> bool {
+    false
+}
+
+/// Unlock memory which was locked by `lock_memory`.
+#[cfg(unix)]
+fn unlock_memory(pointer: *const u8, length: usize) {
+    unsafe {
+        libc::munlock(pointer as *const libc::c_void, length);
+    }
+}
+
+#[cfg(not(unix))]
+fn unlock_memory(_pointer: *const u8, _length: usize) {}
+
+/// This class can be used to keep a plaintext password in memory until it is hashed. The
+/// password is overwritten with zeros as soon as the instance is dropped and its memory is locked
+/// (if the operating system allows it), so it does not end up in the swap space.
+///
+/// The password is never copied by this class and it can not be changed, so there are no
+/// reallocations which could leave copies of it behind.
+///
+/// # Example
+/// ```
+/// use pwned_rs::{HashAlgorithm, PasswordHashEntry, SecretString};
+///
+/// let password = SecretString::new("password".to_string());
+/// let entry = PasswordHashEntry::from_secret(&password, HashAlgorithm::Sha1);
+/// drop(password);
+///
+/// assert_eq!("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", entry.get_hash());
+/// ```
+pub struct SecretString {
+    value: String,
+    locked: bool,
+}
+
+impl SecretString {
+    /// Take the ownership of the supplied password and lock the memory it is stored in.
+    pub fn new(value: String) -> SecretString {
+        let locked = lock_memory(value.as_ptr(), value.capacity());
+        if !locked {
+            debug!(
+                "Could not lock the memory of the password, it might be written to the swap space"
+            );
+        }
+        SecretString { value, locked }
+    }
+
+    /// Get the plaintext password.
+    pub fn as_str(&self) -> &str {
+        self.value.as_str()
+    }
+
+    /// Check if the memory of the password is locked.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Overwrite the whole buffer of the password (including its unused capacity) with zeros and
+    /// empty it.
+    pub fn clear(&mut self) {
+        self.value.zeroize();
+    }
+
+    /// Clear the password and unlock its memory, right before it is released.
+    fn wipe(&mut self) {
+        self.clear();
+        if self.locked {
+            unlock_memory(self.value.as_ptr(), self.value.capacity());
+            self.locked = false;
+        }
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString::new(value)
+    }
+}
+
+impl Debug for SecretString {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "SecretString(***)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_buffer_is_cleared() {
+        let mut value = String::with_capacity(32);
+        value.push_str("password");
+        let mut secret = SecretString::new(value);
+        assert_eq!("password", secret.as_str());
+        assert_eq!("SecretString(***)", format!("{:?}", secret));
+
+        let pointer = secret.value.as_ptr();
+        let capacity = secret.value.capacity();
+        secret.clear();
+        let buffer = unsafe { std::slice::from_raw_parts(pointer, capacity) };
+        assert_eq!(32, buffer.len());
+        assert_eq!(true, buffer.iter().all(|byte| *byte == 0));
+        assert_eq!("", secret.as_str());
+    }
+
+    #[test]
+    fn the_buffer_is_cleared_before_the_password_is_dropped() {
+        let mut value = String::with_capacity(32);
+        value.push_str("password");
+        let mut secret = SecretString::new(value);
+
+        // the same is done by dropping the password, right before its memory is released
+        let pointer = secret.value.as_ptr();
+        let capacity = secret.value.capacity();
+        secret.wipe();
+        let buffer = unsafe { std::slice::from_raw_parts(pointer, capacity) };
+        assert_eq!(32, buffer.len());
+        assert_eq!(true, buffer.iter().all(|byte| *byte == 0));
+        assert_eq!(false, secret.is_locked());
+    }
+}
@@ -0,0 +1,720 @@
+use crate::haveibeenpwned::external_sort::{ExternalSorter, DEFAULT_MEMORY_LIMIT};
+use crate::haveibeenpwned::optimized::{OptimizedDatabase, ShardReader};
+use crate::haveibeenpwned::{CreateInstanceError, DatabaseIterator, ParseError, SortedHashFile};
+use crate::subcommands::exitcode::SubcommandError;
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
+use clap::ArgMatches;
+use log::{debug, error, warn};
+use memmap2::MmapMut;
+use std::convert::TryInto;
+use std::fs::{remove_dir_all, File, OpenOptions};
+use std::io::{stdin, BufRead, BufReader, Error};
+use std::path::Path;
+use std::process::exit;
+use zeroize::{Zeroize, Zeroizing};
+
+/// If more entries than this have to be audited against the sorted password file, the file is
+/// scanned once from the beginning to the end instead of doing a binary search for every entry.
+const SEQUENTIAL_SCAN_THRESHOLD: u64 = 100_000;
+
+/// The size of the result of a single line: the length of the hash, the hash itself, a flag if
+/// it was found and the number of its occurrences.
+const RESULT_RECORD_SIZE: usize = 1 + 20 + 1 + 8;
+
+/// The initial capacity of the buffer for a line of the input. Longer lines cause a reallocation
+/// which can leave a copy of the line behind, so it is big enough for usual passwords.
+const LINE_BUFFER_SIZE: usize = 1024;
+
+/// The database in which the passwords should be looked up.
+enum AuditDatabase<'a> {
+    SortedFile(&'a str, SortedHashFile),
+    OptimizedFolder(Box<OptimizedDatabase>),
+}
+
+/// A single password (or password hash) which should be audited.
+struct AuditEntry {
+    line_number: usize,
+    password_entry: PasswordHashEntry,
+}
+
+/// The passwords or password hashes (one per line) which should be audited, in the order of the
+/// input. Every line is read into the same buffer, which is overwritten with zeros before the next
+/// line is read (and when the input is dropped), so the plaintext passwords do not stay in memory.
+struct AuditInput<R: BufRead> {
+    input: R,
+    line: Zeroizing<String>,
+    hash_algorithm: HashAlgorithm,
+    input_contains_hashes: bool,
+    line_number: usize,
+    invalid_lines: usize,
+}
+
+impl<R: BufRead> AuditInput<R> {
+    fn new(input: R, hash_algorithm: HashAlgorithm, input_contains_hashes: bool) -> AuditInput<R> {
+        AuditInput {
+            input,
+            line: Zeroizing::new(String::with_capacity(LINE_BUFFER_SIZE)),
+            hash_algorithm,
+            input_contains_hashes,
+            line_number: 0,
+            invalid_lines: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for AuditInput<R> {
+    type Item = Result<AuditEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.zeroize();
+            match self.input.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(error) => return Some(Err(error)),
+            }
+            self.line_number += 1;
+
+            // skip all empty lines, they are not passwords we want to check
+            let line = self.line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                continue;
+            }
+
+            // get the hash of the entry we want to look up
+            let password_entry = if self.input_contains_hashes {
+                match PasswordHashEntry::from_hash(line.trim()) {
+                    Ok(entry) if entry.get_algorithm() == self.hash_algorithm => entry,
+                    _ => {
+                        warn!(
+                            "Line {} does not contain a valid {} hash, skipping it.",
+                            self.line_number, self.hash_algorithm
+                        );
+                        self.invalid_lines += 1;
+                        continue;
+                    }
+                }
+            } else {
+                PasswordHashEntry::from_password_with_algorithm(line, self.hash_algorithm)
+            };
+
+            return Some(Ok(AuditEntry {
+                line_number: self.line_number,
+                password_entry,
+            }));
+        }
+    }
+}
+
+/// The results of the audit. The entries are looked up in the order of their hashes, but they are
+/// reported in the order of the input, so the result of every line is stored at the position of
+/// the line in a (memory mapped) temporary file.
+struct AuditResults {
+    records: MmapMut,
+}
+
+impl AuditResults {
+    fn create(path_to_file: &Path, number_of_lines: usize) -> Result<AuditResults, Error> {
+        let file_handle = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path_to_file)?;
+
+        // an empty file can not be mapped, so there is always space for at least one line
+        file_handle.set_len((number_of_lines.max(1) * RESULT_RECORD_SIZE) as u64)?;
+        Ok(AuditResults {
+            records: unsafe { MmapMut::map_mut(&file_handle)? },
+        })
+    }
+
+    fn get_record(&mut self, line_number: usize) -> &mut [u8] {
+        let offset = (line_number - 1) * RESULT_RECORD_SIZE;
+        &mut self.records[offset..offset + RESULT_RECORD_SIZE]
+    }
+
+    fn set_result(&mut self, audit_entry: &AuditEntry, occurrences: Option<u64>) {
+        let digest = audit_entry.password_entry.get_digest();
+        let hash = digest.as_bytes();
+        let record = self.get_record(audit_entry.line_number);
+        record[0] = hash.len() as u8;
+        record[1..1 + hash.len()].copy_from_slice(hash);
+        record[21] = occurrences.is_some() as u8;
+        record[22..].copy_from_slice(&occurrences.unwrap_or(0).to_le_bytes());
+    }
+
+    /// Get the hash of the supplied line and the number of its occurrences (if it was found). If
+    /// the line was not audited (e.g. an empty line), `None` is returned.
+    fn get_result(&mut self, line_number: usize) -> Option<(HashDigest, Option<u64>)> {
+        let record = self.get_record(line_number);
+        let hash = HashDigest::from_bytes(&record[1..1 + record[0] as usize])?;
+        let occurrences = u64::from_le_bytes(record[22..].try_into().ok()?);
+        Some((hash, Some(occurrences).filter(|_| record[21] == 1)))
+    }
+}
+
+/// Read the entries of the input from the file to which they were written ordered by their hash.
+/// Instead of the number of occurrences, the file contains the line number of every entry.
+fn read_sorted_input(
+    path_to_file: &Path,
+) -> Result<impl Iterator<Item = Result<AuditEntry, ParseError>>, CreateInstanceError> {
+    Ok(
+        DatabaseIterator::from_file(&path_to_file.to_string_lossy())?.map(|maybe_entry| {
+            maybe_entry.map(|password_entry| AuditEntry {
+                line_number: password_entry.get_occurrences() as usize,
+                password_entry,
+            })
+        }),
+    )
+}
+
+/// Report that the sorted entries of the input could not be read.
+fn get_sorted_input_error(error: ParseError) -> SubcommandError {
+    error!("Could not read the sorted entries of the input. {}", error);
+    SubcommandError::Output
+}
+
+/// Look up the sorted entries by reading the entries of the sorted password file once. Malformed
+/// lines of the password file are skipped, just like by the binary search in the file.
+fn scan_sorted_entries<D, A, F>(
+    database_entries: D,
+    audit_entries: A,
+    mut report_result: F,
+) -> Result<(), SubcommandError>
+where
+    D: IntoIterator<Item = Result<PasswordHashEntry, ParseError>>,
+    A: IntoIterator<Item = Result<AuditEntry, ParseError>>,
+    F: FnMut(&AuditEntry, Option<u64>),
+{
+    let mut database_entries = database_entries.into_iter().fuse();
+    let mut database_entry: Option<PasswordHashEntry> = None;
+    for maybe_audit_entry in audit_entries {
+        let audit_entry = maybe_audit_entry.map_err(get_sorted_input_error)?;
+        let hash = audit_entry.password_entry.get_digest();
+
+        // skip all entries of the database which are smaller than the entry we are looking for
+        while !matches!(&database_entry, Some(entry) if entry.get_digest() >= hash) {
+            database_entry = match database_entries.next() {
+                Some(Ok(entry)) => Some(entry),
+                Some(Err(error)) if error.is_fatal() => {
+                    error!("Could not read the password file. {}", error);
+                    return Err(SubcommandError::Database);
+                }
+                Some(Err(error)) => {
+                    warn!("Skipping a malformed entry of the password file. {}", error);
+                    continue;
+                }
+                None => None,
+            };
+            if database_entry.is_none() {
+                break;
+            }
+        }
+
+        // the same hash can be in the input multiple times, so the database entry is kept
+        let occurrences = match &database_entry {
+            Some(entry) if entry.get_digest() == hash => Some(entry.get_occurrences()),
+            _ => None,
+        };
+        report_result(&audit_entry, occurrences);
+    }
+    Ok(())
+}
+
+/// Look up the sorted entries by scanning the whole sorted password file once.
+fn scan_sorted_file<A, F>(
+    password_database: &str,
+    audit_entries: A,
+    report_result: F,
+) -> Result<(), SubcommandError>
+where
+    A: IntoIterator<Item = Result<AuditEntry, ParseError>>,
+    F: FnMut(&AuditEntry, Option<u64>),
+{
+    let parser = match DatabaseIterator::from_file(password_database) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            return Err(SubcommandError::from(&error));
+        }
+    };
+    scan_sorted_entries(parser, audit_entries, report_result)
+}
+
+/// Look up the entries by doing a binary search in the sorted password file for every entry.
+fn search_sorted_file<A, F>(
+    lookup: &SortedHashFile,
+    audit_entries: A,
+    mut report_result: F,
+) -> Result<(), SubcommandError>
+where
+    A: IntoIterator<Item = Result<AuditEntry, ParseError>>,
+    F: FnMut(&AuditEntry, Option<u64>),
+{
+    for maybe_audit_entry in audit_entries {
+        let audit_entry = maybe_audit_entry.map_err(get_sorted_input_error)?;
+        report_result(
+            &audit_entry,
+            lookup.get_password_count(&audit_entry.password_entry),
+        );
+    }
+    Ok(())
+}
+
+/// Look up the sorted entries in the optimized database. Since the entries are sorted, every
+/// file of the database has to be read just once. If a file can not be read, the audit is stopped
+/// instead of reporting its entries as not found.
+fn lookup_in_optimized_folder<A, F>(
+    optimized_database: &OptimizedDatabase,
+    audit_entries: A,
+    mut report_result: F,
+) -> Result<(), SubcommandError>
+where
+    A: IntoIterator<Item = Result<AuditEntry, ParseError>>,
+    F: FnMut(&AuditEntry, Option<u64>),
+{
+    let mut current_prefix = String::new();
+    let mut current_reader: Option<ShardReader> = None;
+
+    for maybe_audit_entry in audit_entries {
+        let audit_entry = maybe_audit_entry.map_err(get_sorted_input_error)?;
+        let prefix = match optimized_database.get_prefix(&audit_entry.password_entry) {
+            Some(prefix) => prefix,
+            None => {
+                report_result(&audit_entry, None);
+                continue;
+            }
+        };
+        if prefix != current_prefix {
+            current_reader = match optimized_database.open_file(&prefix) {
+                Ok(reader) => reader,
+                Err(error) => {
+                    error!(
+                        "Could not open the database file for the prefix {}. The error was: {}",
+                        prefix, error
+                    );
+                    return Err(SubcommandError::from(&error));
+                }
+            };
+            current_prefix = prefix;
+        }
+
+        let occurrences = current_reader
+            .as_ref()
+            .and_then(|reader| reader.get_password_count(&audit_entry.password_entry));
+        report_result(&audit_entry, occurrences);
+    }
+    Ok(())
+}
+
+/// Remove the temporary files of the audit and exit with the exit code of the supplied error.
+fn abort_audit(temporary_folder: &Path, error: SubcommandError) -> ! {
+    let _ = remove_dir_all(temporary_folder);
+    exit(error.get_exit_code());
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the input from which we should read the passwords (or the password hashes)
+    let input: Box<dyn BufRead> = match matches.value_of("input") {
+        Some(path) if path != "-" => match OpenOptions::new().read(true).open(path) {
+            Ok(file_handle) => Box::new(BufReader::new(file_handle)),
+            Err(error) => {
+                error!(
+                    "Could not open {} for reading the passwords. The error was: {}",
+                    path, error
+                );
+                exit(-1);
+            }
+        },
+        _ => Box::new(BufReader::new(stdin())),
+    };
+    let input_contains_hashes = matches.is_present("hashes");
+
+    // open the password database in which we should look up the passwords
+    let audit_database = match (
+        matches.value_of("password-database"),
+        matches.value_of("optimized-db-folder"),
+    ) {
+        (Some(path), _) => match SortedHashFile::from_file(Path::new(path)) {
+            Ok(lookup) => AuditDatabase::SortedFile(path, lookup),
+            Err(error) => {
+                error!(
+                    "Could not open {} for looking up passwords. The error was: {}",
+                    path, error
+                );
+                exit(-2);
+            }
+        },
+        (None, Some(folder)) => match OptimizedDatabase::from_folder(Path::new(folder)) {
+            Ok(database) => AuditDatabase::OptimizedFolder(Box::new(database)),
+            Err(error) => {
+                error!(
+                    "Could not open {} for looking up passwords. The error was: {}",
+                    folder, error
+                );
+                exit(-2);
+            }
+        },
+        (None, None) => {
+            error!("It seems that neither the password file nor the folder of an optimized database was provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+
+    // determine which hash algorithm has to be used for the passwords
+    let hash_algorithm = match HashAlgorithm::from_name(
+        matches.value_of("hash-type").unwrap_or("auto"),
+    ) {
+        Some(algorithm) => algorithm,
+        None => match &audit_database {
+            AuditDatabase::SortedFile(_, lookup) => match lookup.get_hash_algorithm() {
+                Some(algorithm) => algorithm,
+                None => {
+                    error!("Could not detect the type of the hashes in the password file, please select it manually.");
+                    exit(-3);
+                }
+            },
+            AuditDatabase::OptimizedFolder(database) => {
+                database.get_hash_algorithm().unwrap_or(HashAlgorithm::Sha1)
+            }
+        },
+    };
+
+    // sort all entries of the input (remembering their line numbers), so they can be looked up in
+    // the order of the database
+    let temporary_folder =
+        std::env::temp_dir().join(format!("pwned-rs-audit-{}", std::process::id()));
+    let mut sorter = match ExternalSorter::new(&temporary_folder.join("runs"), DEFAULT_MEMORY_LIMIT)
+    {
+        Ok(sorter) => sorter,
+        Err(error) => {
+            error!(
+                "Could not create the folder for sorting the input. The error was: {}",
+                error
+            );
+            abort_audit(&temporary_folder, SubcommandError::Output);
+        }
+    };
+    let mut audit_input = AuditInput::new(input, hash_algorithm, input_contains_hashes);
+    while let Some(maybe_audit_entry) = audit_input.next() {
+        let audit_entry = match maybe_audit_entry {
+            Ok(audit_entry) => audit_entry,
+            Err(error) => {
+                error!(
+                    "Could not read line {} of the input. The error was: {}",
+                    audit_input.line_number + 1,
+                    error
+                );
+                abort_audit(&temporary_folder, SubcommandError::Usage);
+            }
+        };
+        let sort_entry = PasswordHashEntry::from_digest(
+            audit_entry.password_entry.get_digest(),
+            audit_entry.line_number as u64,
+        );
+        if let Err(error) = sorter.add_entry(&sort_entry) {
+            error!(
+                "Could not write the sorted entries to a temporary file. The error was: {}",
+                error
+            );
+            abort_audit(&temporary_folder, SubcommandError::Output);
+        }
+    }
+    let sorted_input_path = temporary_folder.join("sorted-input.txt");
+    let number_of_entries = match File::create(&sorted_input_path)
+        .and_then(|sorted_input| sorter.write_sorted(sorted_input))
+    {
+        Ok(number_of_entries) => number_of_entries,
+        Err(error) => {
+            error!(
+                "Could not write the sorted entries of the input. The error was: {}",
+                error
+            );
+            abort_audit(&temporary_folder, SubcommandError::Output);
+        }
+    };
+    debug!("Read {} entries which should be audited", number_of_entries);
+
+    // look up all entries in the selected database
+    let number_of_lines = audit_input.line_number;
+    let mut audit_results =
+        match AuditResults::create(&temporary_folder.join("results.bin"), number_of_lines) {
+            Ok(audit_results) => audit_results,
+            Err(error) => {
+                error!(
+                    "Could not create the temporary file for the results. The error was: {}",
+                    error
+                );
+                abort_audit(&temporary_folder, SubcommandError::Output);
+            }
+        };
+    if number_of_entries > 0 {
+        let sorted_entries = match read_sorted_input(&sorted_input_path) {
+            Ok(sorted_entries) => sorted_entries,
+            Err(error) => {
+                error!(
+                    "Could not read the sorted entries of the input. The error was: {}",
+                    error
+                );
+                abort_audit(&temporary_folder, SubcommandError::Output);
+            }
+        };
+        let report_result = |audit_entry: &AuditEntry, occurrences: Option<u64>| {
+            audit_results.set_result(audit_entry, occurrences)
+        };
+        let lookup_result = match &audit_database {
+            AuditDatabase::SortedFile(path, _) if number_of_entries > SEQUENTIAL_SCAN_THRESHOLD => {
+                debug!("Scanning the whole password file once for all entries");
+                scan_sorted_file(path, sorted_entries, report_result)
+            }
+            AuditDatabase::SortedFile(_, lookup) => {
+                search_sorted_file(lookup, sorted_entries, report_result)
+            }
+            AuditDatabase::OptimizedFolder(database) => {
+                lookup_in_optimized_folder(database, sorted_entries, report_result)
+            }
+        };
+        if let Err(error) = lookup_result {
+            abort_audit(&temporary_folder, error);
+        }
+    }
+
+    // report the results in the order of the input
+    let mut audited_entries = 0;
+    let mut found_entries = 0;
+    for line_number in 1..=number_of_lines {
+        match audit_results.get_result(line_number) {
+            Some((hash, Some(count))) => {
+                found_entries += 1;
+                println!(
+                    "Line {} ({}): found {} times in password breaches",
+                    line_number, hash, count
+                );
+            }
+            Some((hash, None)) => println!("Line {} ({}): not found", line_number, hash),
+            None => continue,
+        }
+        audited_entries += 1;
+    }
+    drop(audit_results);
+    if let Err(error) = remove_dir_all(&temporary_folder) {
+        warn!(
+            "Could not remove the temporary folder {}. The error was: {}",
+            temporary_folder.display(),
+            error
+        );
+    }
+
+    println!(
+        "Audited {} entries: {} found in password breaches, {} not found, {} invalid lines skipped",
+        audited_entries,
+        found_entries,
+        audited_entries - found_entries,
+        audit_input.invalid_lines
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+
+    fn get_database_entries(lines: &[(&str, u64)]) -> Vec<Result<PasswordHashEntry, ParseError>> {
+        lines
+            .iter()
+            .map(|(hash, occurrences)| {
+                Ok(PasswordHashEntry::from_digest(
+                    hash.parse().unwrap(),
+                    *occurrences,
+                ))
+            })
+            .collect()
+    }
+
+    fn read_sorted_entries(input: &str) -> Vec<Result<AuditEntry, ParseError>> {
+        let mut audit_entries: Vec<AuditEntry> =
+            AuditInput::new(input.as_bytes(), HashAlgorithm::Sha1, true)
+                .map(|audit_entry| audit_entry.unwrap())
+                .collect();
+        audit_entries.sort_by_key(|audit_entry| audit_entry.password_entry.get_digest());
+        audit_entries.into_iter().map(Ok).collect()
+    }
+
+    fn get_occurrences<F>(lookup: F) -> Vec<Option<u64>>
+    where
+        F: FnOnce(&mut dyn FnMut(&AuditEntry, Option<u64>)) -> Result<(), SubcommandError>,
+    {
+        let mut results = Vec::new();
+        lookup(&mut |audit_entry, occurrences| {
+            results.push((audit_entry.line_number, occurrences))
+        })
+        .unwrap();
+        results.sort();
+        results
+            .into_iter()
+            .map(|(_, occurrences)| occurrences)
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_and_mixed_case_hashes_are_found() {
+        let occurrences = get_occurrences(|report_result| {
+            scan_sorted_entries(
+                get_database_entries(&[
+                    ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+                    ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+                    ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 24230577),
+                ]),
+                read_sorted_entries(
+                    "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8\n\
+                     7C4A8D09CA3762AF61E59520943DC26494F8941B\n\
+                     5BAA61E4c9b93f3f0682250B6CF8331B7EE68FD8\r\n\
+                     5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD9\n",
+                ),
+                report_result,
+            )
+        });
+        assert_eq!(
+            vec![Some(3861493), Some(24230577), Some(3861493), None],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn entries_past_the_end_of_the_database_are_not_found() {
+        let occurrences = get_occurrences(|report_result| {
+            scan_sorted_entries(
+                get_database_entries(&[
+                    ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+                    ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 24230577),
+                ]),
+                read_sorted_entries(
+                    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF\n\
+                     7C4A8D09CA3762AF61E59520943DC26494F8941B\n\
+                     7C4A8D09CA3762AF61E59520943DC26494F8941C\n",
+                ),
+                report_result,
+            )
+        });
+        assert_eq!(vec![None, Some(24230577), None], occurrences);
+    }
+
+    #[test]
+    fn malformed_database_lines_are_skipped_by_every_lookup() {
+        let path_to_file = TemporaryPath::new("audit-malformed.txt");
+        std::fs::write(
+            &path_to_file,
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n\
+             not a hash\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\n\
+             7C4A8D09CA3762AF61E59520943DC26494F8941B:many\n\
+             B1B3773A05C0ED0176787A4F1574FF0075F7521E:3\n",
+        )
+        .unwrap();
+        let input = "B1B3773A05C0ED0176787A4F1574FF0075F7521E\n\
+                     5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8\n\
+                     7C4A8D09CA3762AF61E59520943DC26494F8941B\n";
+
+        let scanned_occurrences = get_occurrences(|report_result| {
+            scan_sorted_file(
+                path_to_file.to_str().unwrap(),
+                read_sorted_entries(input),
+                report_result,
+            )
+        });
+        let lookup = SortedHashFile::from_file(&path_to_file).unwrap();
+        let searched_occurrences = get_occurrences(|report_result| {
+            search_sorted_file(&lookup, read_sorted_entries(input), report_result)
+        });
+
+        assert_eq!(vec![Some(3), Some(3861493), None], scanned_occurrences);
+        assert_eq!(scanned_occurrences, searched_occurrences);
+    }
+
+    #[test]
+    fn unreadable_database_files_stop_the_audit() {
+        let folder = TemporaryPath::new_folder("audit-unreadable-files");
+        std::fs::write(folder.join("5BA.bin"), "not a binary database").unwrap();
+        let optimized_database = OptimizedDatabase::from_folder(&folder).unwrap();
+
+        let mut reported_entries = 0;
+        let result = lookup_in_optimized_folder(
+            &optimized_database,
+            read_sorted_entries("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8\n"),
+            |_, _| reported_entries += 1,
+        );
+
+        assert_eq!(Err(SubcommandError::CorruptData), result);
+        assert_eq!(0, reported_entries);
+    }
+
+    #[test]
+    fn the_results_are_stored_by_line_number() {
+        let path_to_file = TemporaryPath::new("audit-results.bin");
+        let mut audit_results = AuditResults::create(&path_to_file, 3).unwrap();
+        for (line_number, occurrences) in [(3, None), (1, Some(3861493))] {
+            audit_results.set_result(
+                &AuditEntry {
+                    line_number,
+                    password_entry: PasswordHashEntry::from_password("password"),
+                },
+                occurrences,
+            );
+        }
+
+        let hash = PasswordHashEntry::from_password("password").get_digest();
+        assert_eq!(Some((hash, Some(3861493))), audit_results.get_result(1));
+        assert_eq!(None, audit_results.get_result(2));
+        assert_eq!(Some((hash, None)), audit_results.get_result(3));
+        drop(audit_results);
+    }
+
+    #[test]
+    fn empty_lines_of_the_input_are_skipped() {
+        let mut audit_input = AuditInput::new(
+            "password\n\n123456\nletmein\r\nqwerty\nabc123\n".as_bytes(),
+            HashAlgorithm::Sha1,
+            false,
+        );
+        let line_numbers: Vec<usize> = audit_input
+            .by_ref()
+            .map(|audit_entry| audit_entry.unwrap().line_number)
+            .collect();
+        assert_eq!(vec![1, 3, 4, 5, 6], line_numbers);
+        assert_eq!(6, audit_input.line_number);
+        assert_eq!(0, audit_input.invalid_lines);
+    }
+
+    #[test]
+    fn the_buffer_of_the_lines_is_cleared() {
+        let mut audit_input = AuditInput::new("password\n".as_bytes(), HashAlgorithm::Sha1, false);
+        assert_eq!(1, audit_input.by_ref().count());
+
+        let buffer = unsafe {
+            std::slice::from_raw_parts(audit_input.line.as_ptr(), audit_input.line.capacity())
+        };
+        assert_eq!(LINE_BUFFER_SIZE, buffer.len());
+        assert_eq!(true, buffer.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn invalid_hashes_are_skipped() {
+        let mut audit_input = AuditInput::new(
+            "deadbeef\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8\n8846F7EAEE8FB117AD06BDD830B7586C\n"
+                .as_bytes(),
+            HashAlgorithm::Sha1,
+            true,
+        );
+        let audit_entries: Vec<AuditEntry> = audit_input
+            .by_ref()
+            .map(|audit_entry| audit_entry.unwrap())
+            .collect();
+        assert_eq!(1, audit_entries.len());
+        assert_eq!(2, audit_entries[0].line_number);
+        assert_eq!(2, audit_input.invalid_lines);
+    }
+}
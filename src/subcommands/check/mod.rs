@@ -0,0 +1,283 @@
+use crate::haveibeenpwned::DatabaseIterator;
+use crate::subcommands::exitcode::SubcommandError;
+use crate::{HashAlgorithm, HashDigest, LetterCase, PasswordHashEntry};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, warn};
+
+/// The number of problems of every kind which are reported with their line number.
+const MAXIMUM_REPORTED_PROBLEMS: u64 = 10;
+
+/// A problem of an entry in relation to the entry in front of it.
+#[derive(Debug, PartialEq)]
+enum OrderProblem {
+    /// The hash is lower than the hash of the previous entry.
+    OutOfOrder(String),
+    /// The hash is the same as the hash of the previous entry.
+    Duplicate,
+}
+
+/// The statistics (and the problems) of all entries of the password file which were checked so
+/// far.
+#[derive(Default)]
+struct FileReport {
+    entries: u64,
+    sha1_entries: u64,
+    ntlm_entries: u64,
+    upper_case_entries: u64,
+    lower_case_entries: u64,
+    mixed_case_entries: u64,
+    out_of_order_entries: u64,
+    duplicate_entries: u64,
+    total_occurrences: u64,
+    most_common_entry: Option<(String, u64)>,
+    last_hash: Option<HashDigest>,
+}
+
+impl FileReport {
+    /// Add the supplied entry to the statistics and check it against the previous entry.
+    fn add_entry(&mut self, entry: &PasswordHashEntry) -> Option<OrderProblem> {
+        self.entries += 1;
+        match entry.get_algorithm() {
+            HashAlgorithm::Sha1 => self.sha1_entries += 1,
+            HashAlgorithm::Ntlm => self.ntlm_entries += 1,
+        }
+
+        // hashes without any letter can be used with both cases
+        match entry.get_letter_case() {
+            LetterCase::Mixed => self.mixed_case_entries += 1,
+            LetterCase::Upper => self.upper_case_entries += 1,
+            LetterCase::Lower => self.lower_case_entries += 1,
+            LetterCase::NoLetters => {}
+        }
+
+        self.total_occurrences = self
+            .total_occurrences
+            .saturating_add(entry.get_occurrences());
+        let is_most_common = match &self.most_common_entry {
+            Some((_, occurrences)) => entry.get_occurrences() > *occurrences,
+            None => true,
+        };
+        if is_most_common {
+            self.most_common_entry = Some((entry.get_hash(), entry.get_occurrences()));
+        }
+
+        // the lookups expect the entries in strictly ascending order (independent of the case)
+        let hash = entry.get_digest();
+        let problem = match &self.last_hash {
+            Some(last_hash) if hash < *last_hash => {
+                self.out_of_order_entries += 1;
+                Some(OrderProblem::OutOfOrder(last_hash.to_string()))
+            }
+            Some(last_hash) if hash == *last_hash => {
+                self.duplicate_entries += 1;
+                Some(OrderProblem::Duplicate)
+            }
+            _ => None,
+        };
+        self.last_hash = Some(hash);
+        problem
+    }
+
+    /// Check if the file contains upper and lower case hashes.
+    fn has_mixed_case(&self) -> bool {
+        self.mixed_case_entries > 0 || (self.upper_case_entries > 0 && self.lower_case_entries > 0)
+    }
+
+    /// Check if the file contains SHA-1 and NTLM hashes.
+    fn has_mixed_algorithms(&self) -> bool {
+        self.sha1_entries > 0 && self.ntlm_entries > 0
+    }
+}
+
+pub fn run_subcommand(matches: &ArgMatches) -> Result<(), SubcommandError> {
+    // get the path to the password file
+    let password_hash_path = match matches.value_of("password-hashes") {
+        Some(path) => path,
+        None => {
+            error!("It seems that the path to the file for the password hashes was not provided, please see the help for usage instructions.");
+            return Err(SubcommandError::Usage);
+        }
+    };
+    debug!("Got {} as a password hash file", password_hash_path);
+
+    // get an instance of the password parser
+    let mut parser = match DatabaseIterator::from_file(password_hash_path) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            return Err(SubcommandError::from(&error));
+        }
+    };
+
+    // get an instance from  the progress bar to indicate the checking progress
+    let progress_bar = ProgressBar::new(parser.get_file_size().unwrap_or(0));
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    // check every line of the file, but just report the first problems of every kind
+    let mut report = FileReport::default();
+    let mut malformed_lines = 0;
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        match maybe_password_hash_entry {
+            Ok(entry) => match report.add_entry(&entry) {
+                Some(OrderProblem::OutOfOrder(last_hash))
+                    if report.out_of_order_entries <= MAXIMUM_REPORTED_PROBLEMS =>
+                {
+                    warn!(
+                        "Line {}: {} is not in ascending order (it follows {})",
+                        parser.get_line_count(),
+                        entry.get_hash(),
+                        last_hash
+                    );
+                }
+                Some(OrderProblem::Duplicate)
+                    if report.duplicate_entries <= MAXIMUM_REPORTED_PROBLEMS =>
+                {
+                    warn!(
+                        "Line {}: {} is a duplicate of the previous entry",
+                        parser.get_line_count(),
+                        entry.get_hash()
+                    );
+                }
+                _ => {}
+            },
+            Err(error) => {
+                if error.is_fatal() {
+                    error!("Could not read the password file. {}", error);
+                    return Err(SubcommandError::Database);
+                }
+                malformed_lines += 1;
+                if malformed_lines <= MAXIMUM_REPORTED_PROBLEMS {
+                    warn!("{}", error);
+                }
+            }
+        }
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+    progress_bar.finish_with_message("checked");
+
+    // print the statistics of the whole file
+    let empty_lines = parser.get_line_count() - report.entries - malformed_lines;
+    println!(
+        "Checked {} lines of the {} file {}",
+        parser.get_line_count(),
+        parser.get_compression_format(),
+        password_hash_path
+    );
+    println!(
+        "Entries: {} valid, {} malformed, {} empty lines",
+        report.entries, malformed_lines, empty_lines
+    );
+    println!(
+        "Hash types: {} SHA-1, {} NTLM",
+        report.sha1_entries, report.ntlm_entries
+    );
+    println!(
+        "Hash case: {} upper case, {} lower case, {} mixed",
+        report.upper_case_entries, report.lower_case_entries, report.mixed_case_entries
+    );
+    println!(
+        "Line endings: {} CRLF, {} LF",
+        parser.get_crlf_line_count(),
+        parser.get_lf_line_count()
+    );
+    println!(
+        "Order: {} entries not in ascending order, {} duplicates",
+        report.out_of_order_entries, report.duplicate_entries
+    );
+    if let Some((hash, occurrences)) = &report.most_common_entry {
+        println!(
+            "Occurrences: {} in total, the most common hash is {} ({} times)",
+            report.total_occurrences, hash, occurrences
+        );
+    }
+
+    // the lookups might give wrong answers for files with any of these problems
+    let mut problems = Vec::new();
+    if malformed_lines > 0 {
+        problems.push("malformed lines");
+    }
+    if report.out_of_order_entries > 0 {
+        problems.push("entries which are not ordered by hash");
+    }
+    if report.duplicate_entries > 0 {
+        problems.push("duplicate entries");
+    }
+    if report.has_mixed_case() {
+        problems.push("upper and lower case hashes");
+    }
+    if report.has_mixed_algorithms() {
+        problems.push("SHA-1 and NTLM hashes");
+    }
+    if parser.get_crlf_line_count() > 0 && parser.get_lf_line_count() > 0 {
+        problems.push("mixed line endings");
+    }
+    if report.entries == 0 {
+        problems.push("no entries at all");
+    }
+
+    if !problems.is_empty() {
+        error!("The password file contains {}", problems.join(", "));
+        return Err(SubcommandError::CorruptData);
+    }
+    println!("The password file is valid and can be used for lookups");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_entry(hash: &str) -> PasswordHashEntry {
+        PasswordHashEntry::from_hash(hash).unwrap()
+    }
+
+    #[test]
+    fn entries_which_are_not_in_ascending_order_are_detected() {
+        let mut report = FileReport::default();
+        assert_eq!(
+            None,
+            report.add_entry(&get_entry("000000005AD76BD555C1D6D771DE417A4B87E4B4"))
+        );
+        assert_eq!(
+            None,
+            report.add_entry(&get_entry("7C4A8D09CA3762AF61E59520943DC26494F8941B"))
+        );
+        assert_eq!(
+            Some(OrderProblem::OutOfOrder(
+                "7C4A8D09CA3762AF61E59520943DC26494F8941B".to_string()
+            )),
+            report.add_entry(&get_entry("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"))
+        );
+        assert_eq!(
+            Some(OrderProblem::Duplicate),
+            report.add_entry(&get_entry("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"))
+        );
+        assert_eq!(4, report.entries);
+        assert_eq!(1, report.out_of_order_entries);
+        assert_eq!(1, report.duplicate_entries);
+    }
+
+    #[test]
+    fn the_case_and_type_of_the_hashes_is_counted() {
+        let mut report = FileReport::default();
+        report.add_entry(&get_entry("00000000000000000000000000000000"));
+        report.add_entry(&get_entry("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"));
+        assert_eq!(false, report.has_mixed_case());
+        assert_eq!(true, report.has_mixed_algorithms());
+
+        report.add_entry(&get_entry("7c4a8d09ca3762af61e59520943dc26494f8941b"));
+        assert_eq!(true, report.has_mixed_case());
+        report.add_entry(&get_entry("8846F7EAEE8FB117AD06BDD830B7586c"));
+        assert_eq!(1, report.upper_case_entries);
+        assert_eq!(1, report.lower_case_entries);
+        assert_eq!(1, report.mixed_case_entries);
+        assert_eq!(2, report.ntlm_entries);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::subcommands::SortedFileEntries;
+use crate::{HashDigest, PasswordHashEntry};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info};
+use std::cmp::Ordering;
+use std::fs::{remove_file, File};
+use std::io::{stdout, BufWriter, Error, Write};
+use std::iter::Peekable;
+use std::process::exit;
+
+/// A difference between the old and the new release of a data set.
+#[derive(Debug, PartialEq)]
+enum Difference {
+    /// The hash is just part of the new release.
+    Added { hash: HashDigest, occurrences: u64 },
+    /// The hash is just part of the old release.
+    Removed { hash: HashDigest, occurrences: u64 },
+    /// The hash is part of both releases, but it occurs more often in the new one.
+    Increased {
+        hash: HashDigest,
+        old_occurrences: u64,
+        new_occurrences: u64,
+    },
+    /// The hash is part of both releases, but it occurs less often in the new one (e.g. because
+    /// a source was removed from the data set).
+    Decreased {
+        hash: HashDigest,
+        old_occurrences: u64,
+        new_occurrences: u64,
+    },
+}
+
+impl Difference {
+    /// Get the line which describes the difference in the text output.
+    fn get_text_line(&self) -> String {
+        match self {
+            Difference::Added { hash, occurrences } => format!("+{}:{}\n", hash, occurrences),
+            Difference::Removed { hash, occurrences } => format!("-{}:{}\n", hash, occurrences),
+            Difference::Increased {
+                hash,
+                old_occurrences,
+                new_occurrences,
+            } => format!(">{}:{}:{}\n", hash, old_occurrences, new_occurrences),
+            Difference::Decreased {
+                hash,
+                old_occurrences,
+                new_occurrences,
+            } => format!("<{}:{}:{}\n", hash, old_occurrences, new_occurrences),
+        }
+    }
+
+    /// Get the line of the delta file (the entry of the new release), if the difference is part
+    /// of it. The delta just contains the added hashes and the ones which occur more often, the
+    /// removed hashes and lower occurrence counts can not be applied to a database by it.
+    fn get_delta_line(&self) -> Option<String> {
+        match self {
+            Difference::Added { hash, occurrences } => Some(format!("{}:{}\n", hash, occurrences)),
+            Difference::Increased {
+                hash,
+                new_occurrences,
+                ..
+            } => Some(format!("{}:{}\n", hash, new_occurrences)),
+            Difference::Removed { .. } | Difference::Decreased { .. } => None,
+        }
+    }
+}
+
+/// This class walks through the entries of two releases (both ordered by hash) at the same time
+/// and returns the differences between them. If one of the releases could not be read, its error
+/// is returned instead.
+struct DifferenceWalker<E, O, N>
+where
+    O: Iterator<Item = Result<PasswordHashEntry, E>>,
+    N: Iterator<Item = Result<PasswordHashEntry, E>>,
+{
+    old_entries: Peekable<O>,
+    new_entries: Peekable<N>,
+}
+
+impl<E, O, N> DifferenceWalker<E, O, N>
+where
+    O: Iterator<Item = Result<PasswordHashEntry, E>>,
+    N: Iterator<Item = Result<PasswordHashEntry, E>>,
+{
+    fn new(old_entries: O, new_entries: N) -> DifferenceWalker<E, O, N> {
+        DifferenceWalker {
+            old_entries: old_entries.peekable(),
+            new_entries: new_entries.peekable(),
+        }
+    }
+}
+
+impl<E, O, N> Iterator for DifferenceWalker<E, O, N>
+where
+    O: Iterator<Item = Result<PasswordHashEntry, E>>,
+    N: Iterator<Item = Result<PasswordHashEntry, E>>,
+{
+    type Item = Result<Difference, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // the lower hash of both releases is the next one which has to be compared
+            let ordering = match (self.old_entries.peek(), self.new_entries.peek()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) => return Some(Err(self.old_entries.next()?.err()?)),
+                (_, Some(Err(_))) => return Some(Err(self.new_entries.next()?.err()?)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(Ok(old_entry)), Some(Ok(new_entry))) => old_entry.cmp(new_entry),
+            };
+
+            match ordering {
+                Ordering::Less => {
+                    let old_entry = self.old_entries.next()?.ok()?;
+                    return Some(Ok(Difference::Removed {
+                        hash: old_entry.get_digest(),
+                        occurrences: old_entry.get_occurrences(),
+                    }));
+                }
+                Ordering::Greater => {
+                    let new_entry = self.new_entries.next()?.ok()?;
+                    return Some(Ok(Difference::Added {
+                        hash: new_entry.get_digest(),
+                        occurrences: new_entry.get_occurrences(),
+                    }));
+                }
+                Ordering::Equal => {
+                    let old_entry = self.old_entries.next()?.ok()?;
+                    let new_entry = self.new_entries.next()?.ok()?;
+                    let hash = new_entry.get_digest();
+                    let old_occurrences = old_entry.get_occurrences();
+                    let new_occurrences = new_entry.get_occurrences();
+                    match old_occurrences.cmp(&new_occurrences) {
+                        Ordering::Less => {
+                            return Some(Ok(Difference::Increased {
+                                hash,
+                                old_occurrences,
+                                new_occurrences,
+                            }))
+                        }
+                        Ordering::Greater => {
+                            return Some(Ok(Difference::Decreased {
+                                hash,
+                                old_occurrences,
+                                new_occurrences,
+                            }))
+                        }
+                        Ordering::Equal => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The number of differences of every kind.
+#[derive(Debug, Default, PartialEq)]
+struct DifferenceCounts {
+    added: u64,
+    removed: u64,
+    increased: u64,
+    decreased: u64,
+}
+
+/// Write all differences in the requested format and count them by their kind.
+fn write_differences<W: Write>(
+    differences: impl Iterator<Item = Difference>,
+    write_delta: bool,
+    output: &mut W,
+) -> Result<DifferenceCounts, Error> {
+    let mut counts = DifferenceCounts::default();
+    for difference in differences {
+        match difference {
+            Difference::Added { .. } => counts.added += 1,
+            Difference::Removed { .. } => counts.removed += 1,
+            Difference::Increased { .. } => counts.increased += 1,
+            Difference::Decreased { .. } => counts.decreased += 1,
+        }
+        if !write_delta {
+            output.write_all(difference.get_text_line().as_bytes())?;
+        } else if let Some(delta_line) = difference.get_delta_line() {
+            output.write_all(delta_line.as_bytes())?;
+        }
+    }
+    output.flush()?;
+    Ok(counts)
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the paths to both releases of the password file
+    let (old_path, new_path) = match (
+        matches.value_of("old-password-hashes"),
+        matches.value_of("new-password-hashes"),
+    ) {
+        (Some(old_path), Some(new_path)) => (old_path, new_path),
+        _ => {
+            error!("It seems that the paths to the password files were not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+    debug!("Comparing {} with {}", old_path, new_path);
+
+    // get an instance from  the progress bar to indicate the progress of reading both files
+    let progress_bar = ProgressBar::new(0);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+    let old_entries = SortedFileEntries::from_file(old_path, &progress_bar);
+    let new_entries = SortedFileEntries::from_file(new_path, &progress_bar);
+    if let (Some(old_algorithm), Some(new_algorithm)) = (
+        old_entries.get_hash_algorithm(),
+        new_entries.get_hash_algorithm(),
+    ) {
+        if old_algorithm != new_algorithm {
+            error!(
+                "The old release contains {} hashes, but the new one contains {} hashes.",
+                old_algorithm, new_algorithm
+            );
+            exit(-2);
+        }
+    }
+
+    // write the differences to the requested file (or the standard output)
+    let output_path = matches.value_of("output");
+    let mut output: BufWriter<Box<dyn Write>> = match output_path {
+        Some(output_path) => match File::create(output_path) {
+            Ok(output_file) => BufWriter::new(Box::new(output_file)),
+            Err(error) => {
+                error!(
+                    "Could not create the output file {}. The error was: {}",
+                    output_path, error
+                );
+                exit(-4);
+            }
+        },
+        None => BufWriter::new(Box::new(stdout())),
+    };
+    let write_delta = matches.value_of("format") == Some("delta");
+    let mut read_error = None;
+    let differences =
+        DifferenceWalker::new(old_entries, new_entries).map_while(|maybe_difference| {
+            match maybe_difference {
+                Ok(difference) => Some(difference),
+                Err(error) => {
+                    read_error = Some(error);
+                    None
+                }
+            }
+        });
+    let write_result = write_differences(differences, write_delta, &mut output);
+    progress_bar.finish_and_clear();
+
+    // a partial output file would look like a complete one, so it is removed after an error
+    let exit_code = match (read_error, write_result) {
+        (Some(error), _) => {
+            error!("{}", error);
+            -3
+        }
+        (None, Err(error)) => {
+            error!("Could not write the differences. The error was: {}", error);
+            -4
+        }
+        (None, Ok(counts)) => {
+            print_summary(counts, output_path.is_some());
+            return;
+        }
+    };
+    drop(output);
+    if let Some(output_path) = output_path {
+        let _ = remove_file(output_path);
+    }
+    exit(exit_code);
+}
+
+/// Print how many hashes were added, removed and changed.
+fn print_summary(counts: DifferenceCounts, output_is_a_file: bool) {
+    // the text differences might have been written to the standard output, so the summary just
+    // goes there if they were written to a file
+    let summary = format!(
+        "{} hashes were added, {} removed, {} occur more often and {} less often",
+        counts.added, counts.removed, counts.increased, counts.decreased
+    );
+    if output_is_a_file {
+        println!("{}", summary);
+    } else {
+        info!("{}", summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn get_entries(entries: &[(&str, u64)]) -> Vec<Result<PasswordHashEntry, String>> {
+        entries
+            .iter()
+            .map(|&(hash, occurrences)| {
+                let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+                entry.occurrences = occurrences;
+                Ok(entry)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn differences_between_two_releases_are_found() {
+        let old_entries = get_entries(&[
+            ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+            ("00000000A8DAE4228F821FB418F59826079BF368", 2),
+            ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+            ("B1B3773A05C0ED0176787A4F1574FF0075F7521E", 10),
+        ]);
+        let new_entries = get_entries(&[
+            ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+            ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861494),
+            ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 24230577),
+            ("B1B3773A05C0ED0176787A4F1574FF0075F7521E", 9),
+        ]);
+
+        let differences: Vec<Difference> =
+            DifferenceWalker::new(old_entries.into_iter(), new_entries.into_iter())
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(
+            vec![
+                Difference::Removed {
+                    hash: HashDigest::from_str("00000000A8DAE4228F821FB418F59826079BF368").unwrap(),
+                    occurrences: 2
+                },
+                Difference::Increased {
+                    hash: HashDigest::from_str("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8").unwrap(),
+                    old_occurrences: 3861493,
+                    new_occurrences: 3861494
+                },
+                Difference::Added {
+                    hash: HashDigest::from_str("7C4A8D09CA3762AF61E59520943DC26494F8941B").unwrap(),
+                    occurrences: 24230577
+                },
+                Difference::Decreased {
+                    hash: HashDigest::from_str("B1B3773A05C0ED0176787A4F1574FF0075F7521E").unwrap(),
+                    old_occurrences: 10,
+                    new_occurrences: 9
+                },
+            ],
+            differences
+        );
+
+        let mut text_output = Vec::new();
+        assert_eq!(
+            DifferenceCounts {
+                added: 1,
+                removed: 1,
+                increased: 1,
+                decreased: 1
+            },
+            write_differences(differences.into_iter(), false, &mut text_output).unwrap()
+        );
+        assert_eq!(
+            "-00000000A8DAE4228F821FB418F59826079BF368:2\n>5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493:3861494\n+7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\n<B1B3773A05C0ED0176787A4F1574FF0075F7521E:10:9\n",
+            String::from_utf8(text_output).unwrap()
+        );
+    }
+
+    #[test]
+    fn the_delta_contains_the_new_and_increased_entries() {
+        let old_entries = get_entries(&[
+            ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+            ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 24230577),
+        ]);
+        let new_entries = get_entries(&[
+            ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+            ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861494),
+            ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 24230576),
+        ]);
+
+        let mut delta_output = Vec::new();
+        write_differences(
+            DifferenceWalker::new(old_entries.into_iter(), new_entries.into_iter())
+                .map(Result::unwrap),
+            true,
+            &mut delta_output,
+        )
+        .unwrap();
+        assert_eq!(
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\n5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861494\n",
+            String::from_utf8(delta_output).unwrap()
+        );
+    }
+
+    #[test]
+    fn the_walk_stops_at_the_first_unreadable_entry() {
+        let old_entries = get_entries(&[("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4)]);
+        let mut new_entries = get_entries(&[("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3)]);
+        new_entries.insert(0, Err("line 1 is malformed".to_string()));
+
+        let mut differences =
+            DifferenceWalker::new(old_entries.into_iter(), new_entries.into_iter());
+        assert_eq!(
+            Some(Err("line 1 is malformed".to_string())),
+            differences.next()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::haveibeenpwned::policy::Verdict;
+use crate::haveibeenpwned::CreateInstanceError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The exit code if the password was not found (or less often than the threshold for rejecting
+/// it).
+pub const EXIT_NOT_FOUND: i32 = 0;
+
+/// The exit code if the password was found and should be rejected.
+pub const EXIT_FOUND: i32 = 1;
+
+/// The exit code if the password was found less often than the threshold for rejecting it. The
+/// password is still accepted, so this is the same exit code as if it was not found at all and
+/// scripts which only check for a successful exit code keep working. The warning is printed
+/// (or contained in the structured output) instead.
+pub const EXIT_WARN: i32 = EXIT_NOT_FOUND;
+
+/// The exit code if the check of a password file or an optimized database did not find any
+/// problems. Like a password which was not found, this is the successful outcome of the check.
+pub const EXIT_VALID: i32 = EXIT_NOT_FOUND;
+
+/// The exit code if the command line arguments or the input of the user could not be used.
+pub const EXIT_USAGE_ERROR: i32 = 2;
+
+/// The exit code if the password database could not be opened or read.
+pub const EXIT_DATABASE_ERROR: i32 = 3;
+
+/// The exit code if the password database contains data which could not be parsed.
+pub const EXIT_CORRUPT_DATA: i32 = 4;
+
+/// The exit code if the result could not be formatted or written.
+pub const EXIT_OUTPUT_ERROR: i32 = 5;
+
+/// The reasons why a lookup could not be completed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubcommandError {
+    /// The command line arguments or the input of the user could not be used.
+    Usage,
+    /// The password database could not be opened or read.
+    Database,
+    /// The password database contains data which could not be parsed.
+    CorruptData,
+    /// The result could not be formatted or written.
+    Output,
+}
+
+impl SubcommandError {
+    /// Get the exit code of the process for this error.
+    pub fn get_exit_code(self) -> i32 {
+        match self {
+            SubcommandError::Usage => EXIT_USAGE_ERROR,
+            SubcommandError::Database => EXIT_DATABASE_ERROR,
+            SubcommandError::CorruptData => EXIT_CORRUPT_DATA,
+            SubcommandError::Output => EXIT_OUTPUT_ERROR,
+        }
+    }
+}
+
+impl Display for SubcommandError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            SubcommandError::Usage => write!(f, "usage error"),
+            SubcommandError::Database => write!(f, "database error"),
+            SubcommandError::CorruptData => write!(f, "corrupt data"),
+            SubcommandError::Output => write!(f, "output error"),
+        }
+    }
+}
+
+impl From<&CreateInstanceError> for SubcommandError {
+    fn from(error: &CreateInstanceError) -> Self {
+        match error {
+            CreateInstanceError::Format(_) => SubcommandError::CorruptData,
+            CreateInstanceError::Io(_) => SubcommandError::Database,
+        }
+    }
+}
+
+/// Get the exit code of the process for the result of a lookup.
+///
+/// # Example
+/// ```
+/// use pwned_rs::haveibeenpwned::policy::Verdict;
+/// use pwned_rs::subcommands::exitcode::{get_exit_code, SubcommandError};
+///
+/// assert_eq!(0, get_exit_code(Ok(Verdict::NotFound)));
+/// assert_eq!(0, get_exit_code(Ok(Verdict::Warn(3))));
+/// assert_eq!(1, get_exit_code(Ok(Verdict::Reject(3861493))));
+/// assert_eq!(3, get_exit_code(Err(SubcommandError::Database)));
+/// ```
+pub fn get_exit_code(result: Result<Verdict, SubcommandError>) -> i32 {
+    match result {
+        Ok(verdict) if verdict.is_rejected() => EXIT_FOUND,
+        Ok(Verdict::Warn(_)) => EXIT_WARN,
+        Ok(_) => EXIT_NOT_FOUND,
+        Err(error) => error.get_exit_code(),
+    }
+}
+
+/// Get the exit code of the process for the result of a subcommand which checks the database
+/// instead of looking up a password. If no problems were found, the process exits successfully.
+///
+/// # Example
+/// ```
+/// use pwned_rs::subcommands::exitcode::{get_check_exit_code, SubcommandError};
+///
+/// assert_eq!(0, get_check_exit_code(Ok(())));
+/// assert_eq!(4, get_check_exit_code(Err(SubcommandError::CorruptData)));
+/// ```
+pub fn get_check_exit_code(result: Result<(), SubcommandError>) -> i32 {
+    match result {
+        Ok(()) => EXIT_VALID,
+        Err(error) => error.get_exit_code(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::FormatErrorKind;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn every_result_has_its_exit_code() {
+        assert_eq!(0, get_exit_code(Ok(Verdict::NotFound)));
+        assert_eq!(0, get_exit_code(Ok(Verdict::Warn(3))));
+        assert_eq!(1, get_exit_code(Ok(Verdict::Reject(3))));
+        assert_eq!(2, get_exit_code(Err(SubcommandError::Usage)));
+        assert_eq!(3, get_exit_code(Err(SubcommandError::Database)));
+        assert_eq!(4, get_exit_code(Err(SubcommandError::CorruptData)));
+        assert_eq!(5, get_exit_code(Err(SubcommandError::Output)));
+        assert_eq!(0, get_check_exit_code(Ok(())));
+        assert_eq!(3, get_check_exit_code(Err(SubcommandError::Database)));
+        assert_eq!(4, get_check_exit_code(Err(SubcommandError::CorruptData)));
+
+        assert_eq!(
+            SubcommandError::Database,
+            SubcommandError::from(&CreateInstanceError::Io(Error::from(ErrorKind::NotFound)))
+        );
+        assert_eq!(
+            SubcommandError::CorruptData,
+            SubcommandError::from(&CreateInstanceError::Format(
+                FormatErrorKind::LineFormatNotCorrect
+            ))
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use crate::haveibeenpwned::filter::{BloomFilter, DEFAULT_FALSE_POSITIVE_RATE};
+use crate::haveibeenpwned::{DatabaseIterator, EntryOrder};
+use crate::PasswordHashEntry;
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// The length of the hash prefix which is used for the names of the range files.
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/// The number of range files which are written (one for every possible prefix).
+const NUMBER_OF_RANGE_FILES: u32 = 1 << (4 * RANGE_PREFIX_LENGTH);
+
+/// The name of the file in the output folder into which the filter is written.
+const FILTER_FILE_NAME: &str = "password-filter.bloom";
+
+/// This class writes the entries of a password file ordered by hash into one file per 5-character
+/// prefix. Each file only contains the `SUFFIX:COUNT` lines (separated by CRLF), exactly like the
+/// response of the range API of https://haveibeenpwned.com.
+struct RangeFileWriter {
+    output_folder: PathBuf,
+    current_prefix: Option<u32>,
+    current_file: Option<BufWriter<File>>,
+    next_prefix: u32,
+    number_of_files: u32,
+}
+
+impl RangeFileWriter {
+    fn new(output_folder: &Path) -> RangeFileWriter {
+        RangeFileWriter {
+            output_folder: output_folder.to_path_buf(),
+            current_prefix: None,
+            current_file: None,
+            next_prefix: 0,
+            number_of_files: 0,
+        }
+    }
+
+    fn create_file(&mut self, prefix: u32) -> Result<BufWriter<File>, Error> {
+        let file_name = format!("{:05X}", prefix);
+        let file_handle = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.output_folder.join(file_name))?;
+        self.number_of_files += 1;
+        Ok(BufWriter::new(file_handle))
+    }
+
+    /// Finish the currently open file and create empty files for all prefixes up to (but not
+    /// including) the supplied one.
+    fn advance_to(&mut self, prefix: u32) -> Result<(), Error> {
+        if let Some(mut current_file) = self.current_file.take() {
+            current_file.flush()?;
+        }
+        while self.next_prefix < prefix {
+            let mut empty_file = self.create_file(self.next_prefix)?;
+            empty_file.flush()?;
+            self.next_prefix += 1;
+        }
+        Ok(())
+    }
+
+    fn write_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        let hash = entry.get_hash();
+        let prefix = match u32::from_str_radix(&hash[..RANGE_PREFIX_LENGTH], 16) {
+            Ok(prefix) => prefix,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the hash {} is not a hexadecimal hash", hash),
+                ))
+            }
+        };
+
+        // if we reached a new prefix, we have to switch to the file for it
+        if self.current_prefix != Some(prefix) {
+            if prefix < self.next_prefix {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the hash {} is not in ascending order", hash),
+                ));
+            }
+            self.advance_to(prefix)?;
+            self.current_file = Some(self.create_file(prefix)?);
+            self.current_prefix = Some(prefix);
+            self.next_prefix = prefix + 1;
+        } else if let Some(current_file) = self.current_file.as_mut() {
+            current_file.write_all(b"\r\n")?;
+        }
+
+        if let Some(current_file) = self.current_file.as_mut() {
+            write!(
+                current_file,
+                "{}:{}",
+                &hash[RANGE_PREFIX_LENGTH..],
+                entry.get_occurrences()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Finish the export by creating the files for all remaining prefixes. Returns the number of
+    /// written files.
+    fn finish(mut self) -> Result<u32, Error> {
+        self.advance_to(NUMBER_OF_RANGE_FILES)?;
+        Ok(self.number_of_files)
+    }
+}
+
+/// Get a progress bar for reading the supplied number of bytes of the password file.
+fn create_progress_bar(length: u64) -> ProgressBar {
+    let progress_bar = ProgressBar::new(length);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+    progress_bar
+}
+
+/// Export the entries of the password file into one range file per 5-character prefix.
+fn export_ranges(mut parser: DatabaseIterator, output_folder: &Path) {
+    if parser.get_entry_order() == EntryOrder::Unordered {
+        error!("The password file is not ordered by hash. Please use the file which is ordered by hash or optimize this one instead.");
+        exit(-3);
+    }
+
+    // get an instance from  the progress bar to indicate the export progress
+    let progress_bar = create_progress_bar(parser.get_file_size().unwrap_or(0));
+
+    // write all entries into the range files
+    let mut range_file_writer = RangeFileWriter::new(output_folder);
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        // the range files would be incomplete if we skipped a line, so we have to stop here
+        let password_hash_entry = match maybe_password_hash_entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                error!("Could not read the password file. {}", error);
+                exit(-4);
+            }
+        };
+        if let Err(error) = range_file_writer.write_entry(&password_hash_entry) {
+            error!(
+                "Could not write a password entry into the range files. The error was: {}",
+                error
+            );
+            exit(-4);
+        }
+
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+
+    let number_of_files = match range_file_writer.finish() {
+        Ok(number_of_files) => number_of_files,
+        Err(error) => {
+            error!(
+                "Could not finish writing the range files. The error was: {}",
+                error
+            );
+            exit(-5);
+        }
+    };
+    progress_bar.finish_with_message("exported");
+
+    info!(
+        "Exported the password database into {} range files",
+        number_of_files
+    );
+}
+
+/// Export the hashes of the password file into a Bloom filter. The size of the filter depends on
+/// the number of entries, so the password file is read twice: once for counting the entries and
+/// once for adding them to the filter.
+fn export_filter(
+    mut parser: DatabaseIterator,
+    password_hash_path: &str,
+    filter_path: &Path,
+    false_positive_rate: f64,
+) {
+    let hash_algorithm = match parser.get_hash_algorithm() {
+        Some(algorithm) => algorithm,
+        None => {
+            error!("Could not detect the type of the hashes in the password file.");
+            exit(-3);
+        }
+    };
+    let file_size = parser.get_file_size().unwrap_or(0);
+    let progress_bar = create_progress_bar(2 * file_size);
+
+    // the filter would report false negatives if we skipped a line, so we have to stop here
+    let mut number_of_entries: u64 = 0;
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        if let Err(error) = maybe_password_hash_entry {
+            error!("Could not read the password file. {}", error);
+            exit(-4);
+        }
+        number_of_entries += 1;
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+
+    let mut filter = match BloomFilter::new(number_of_entries, false_positive_rate, hash_algorithm)
+    {
+        Ok(filter) => filter,
+        Err(error) => {
+            error!("Could not create the filter. The error was: {}", error);
+            exit(-2);
+        }
+    };
+    debug!(
+        "Adding {} entries to a filter with {} bytes",
+        number_of_entries,
+        filter.get_size_in_bytes()
+    );
+
+    let mut parser = match DatabaseIterator::from_file(password_hash_path) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            exit(-3);
+        }
+    };
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        let insert_result = match maybe_password_hash_entry {
+            Ok(entry) => filter.insert(&entry).map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        if let Err(error) = insert_result {
+            error!(
+                "Could not add an entry of the password file to the filter. {}",
+                error
+            );
+            exit(-4);
+        }
+        progress_bar.set_position(file_size + parser.get_processed_bytes());
+    }
+
+    if let Err(error) = filter.write_to_file(filter_path) {
+        error!(
+            "Could not write the filter to {}. The error was: {}",
+            filter_path.display(),
+            error
+        );
+        exit(-5);
+    }
+    progress_bar.finish_with_message("exported");
+
+    info!(
+        "Exported {} hashes into the filter {} ({} bytes, {} hash functions, false positive rate {:.6})",
+        filter.get_entry_count(),
+        filter_path.display(),
+        filter.get_size_in_bytes(),
+        filter.get_number_of_hash_functions(),
+        filter.get_false_positive_rate()
+    );
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the path to the password file
+    let password_hash_path = match matches.value_of("password-hashes") {
+        Some(path) => path,
+        None => {
+            error!("It seems that the path to the file for the password hashes was not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+    debug!("Got {} as a password hash file", password_hash_path);
+
+    // get the output folder where the exported files should be stored
+    let output_folder = match matches.value_of("output-folder") {
+        Some(path) => {
+            if !Path::new(path).exists() {
+                error!(
+                    "The supplied path ('{}') does not exists. Please select an existing folder.",
+                    path
+                );
+                exit(-1);
+            }
+            path
+        }
+        None => {
+            error!("It seems that the path where the exported files should be stored was not provided, please see the help for usage instructions.");
+            exit(-2);
+        }
+    };
+    debug!("Got {} as the output folder", output_folder);
+
+    // get an instance of the password parser
+    let parser = match DatabaseIterator::from_file(password_hash_path) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            exit(-3);
+        }
+    };
+
+    match matches.value_of("format") {
+        Some("bloom") => {
+            let false_positive_rate = match matches.value_of("false-positive-rate") {
+                Some(value) => match value.parse::<f64>() {
+                    Ok(rate) => rate,
+                    Err(_) => {
+                        error!(
+                            "The supplied false positive rate ('{}') is not a number.",
+                            value
+                        );
+                        exit(-2);
+                    }
+                },
+                None => DEFAULT_FALSE_POSITIVE_RATE,
+            };
+            export_filter(
+                parser,
+                password_hash_path,
+                &Path::new(output_folder).join(FILTER_FILE_NAME),
+                false_positive_rate,
+            );
+        }
+        _ => export_ranges(parser, Path::new(output_folder)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+
+    #[test]
+    fn range_files_contain_the_suffixes_of_their_prefix() {
+        let password_file = TemporaryPath::new("export-ranges.txt");
+        std::fs::write(
+            &password_file,
+            "00000A1B2C3D4E5F60718293A4B5C6D7E8F90A1B:3\r\n\
+             00000a1b2c3d4e5f60718293a4b5c6d7e8f90a1c:10\r\n\
+             000030000000000000000000000000000000000F:1\r\n",
+        )
+        .unwrap();
+        let output_folder = TemporaryPath::new_folder("export-ranges");
+
+        let mut range_file_writer = RangeFileWriter::new(&output_folder);
+        let parser = DatabaseIterator::from_file(&password_file.to_string_lossy()).unwrap();
+        for maybe_password_hash_entry in parser {
+            range_file_writer
+                .write_entry(&maybe_password_hash_entry.unwrap())
+                .unwrap();
+        }
+        // finishing would create all 2^20 files, so just the first ones are completed
+        range_file_writer.advance_to(5).unwrap();
+        assert_eq!(5, range_file_writer.number_of_files);
+
+        let read_range_file =
+            |name: &str| std::fs::read_to_string(output_folder.join(name)).unwrap();
+        assert_eq!(
+            "A1B2C3D4E5F60718293A4B5C6D7E8F90A1B:3\r\nA1B2C3D4E5F60718293A4B5C6D7E8F90A1C:10",
+            read_range_file("00000")
+        );
+        assert_eq!("", read_range_file("00001"));
+        assert_eq!("", read_range_file("00002"));
+        assert_eq!(
+            "0000000000000000000000000000000000F:1",
+            read_range_file("00003")
+        );
+        assert_eq!("", read_range_file("00004"));
+        assert_eq!(false, output_folder.join("00005").exists());
+    }
+
+    #[test]
+    fn unordered_entries_are_rejected() {
+        let output_folder = TemporaryPath::new_folder("export-unordered");
+
+        let mut range_file_writer = RangeFileWriter::new(&output_folder);
+        let first_entry = PasswordHashEntry::from_digest(
+            "0000200000000000000000000000000000000000".parse().unwrap(),
+            1,
+        );
+        let second_entry = PasswordHashEntry::from_digest(
+            "0000100000000000000000000000000000000000".parse().unwrap(),
+            1,
+        );
+        range_file_writer.write_entry(&first_entry).unwrap();
+        let error = range_file_writer.write_entry(&second_entry).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+    }
+}
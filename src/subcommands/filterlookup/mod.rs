@@ -0,0 +1,108 @@
+use crate::haveibeenpwned::filter::BloomFilter;
+use crate::{PasswordHashEntry, SecretString};
+use clap::ArgMatches;
+use log::{debug, error, info};
+use rpassword::read_password_from_tty;
+use std::path::Path;
+use std::process::exit;
+
+/// Check if the supplied password is (probably) part of the filter. The password is hashed with
+/// the hash algorithm recorded in the filter and dropped as soon as it is hashed.
+fn lookup_password(filter: &BloomFilter, password: SecretString) -> bool {
+    debug!(
+        "Looking up the {} hash of the password in a filter with {} hashes",
+        filter.get_hash_algorithm(),
+        filter.get_entry_count()
+    );
+    let password_entry = PasswordHashEntry::from_secret(&password, filter.get_hash_algorithm());
+    drop(password);
+    filter.contains(&password_entry)
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the path to the password filter
+    let filter_path = match matches.value_of("password-filter") {
+        Some(path) => Path::new(path),
+        None => {
+            error!("It seems that the path to the password filter was not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+
+    // try to read the password from the user
+    let read_password =
+        match read_password_from_tty(Some("Enter the password you are looking for: ")) {
+            Ok(password) => SecretString::new(password),
+            Err(_) => {
+                error!("Could not read the password from the user.");
+                return;
+            }
+        };
+
+    // read the filter, the type of the hashes is recorded in the filter
+    let filter = match BloomFilter::from_file(filter_path) {
+        Ok(filter) => filter,
+        Err(error) => {
+            error!(
+                "Could not open {} for looking up passwords. The error was: {}",
+                filter_path.display(),
+                error
+            );
+            exit(-2);
+        }
+    };
+    // the filter might report a password which is not part of any breach, but never the other way
+    if lookup_password(&filter, read_password) {
+        info!(
+            "Choose a different password - the one you entered is part of a list of hacked passwords (the filter has a false positive rate of {:.4}%)!",
+            filter.get_false_positive_rate() * 100.0
+        );
+    } else {
+        info!("Perfect! Could not find the password in any of the available breaches. Go on!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use crate::HashAlgorithm;
+
+    #[test]
+    fn passwords_are_looked_up_in_a_written_filter() {
+        let file_path = TemporaryPath::new("filterlookup.bloom");
+        let mut filter = BloomFilter::new(100, 0.001, HashAlgorithm::Sha1).unwrap();
+        filter
+            .insert(&PasswordHashEntry::from_password("password"))
+            .unwrap();
+        filter.write_to_file(&file_path).unwrap();
+
+        let read_filter = BloomFilter::from_file(&file_path).unwrap();
+        assert_eq!(
+            true,
+            lookup_password(&read_filter, SecretString::new("password".to_string()))
+        );
+        assert_eq!(
+            false,
+            lookup_password(
+                &read_filter,
+                SecretString::new("not a password".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn passwords_are_hashed_with_the_algorithm_of_the_filter() {
+        let mut filter = BloomFilter::new(100, 0.001, HashAlgorithm::Ntlm).unwrap();
+        filter
+            .insert(&PasswordHashEntry::from_password_with_algorithm(
+                "password",
+                HashAlgorithm::Ntlm,
+            ))
+            .unwrap();
+        assert_eq!(
+            true,
+            lookup_password(&filter, SecretString::new("password".to_string()))
+        );
+    }
+}
@@ -1,57 +1,113 @@
-use crate::haveibeenpwned::DatabaseReader;
-use crate::PasswordHashEntry;
+use crate::haveibeenpwned::database::PasswordDatabase;
+use crate::haveibeenpwned::optimized::OptimizedDatabase;
+use crate::haveibeenpwned::policy::{ThresholdPolicy, Verdict};
+use crate::subcommands::exitcode::SubcommandError;
+use crate::subcommands::output::{print_structured_result, LookupResult, OutputFormat};
+use crate::{HashAlgorithm, PasswordHashEntry, SecretString};
 use clap::ArgMatches;
-use log::{debug, error, info};
+use log::{debug, error};
 use rpassword::read_password_from_tty;
 use std::path::Path;
-use std::process::exit;
+use std::time::Instant;
 
-pub fn run_subcommand(matches: &ArgMatches) {
+/// Look up a password which is entered by the user. The verdict is returned, so the process can
+/// exit with a code which tells if the password was found.
+pub fn run_subcommand(matches: &ArgMatches) -> Result<Verdict, SubcommandError> {
     // get the path to the optimized password database
     let password_hash_folder = match matches.value_of("optimized-db-folder") {
         Some(path) => path,
         None => {
             error!("It seems that the path to the folder for the optimized password hash files was not provided, please see the help for usage instructions.");
-            exit(-1);
+            return Err(SubcommandError::Usage);
+        }
+    };
+
+    // get the format in which the result should be written
+    let output_format = OutputFormat::from_name(matches.value_of("format").unwrap_or("text"))
+        .unwrap_or(OutputFormat::Text);
+
+    // get the number of occurrences from which on a password should be rejected
+    let policy = match matches.value_of("reject-threshold") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(threshold) => ThresholdPolicy::new(threshold),
+            Err(_) => {
+                error!("The supplied threshold ('{}') is not a number.", value);
+                return Err(SubcommandError::Usage);
+            }
+        },
+        None => ThresholdPolicy::default(),
+    };
+
+    // try to get the optimized database and the layout it was written with
+    let optimized_database = match OptimizedDatabase::from_folder(Path::new(password_hash_folder)) {
+        Ok(database) => database,
+        Err(error) => {
+            error!("Could not open the database. The error was: {}", error);
+            return Err(SubcommandError::from(&error));
         }
     };
 
     // try to read the password from the user
     let read_password =
         match read_password_from_tty(Some("Enter the password you are looking for: ")) {
-            Ok(password) => password,
+            Ok(password) => SecretString::new(password),
             Err(_) => {
                 error!("Could not read the password from the user.");
-                return;
+                return Err(SubcommandError::Usage);
             }
         };
 
-    // get the SHA-1 hashed password
-    let password_entry = PasswordHashEntry::from_password(&read_password);
-    debug!(
-        "Looking up password in {}.txt...",
-        password_entry.get_prefix()
-    );
-
-    // try to get the reader for the database
-    let file_path =
-        Path::new(password_hash_folder).join(format!("{}.txt", password_entry.get_prefix()));
-    let read_database = match DatabaseReader::from_file(&file_path) {
-        Ok(parser) => parser,
+    // get the hashed password, based on the type of the hashes in the database (databases without
+    // a manifest were always written from SHA-1 hashes)
+    let hash_algorithm =
+        match HashAlgorithm::from_name(matches.value_of("hash-type").unwrap_or("auto")) {
+            Some(algorithm) => algorithm,
+            None => optimized_database
+                .get_hash_algorithm()
+                .unwrap_or(HashAlgorithm::Sha1),
+        };
+    debug!("Looking up the password as a {} hash", hash_algorithm);
+    let password_entry = PasswordHashEntry::from_secret(&read_password, hash_algorithm);
+    drop(read_password);
+
+    // look up the password in the file for its prefix
+    let lookup_start = Instant::now();
+    let verdict = match optimized_database.get_verdict(&password_entry.get_digest(), &policy) {
+        Ok(verdict) => verdict,
         Err(error) => {
-            error!("Could not open the database. The error was: {}", error);
-            return;
+            error!("Could not read the database. The error was: {}", error);
+            return Err(SubcommandError::from(&error));
         }
     };
+    let lookup_duration = lookup_start.elapsed();
 
-    //
-    match read_database.get_password_count(password_entry.get_hash()) {
-        Some(count) => info!(
+    // tell the user what to do with the password
+    match (output_format, verdict) {
+        (OutputFormat::Text, Verdict::Reject(count)) => println!(
             "The password was found {} times in password breaches. Please change the password!",
             count
         ),
-        None => {
-            info!("Perfect! Could not find the password in any of the available breaches. Go on!")
+        (OutputFormat::Text, Verdict::Warn(count)) => println!(
+            "The password was found {} times in password breaches. This is less than {} times, but you should consider changing the password.",
+            count,
+            policy.get_reject_threshold()
+        ),
+        (OutputFormat::Text, Verdict::NotFound) => {
+            println!("Perfect! Could not find the password in any of the available breaches. Go on!")
+        }
+        (format, verdict) => {
+            let result = LookupResult::new(
+                &password_entry.get_hash(),
+                hash_algorithm,
+                verdict,
+                password_hash_folder,
+                lookup_duration,
+            );
+            if let Err(error) = print_structured_result(&result, format) {
+                error!("Could not format the result. The error was: {}", error);
+                return Err(SubcommandError::Output);
+            }
         }
     }
+    Ok(verdict)
 }
@@ -0,0 +1,611 @@
+use crate::haveibeenpwned::manifest::{DatabaseManifest, ManifestFileEntry, DEFAULT_PREFIX_LENGTH};
+use crate::haveibeenpwned::optimized::{get_temporary_path, ShardWriter};
+use crate::subcommands::SortedFileEntries;
+use crate::{HashDigest, PasswordHashEntry};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info};
+use std::cmp::{max, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{remove_file, File};
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// How the occurrence counts of a hash which is part of several sources are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CountPolicy {
+    /// The counts of all sources are added up.
+    Sum,
+    /// The highest count of all sources is used.
+    Max,
+    /// The count of the first source (in the order they were supplied) is used.
+    PreferFirst,
+}
+
+impl CountPolicy {
+    fn from_name(name: &str) -> Option<CountPolicy> {
+        match name {
+            "sum" => Some(CountPolicy::Sum),
+            "max" => Some(CountPolicy::Max),
+            "prefer-first" => Some(CountPolicy::PreferFirst),
+            _ => None,
+        }
+    }
+
+    /// Combine the counts of all sources (ordered by the index of the source) of a hash.
+    fn combine(self, occurrences: &[(usize, u64)]) -> u64 {
+        match self {
+            CountPolicy::Sum => occurrences
+                .iter()
+                .fold(0u64, |sum, &(_, count)| sum.saturating_add(count)),
+            CountPolicy::Max => occurrences
+                .iter()
+                .fold(0, |maximum, &(_, count)| max(maximum, count)),
+            CountPolicy::PreferFirst => occurrences.first().map_or(0, |&(_, count)| count),
+        }
+    }
+}
+
+/// A hash of the merged database together with the indices of the sources it was found in.
+struct MergedEntry {
+    entry: PasswordHashEntry,
+    sources: Vec<usize>,
+}
+
+/// This class walks through the entries of several sources (all ordered by hash) at the same time
+/// and returns every hash just once, with the counts of all sources combined. If one of the
+/// sources could not be read, its error is returned instead.
+struct MergeWalker<E, I: Iterator<Item = Result<PasswordHashEntry, E>>> {
+    sources: Vec<I>,
+    current_entries: Vec<Option<PasswordHashEntry>>,
+    next_hashes: BinaryHeap<Reverse<(HashDigest, usize)>>,
+    policy: CountPolicy,
+    error: Option<E>,
+}
+
+impl<E, I: Iterator<Item = Result<PasswordHashEntry, E>>> MergeWalker<E, I> {
+    fn new(sources: Vec<I>, policy: CountPolicy) -> MergeWalker<E, I> {
+        let mut walker = MergeWalker {
+            current_entries: sources.iter().map(|_| None).collect(),
+            sources,
+            next_hashes: BinaryHeap::new(),
+            policy,
+            error: None,
+        };
+        for source_index in 0..walker.sources.len() {
+            walker.read_next_entry(source_index);
+        }
+        walker
+    }
+
+    /// Read the next entry of the supplied source, the hashes are compared by their raw bytes (so
+    /// independent of their case).
+    fn read_next_entry(&mut self, source_index: usize) {
+        match self.sources[source_index].next() {
+            Some(Ok(entry)) => {
+                self.next_hashes
+                    .push(Reverse((entry.get_digest(), source_index)));
+                self.current_entries[source_index] = Some(entry);
+            }
+            Some(Err(error)) if self.error.is_none() => self.error = Some(error),
+            _ => {}
+        }
+    }
+}
+
+impl<E, I: Iterator<Item = Result<PasswordHashEntry, E>>> Iterator for MergeWalker<E, I> {
+    type Item = Result<MergedEntry, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the merge can not be continued correctly without the entries of a failed source
+        if let Some(error) = self.error.take() {
+            self.next_hashes.clear();
+            return Some(Err(error));
+        }
+        let Reverse((hash, source_index)) = self.next_hashes.pop()?;
+
+        // collect the counts of all sources whose current entry has the same hash, the heap
+        // returns them ordered by the index of their source
+        let mut occurrences = Vec::new();
+        let mut next_source_index = Some(source_index);
+        while let Some(source_index) = next_source_index {
+            if let Some(entry) = self.current_entries[source_index].take() {
+                occurrences.push((source_index, entry.get_occurrences()));
+            }
+            self.read_next_entry(source_index);
+
+            next_source_index = match self.next_hashes.peek() {
+                Some(Reverse((next_hash, _))) if *next_hash == hash => self
+                    .next_hashes
+                    .pop()
+                    .map(|Reverse((_, source_index))| source_index),
+                _ => None,
+            };
+        }
+
+        // the failed source could have contained the same hash as well
+        if let Some(error) = self.error.take() {
+            self.next_hashes.clear();
+            return Some(Err(error));
+        }
+        Some(Ok(MergedEntry {
+            entry: PasswordHashEntry::from_digest(hash, self.policy.combine(&occurrences)),
+            sources: occurrences
+                .iter()
+                .map(|&(source_index, _)| source_index)
+                .collect(),
+        }))
+    }
+}
+
+/// This class writes the merged entries into the files of an optimized database. Every file is
+/// written under a temporary name and renamed as soon as all entries of its prefix were written.
+struct FolderWriter<'a> {
+    folder: &'a Path,
+    manifest: DatabaseManifest,
+    current_file: Option<(String, PathBuf, ShardWriter, u64)>,
+}
+
+impl<'a> FolderWriter<'a> {
+    fn new(folder: &'a Path, manifest: DatabaseManifest) -> FolderWriter<'a> {
+        FolderWriter {
+            folder,
+            manifest,
+            current_file: None,
+        }
+    }
+
+    fn write_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        let prefix = self.manifest.get_prefix(entry).unwrap_or_default();
+        let is_new_prefix = match &self.current_file {
+            Some((current_prefix, ..)) => *current_prefix != prefix,
+            None => true,
+        };
+        if is_new_prefix {
+            self.finish_current_file()?;
+            let temporary_path =
+                get_temporary_path(&self.manifest.get_file_path(self.folder, &prefix));
+            let writer = ShardWriter::create(
+                &temporary_path,
+                self.manifest.get_hash_algorithm(),
+                self.manifest.is_binary(),
+            )?;
+            self.current_file = Some((prefix, temporary_path, writer, 0));
+        }
+
+        if let Some((_, _, writer, record_count)) = &mut self.current_file {
+            writer.write_entry(entry)?;
+            *record_count += 1;
+        }
+        Ok(())
+    }
+
+    fn finish_current_file(&mut self) -> Result<(), Error> {
+        if let Some((prefix, temporary_path, writer, record_count)) = self.current_file.take() {
+            let path_to_file = self.manifest.get_file_path(self.folder, &prefix);
+            writer.finish()?;
+            std::fs::rename(&temporary_path, &path_to_file)?;
+            self.manifest.add_file(ManifestFileEntry::from_file(
+                &prefix,
+                &path_to_file,
+                record_count,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Finish the last file and write the manifest of the database.
+    fn finish(mut self) -> Result<(), Error> {
+        self.finish_current_file()?;
+        self.manifest.write_to_folder(self.folder)
+    }
+
+    /// Remove all files which were written so far, since the database is incomplete.
+    fn remove(mut self) {
+        if let Some((_, temporary_path, writer, _)) = self.current_file.take() {
+            drop(writer);
+            let _ = remove_file(temporary_path);
+        }
+        for file_entry in self.manifest.get_files() {
+            let _ = remove_file(
+                self.manifest
+                    .get_file_path(self.folder, file_entry.get_prefix()),
+            );
+        }
+    }
+}
+
+/// The destination of the merged entries.
+enum MergeOutput<'a> {
+    File(&'a str, BufWriter<File>),
+    Folder(Box<FolderWriter<'a>>),
+}
+
+impl<'a> MergeOutput<'a> {
+    fn write_entry(&mut self, entry: &PasswordHashEntry) -> Result<(), Error> {
+        match self {
+            MergeOutput::File(_, writer) => writer.write_all(entry.get_line_to_write().as_bytes()),
+            MergeOutput::Folder(writer) => writer.write_entry(entry),
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            MergeOutput::File(_, mut writer) => writer.flush(),
+            MergeOutput::Folder(writer) => writer.finish(),
+        }
+    }
+
+    /// Remove the merged entries which were written so far, since the output is incomplete.
+    fn remove(self) {
+        match self {
+            MergeOutput::File(output_path, writer) => {
+                drop(writer);
+                let _ = remove_file(output_path);
+            }
+            MergeOutput::Folder(writer) => writer.remove(),
+        }
+    }
+}
+
+/// Remove the incomplete outputs of the merge and exit with the supplied exit code.
+fn abort_merge(
+    output: MergeOutput,
+    tags_output: Option<(&str, BufWriter<File>)>,
+    exit_code: i32,
+) -> ! {
+    output.remove();
+    if let Some((tags_path, tags_writer)) = tags_output {
+        drop(tags_writer);
+        let _ = remove_file(tags_path);
+    }
+    exit(exit_code);
+}
+
+/// Get the tag of every source. Sources without an explicitly supplied tag are tagged with the
+/// name of their file (without any extension).
+fn get_source_tags(input_paths: &[&str], supplied_tags: &[&str]) -> Vec<String> {
+    input_paths
+        .iter()
+        .enumerate()
+        .map(
+            |(source_index, input_path)| match supplied_tags.get(source_index) {
+                Some(tag) => tag.to_string(),
+                None => {
+                    Path::new(input_path)
+                        .file_name()
+                        .map_or(input_path.to_string(), |file_name| {
+                            let file_name = file_name.to_string_lossy();
+                            file_name.split('.').next().unwrap_or_default().to_string()
+                        })
+                }
+            },
+        )
+        .collect()
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the paths to all password files which should be merged
+    let input_paths: Vec<&str> = match matches.values_of("password-hashes") {
+        Some(paths) => paths.collect(),
+        None => {
+            error!("It seems that the paths to the password files were not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+    let supplied_tags: Vec<&str> = matches
+        .values_of("tag")
+        .map_or(Vec::new(), |tags| tags.collect());
+    if supplied_tags.len() > input_paths.len() {
+        error!(
+            "{} tags were supplied for {} password files.",
+            supplied_tags.len(),
+            input_paths.len()
+        );
+        exit(-1);
+    }
+    let source_tags = get_source_tags(&input_paths, &supplied_tags);
+
+    // get the policy for combining the counts of hashes which are part of several files
+    let policy = match matches.value_of("policy").and_then(CountPolicy::from_name) {
+        Some(policy) => policy,
+        None => {
+            error!("The policy for combining the occurrence counts is not known.");
+            exit(-1);
+        }
+    };
+    debug!(
+        "Merging {} password files with the policy {:?}",
+        input_paths.len(),
+        policy
+    );
+
+    // get an instance from  the progress bar to indicate the progress of reading all files
+    let progress_bar = ProgressBar::new(0);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    // all files have to contain the same type of hashes
+    let sources: Vec<SortedFileEntries> = input_paths
+        .iter()
+        .map(|input_path| SortedFileEntries::from_file(input_path, &progress_bar))
+        .collect();
+    let hash_algorithm = match sources[0].get_hash_algorithm() {
+        Some(algorithm) => algorithm,
+        None => {
+            error!(
+                "Could not detect the type of the hashes in {}.",
+                input_paths[0]
+            );
+            exit(-2);
+        }
+    };
+    for (source_index, source) in sources.iter().enumerate() {
+        if let Some(algorithm) = source.get_hash_algorithm() {
+            if algorithm != hash_algorithm {
+                error!(
+                    "{} contains {} hashes, but {} contains {} hashes.",
+                    input_paths[source_index], algorithm, input_paths[0], hash_algorithm
+                );
+                exit(-2);
+            }
+        }
+    }
+    info!(
+        "Merging {} data sets with {} hashes",
+        sources.len(),
+        hash_algorithm
+    );
+
+    // the merged entries are either written into a single file or into an optimized database
+    let output_folder = matches.value_of("output-folder").map(Path::new);
+    let mut output = match (matches.value_of("output"), output_folder) {
+        (Some(output_path), _) => match File::create(output_path) {
+            Ok(output_file) => MergeOutput::File(output_path, BufWriter::new(output_file)),
+            Err(error) => {
+                error!(
+                    "Could not create the output file {}. The error was: {}",
+                    output_path, error
+                );
+                exit(-4);
+            }
+        },
+        (None, Some(output_folder)) => {
+            if !output_folder.is_dir() {
+                error!(
+                    "The supplied path ('{}') does not exists. Please select an existing folder.",
+                    output_folder.display()
+                );
+                exit(-1);
+            }
+            let prefix_length = match matches.value_of("prefix-length") {
+                Some(value) => match value.parse::<usize>() {
+                    Ok(length) => length,
+                    Err(_) => {
+                        error!("The supplied prefix length ('{}') is not a number.", value);
+                        exit(-2);
+                    }
+                },
+                None => DEFAULT_PREFIX_LENGTH,
+            };
+            match DatabaseManifest::new(
+                prefix_length,
+                matches.is_present("nested"),
+                matches.is_present("binary"),
+                hash_algorithm,
+            ) {
+                Ok(manifest) => {
+                    MergeOutput::Folder(Box::new(FolderWriter::new(output_folder, manifest)))
+                }
+                Err(error) => {
+                    error!(
+                        "The requested layout can not be used. The error was: {}",
+                        error
+                    );
+                    exit(-2);
+                }
+            }
+        }
+        (None, None) => {
+            error!("It seems that neither an output file nor an output folder was provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+
+    // the tags of the sources of every hash are written into a separate file, so the merged
+    // database can still be read by the lookups
+    let mut tags_output = match matches.value_of("tags-output") {
+        Some(tags_path) => match File::create(tags_path) {
+            Ok(tags_file) => Some((tags_path, BufWriter::new(tags_file))),
+            Err(error) => {
+                error!(
+                    "Could not create the file for the tags {}. The error was: {}",
+                    tags_path, error
+                );
+                exit(-4);
+            }
+        },
+        None => None,
+    };
+
+    // merge all files and count how many hashes were found in every source (and in no other one)
+    let mut merged_entries: u64 = 0;
+    let mut source_entries = vec![0u64; sources.len()];
+    let mut exclusive_entries = vec![0u64; sources.len()];
+    for maybe_merged_entry in MergeWalker::new(sources, policy) {
+        let merged_entry = match maybe_merged_entry {
+            Ok(merged_entry) => merged_entry,
+            Err(error) => {
+                error!("{}", error);
+                abort_merge(output, tags_output, -3);
+            }
+        };
+        let write_result =
+            output
+                .write_entry(&merged_entry.entry)
+                .and_then(|_| match &mut tags_output {
+                    Some((_, tags_output)) => {
+                        let tags: Vec<&str> = merged_entry
+                            .sources
+                            .iter()
+                            .map(|&source_index| source_tags[source_index].as_str())
+                            .collect();
+                        writeln!(
+                            tags_output,
+                            "{}:{}",
+                            merged_entry.entry.get_hash(),
+                            tags.join(",")
+                        )
+                    }
+                    None => Ok(()),
+                });
+        if let Err(error) = write_result {
+            error!(
+                "Could not write the merged entries. The error was: {}",
+                error
+            );
+            abort_merge(output, tags_output, -7);
+        }
+
+        merged_entries += 1;
+        for &source_index in &merged_entry.sources {
+            source_entries[source_index] += 1;
+        }
+        if let [source_index] = merged_entry.sources[..] {
+            exclusive_entries[source_index] += 1;
+        }
+    }
+    let finish_result = output.finish().and_then(|_| match &mut tags_output {
+        Some((_, tags_output)) => tags_output.flush(),
+        None => Ok(()),
+    });
+    if let Err(error) = finish_result {
+        error!(
+            "Could not write the merged entries. The error was: {}",
+            error
+        );
+        exit(-7);
+    }
+    progress_bar.finish_with_message("merged");
+
+    for (source_index, tag) in source_tags.iter().enumerate() {
+        println!(
+            "{} ({}): {} hashes, {} of them are not part of any other source",
+            tag,
+            input_paths[source_index],
+            source_entries[source_index],
+            exclusive_entries[source_index]
+        );
+    }
+    println!("The merged database contains {} hashes", merged_entries);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_entries(
+        entries: &[(&str, u64)],
+    ) -> std::vec::IntoIter<Result<PasswordHashEntry, String>> {
+        entries
+            .iter()
+            .map(|&(hash, occurrences)| {
+                let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+                entry.occurrences = occurrences;
+                Ok(entry)
+            })
+            .collect::<Vec<Result<PasswordHashEntry, String>>>()
+            .into_iter()
+    }
+
+    fn merge(policy: CountPolicy) -> Vec<(String, u64, Vec<usize>)> {
+        let sources = vec![
+            get_entries(&[
+                ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+                ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+            ]),
+            get_entries(&[
+                ("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8", 7),
+                ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 2),
+            ]),
+            get_entries(&[("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861494)]),
+        ];
+        MergeWalker::new(sources, policy)
+            .map(|merged_entry| {
+                let merged_entry = merged_entry.unwrap();
+                (
+                    merged_entry.entry.get_hash(),
+                    merged_entry.entry.get_occurrences(),
+                    merged_entry.sources,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_hash_is_merged_just_once() {
+        assert_eq!(
+            vec![
+                (
+                    "000000005AD76BD555C1D6D771DE417A4B87E4B4".to_string(),
+                    4,
+                    vec![0]
+                ),
+                (
+                    "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8".to_string(),
+                    7722994,
+                    vec![0, 1, 2]
+                ),
+                (
+                    "7C4A8D09CA3762AF61E59520943DC26494F8941B".to_string(),
+                    2,
+                    vec![1]
+                ),
+            ],
+            merge(CountPolicy::Sum)
+        );
+        assert_eq!(3861494, merge(CountPolicy::Max)[1].1);
+        assert_eq!(3861493, merge(CountPolicy::PreferFirst)[1].1);
+    }
+
+    #[test]
+    fn the_merge_stops_at_the_first_unreadable_entry() {
+        let mut failing_source =
+            get_entries(&[("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493)])
+                .collect::<Vec<Result<PasswordHashEntry, String>>>();
+        failing_source.push(Err("line 2 is malformed".to_string()));
+        let sources = vec![
+            get_entries(&[
+                ("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4),
+                ("7C4A8D09CA3762AF61E59520943DC26494F8941B", 2),
+            ]),
+            failing_source.into_iter(),
+        ];
+
+        let merged_entries: Vec<Result<String, String>> =
+            MergeWalker::new(sources, CountPolicy::Sum)
+                .map(|merged_entry| merged_entry.map(|merged_entry| merged_entry.entry.get_hash()))
+                .collect();
+        assert_eq!(
+            vec![
+                Ok("000000005AD76BD555C1D6D771DE417A4B87E4B4".to_string()),
+                Err("line 2 is malformed".to_string())
+            ],
+            merged_entries
+        );
+    }
+
+    #[test]
+    fn sources_without_a_tag_are_tagged_with_their_file_name() {
+        assert_eq!(
+            vec!["hibp".to_string(), "incidents-2024".to_string()],
+            get_source_tags(
+                &["/data/pwned-passwords.txt", "/data/incidents-2024.txt.gz"],
+                &["hibp"]
+            )
+        );
+    }
+}
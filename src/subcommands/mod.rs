@@ -1,3 +1,98 @@
+pub mod audit;
+pub mod check;
+pub mod diff;
+pub mod exitcode;
+pub mod export;
+pub mod filterlookup;
 pub mod lookup;
+pub mod merge;
 pub mod optimize;
+pub mod output;
 pub mod quicklookup;
+pub mod serve;
+pub mod stats;
+pub mod update;
+pub mod verify;
+
+use crate::haveibeenpwned::ordered::SortedEntries;
+use crate::haveibeenpwned::ParseError;
+use crate::{HashAlgorithm, PasswordHashEntry};
+use indicatif::ProgressBar;
+use log::error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::process::exit;
+
+/// The error if a password file which has to be ordered by hash could not be read, one of its
+/// lines is malformed or not ordered by hash.
+#[derive(Debug)]
+pub(crate) struct SortedFileError {
+    path_to_file: String,
+    error: ParseError,
+}
+
+impl Display for SortedFileError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Could not read {}. {}", self.path_to_file, self.error)
+    }
+}
+
+/// The entries of a password file which has to be ordered by hash, for the subcommands which walk
+/// through several of these files at the same time. If the file can not be read or one of its
+/// lines is malformed or not ordered by hash, an error is returned and the iteration stops.
+///
+/// The progress of reading the file is added to the supplied progress bar, so the bar can be
+/// shared by all files which are read at the same time.
+pub(crate) struct SortedFileEntries {
+    entries: SortedEntries,
+    path_to_file: String,
+    progress_bar: ProgressBar,
+    reported_bytes: u64,
+}
+
+impl SortedFileEntries {
+    pub(crate) fn from_file(path_to_file: &str, progress_bar: &ProgressBar) -> SortedFileEntries {
+        let entries = match SortedEntries::from_file(path_to_file) {
+            Ok(entries) => entries,
+            Err(error) => {
+                error!(
+                    "Could not use {} as a password file ordered by hash. The error was: {}",
+                    path_to_file, error
+                );
+                exit(-2);
+            }
+        };
+        progress_bar.inc_length(entries.get_file_size().unwrap_or(0));
+
+        SortedFileEntries {
+            entries,
+            path_to_file: path_to_file.to_string(),
+            progress_bar: progress_bar.clone(),
+            reported_bytes: 0,
+        }
+    }
+
+    pub(crate) fn get_hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.entries.get_hash_algorithm()
+    }
+}
+
+impl Iterator for SortedFileEntries {
+    type Item = Result<PasswordHashEntry, SortedFileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.entries.next()? {
+            Ok(entry) => entry,
+            Err(error) => {
+                return Some(Err(SortedFileError {
+                    path_to_file: self.path_to_file.clone(),
+                    error,
+                }))
+            }
+        };
+        let processed_bytes = self.entries.get_processed_bytes();
+        self.progress_bar
+            .inc(processed_bytes.saturating_sub(self.reported_bytes));
+        self.reported_bytes = processed_bytes;
+        Some(Ok(entry))
+    }
+}
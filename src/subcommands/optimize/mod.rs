@@ -1,14 +1,1080 @@
-use crate::haveibeenpwned::DatabaseIterator;
+use crate::haveibeenpwned::checkpoint::{Checkpoint, CheckpointHeader, CheckpointWriter};
+use crate::haveibeenpwned::external_sort::{ExternalSorter, DEFAULT_MEMORY_LIMIT};
+use crate::haveibeenpwned::manifest::{
+    DatabaseManifest, ManifestFileEntry, SourceFile, DEFAULT_PREFIX_LENGTH,
+};
+use crate::haveibeenpwned::optimized::{get_temporary_path, ShardWriter};
+use crate::haveibeenpwned::{DatabaseIterator, EntryOrder, ParseError};
+use crate::subcommands::output::{print_structured_result, OptimizeResult, OutputFormat};
+use crate::{HashAlgorithm, HashDigest, PasswordHashEntry};
 use clap::ArgMatches;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::{debug, error, info};
-use std::cmp::min;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::path::Path;
+use log::{debug, error, info, warn};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Instant;
+
+/// The number of processed bytes after which a worker updates the shared progress bar.
+const PROGRESS_UPDATE_INTERVAL: u64 = 1024 * 1024;
+
+/// The name of the file in the output folder into which a password file is sorted, if it is not
+/// ordered by hash.
+const SORTED_FILE_NAME: &str = "sorted-password-file.txt";
+
+/// What should happen if a line of the password file could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorPolicy {
+    /// Abort the optimization on the first malformed line.
+    Strict,
+    /// Skip (and report) all malformed lines.
+    Skip,
+    /// Skip (and report) malformed lines, but abort if there are more than the supplied number.
+    MaxErrors(u64),
+}
+
+impl ErrorPolicy {
+    /// Get the policy selected by the `--on-error` and `--max-errors` arguments. A maximum number
+    /// of errors implies that malformed lines are skipped.
+    fn from_arguments(matches: &ArgMatches) -> Result<ErrorPolicy, String> {
+        match matches.value_of("max-errors") {
+            Some(value) => match value.parse::<u64>() {
+                Ok(maximum) => Ok(ErrorPolicy::MaxErrors(maximum)),
+                Err(_) => Err(format!(
+                    "The supplied maximum number of errors ('{}') is not a number.",
+                    value
+                )),
+            },
+            None => match matches.value_of("on-error") {
+                Some("skip") => Ok(ErrorPolicy::Skip),
+                _ => Ok(ErrorPolicy::Strict),
+            },
+        }
+    }
+
+    /// Check if the optimization can go on after the supplied number of malformed lines (including
+    /// the current one), e.g. `MaxErrors(2)` allows two malformed lines and fails on the third.
+    fn allows(self, number_of_errors: u64) -> bool {
+        match self {
+            ErrorPolicy::Strict => false,
+            ErrorPolicy::Skip => true,
+            ErrorPolicy::MaxErrors(maximum) => number_of_errors <= maximum,
+        }
+    }
+}
+
+/// The reason why an optimization failed, together with the exit code of the process.
+#[derive(Debug)]
+struct OptimizeError {
+    exit_code: i32,
+    message: String,
+}
+
+impl OptimizeError {
+    fn new(exit_code: i32, message: String) -> OptimizeError {
+        OptimizeError { exit_code, message }
+    }
+}
+
+impl Display for OptimizeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The file (one prefix) of the optimized database which is currently written. It is written
+/// under a temporary name and just renamed to its final name after it was completed, so the
+/// optimized database never contains half-written files.
+struct OutputFile {
+    prefix: String,
+    path: PathBuf,
+    temporary_path: PathBuf,
+    writer: ShardWriter,
+    record_count: u64,
+}
+
+/// Complete the supplied file and record in the checkpoint that the chunk can be resumed from the
+/// supplied offset of the password file, after the supplied number of lines of the chunk were
+/// skipped.
+fn finish_output_file(
+    output_file: OutputFile,
+    chunk_index: usize,
+    resume_offset: u64,
+    skipped_lines: u64,
+    context: &OptimizationContext,
+    file_entries: &mut Vec<ManifestFileEntry>,
+) -> Result<(), OptimizeError> {
+    if output_file.writer.finish().is_err()
+        || std::fs::rename(&output_file.temporary_path, &output_file.path).is_err()
+    {
+        return Err(OptimizeError::new(
+            -7,
+            "Could not finish writing the output file for the optimized data set.".to_string(),
+        ));
+    }
+
+    // record the written file, so it can be verified later on
+    let file_entry = match ManifestFileEntry::from_file(
+        &output_file.prefix,
+        &output_file.path,
+        output_file.record_count,
+    ) {
+        Ok(file_entry) => file_entry,
+        Err(error) => {
+            return Err(OptimizeError::new(
+                -7,
+                format!(
+                    "Could not compute the checksum of {}. The error was: {}",
+                    output_file.path.display(),
+                    error
+                ),
+            ))
+        }
+    };
+    if let Err(error) = context.checkpoint.add_completed_file(
+        chunk_index,
+        resume_offset,
+        skipped_lines,
+        &file_entry,
+    ) {
+        return Err(OptimizeError::new(
+            -7,
+            format!(
+                "Could not update the checkpoint of the optimization. The error was: {}",
+                error
+            ),
+        ));
+    }
+    file_entries.push(file_entry);
+    Ok(())
+}
+
+/// The state which is shared by all threads optimizing a part of the password file.
+struct OptimizationContext<'a> {
+    manifest: &'a DatabaseManifest,
+    output_folder: &'a Path,
+    error_policy: ErrorPolicy,
+    skipped_lines: AtomicU64,
+    abort: AtomicBool,
+    progress_bar: &'a ProgressBar,
+    checkpoint: &'a CheckpointWriter,
+}
+
+/// The result of optimizing a part of the password file.
+struct ChunkResult {
+    file_entries: Vec<ManifestFileEntry>,
+    skipped_lines: Vec<ParseError>,
+    failure: Option<ParseError>,
+    is_unordered: bool,
+}
+
+/// Get the (upper case) prefix of the hash of a line, if the line contains a valid entry.
+fn get_line_prefix(line: &[u8], prefix_length: usize) -> Option<String> {
+    let entry_line = std::str::from_utf8(line).ok()?.trim();
+    let (hash, _) = entry_line.split_once(':')?;
+    PasswordHashEntry::from_hash(hash)
+        .ok()?
+        .get_dynamic_prefix(prefix_length)
+}
+
+/// Find the start of the first line behind the supplied position whose prefix differs from the
+/// prefix of the line in front of it. If there is no such line, the end of the file is returned.
+fn find_prefix_boundary<R: BufRead + Seek>(
+    reader: &mut R,
+    position: u64,
+    prefix_length: usize,
+) -> Result<u64, Error> {
+    reader.seek(SeekFrom::Start(position))?;
+
+    // the position usually points into the middle of a line, so we skip the rest of it
+    let mut line = Vec::new();
+    let mut current_position = position + reader.read_until(b'\n', &mut line)? as u64;
+    let mut last_prefix: Option<String> = None;
+    loop {
+        line.clear();
+        let line_length = reader.read_until(b'\n', &mut line)?;
+        if line_length == 0 {
+            return Ok(current_position);
+        }
+
+        // malformed lines (e.g. empty lines) stay with the lines in front of them
+        if let Some(prefix) = get_line_prefix(&line, prefix_length) {
+            match &last_prefix {
+                Some(last_prefix) if *last_prefix != prefix => return Ok(current_position),
+                _ => last_prefix = Some(prefix),
+            }
+        }
+        current_position += line_length as u64;
+    }
+}
+
+/// Split the (uncompressed) password file into at most the supplied number of byte ranges. Every
+/// range starts at the beginning of a line and all entries with the same prefix are in the same
+/// range, so every file of the optimized database is written by exactly one thread.
+fn split_into_chunks(
+    path_to_file: &Path,
+    number_of_chunks: u64,
+    prefix_length: usize,
+) -> Result<Vec<(u64, u64)>, Error> {
+    let mut reader = BufReader::new(File::open(path_to_file)?);
+    let file_size = reader.get_ref().metadata()?.len();
+
+    let mut boundaries = vec![0];
+    for chunk_index in 1..number_of_chunks {
+        let last_boundary = boundaries[boundaries.len() - 1];
+        let target_position = file_size * chunk_index / number_of_chunks;
+        if target_position <= last_boundary {
+            continue;
+        }
+
+        let boundary = find_prefix_boundary(&mut reader, target_position, prefix_length)?;
+        if boundary > last_boundary && boundary < file_size {
+            boundaries.push(boundary);
+        }
+    }
+    boundaries.push(file_size);
+
+    Ok(boundaries
+        .windows(2)
+        .map(|range| (range[0], range[1]))
+        .collect())
+}
+
+/// Count the lines in front of the supplied position of the file. This is just needed for
+/// reporting the correct line numbers of malformed lines.
+fn count_lines_before(path_to_file: &Path, position: u64) -> Result<u64, Error> {
+    let mut reader = BufReader::new(File::open(path_to_file)?.take(position));
+    let mut number_of_lines = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(number_of_lines);
+        }
+        number_of_lines += buffer.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        let buffer_length = buffer.len();
+        reader.consume(buffer_length);
+    }
+}
+
+/// Write all entries returned by the parser into the files of the optimized database. All lines
+/// in front of the resume offset were already optimized by an interrupted run (which skipped the
+/// supplied number of malformed lines of the chunk), so they are skipped.
+///
+/// If the optimization fails, all other threads are told to stop as well and the file which is
+/// currently written is removed, so the caller can report the error after all threads are done.
+fn optimize_chunk(
+    mut parser: DatabaseIterator,
+    chunk_index: usize,
+    chunk_start: u64,
+    resume_offset: u64,
+    resumed_skipped_lines: u64,
+    context: &OptimizationContext,
+) -> Result<ChunkResult, OptimizeError> {
+    let mut current_output_file: Option<OutputFile> = None;
+    let mut reported_position = chunk_start;
+    let maybe_result = write_chunk_entries(
+        &mut parser,
+        chunk_index,
+        resume_offset,
+        resumed_skipped_lines,
+        context,
+        &mut current_output_file,
+        &mut reported_position,
+    );
+    context.progress_bar.inc(
+        parser
+            .get_processed_bytes()
+            .saturating_sub(reported_position),
+    );
+
+    // a file which was not completed is written again by the next (resumed) run
+    if let Some(output_file) = current_output_file.take() {
+        drop(output_file.writer);
+        let _ = std::fs::remove_file(&output_file.temporary_path);
+    }
+    if maybe_result.is_err() {
+        context.abort.store(true, Ordering::Relaxed);
+    }
+    maybe_result
+}
+
+/// Write the entries of a chunk (see `optimize_chunk`). The file which is currently written is
+/// stored in the supplied option, it is just completed if the whole chunk was written.
+fn write_chunk_entries(
+    parser: &mut DatabaseIterator,
+    chunk_index: usize,
+    resume_offset: u64,
+    resumed_skipped_lines: u64,
+    context: &OptimizationContext,
+    current_output_file: &mut Option<OutputFile>,
+    reported_position: &mut u64,
+) -> Result<ChunkResult, OptimizeError> {
+    let mut result = ChunkResult {
+        file_entries: Vec::new(),
+        skipped_lines: Vec::new(),
+        failure: None,
+        is_unordered: false,
+    };
+    let mut last_prefix = "".to_string();
+    let mut last_digest: Option<HashDigest> = None;
+    let mut line_offset = parser.get_byte_offset();
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        // stop as soon as one of the other threads failed
+        if context.abort.load(Ordering::Relaxed) {
+            return Ok(result);
+        }
+
+        // update the progress bar (based on the compressed bytes for archives) every now and then,
+        // since it is shared by all threads
+        let processed_bytes = parser.get_processed_bytes();
+        if processed_bytes >= *reported_position + PROGRESS_UPDATE_INTERVAL {
+            context
+                .progress_bar
+                .inc(processed_bytes - *reported_position);
+            *reported_position = processed_bytes;
+        }
+
+        // compressed files can not be read from the middle, so the lines which were optimized
+        // before the interruption have to be skipped
+        let entry_offset = line_offset;
+        line_offset = parser.get_byte_offset();
+        let is_fatal = matches!(&maybe_password_hash_entry, Err(error) if error.is_fatal());
+        if line_offset <= resume_offset && !is_fatal {
+            continue;
+        }
+
+        // deal with malformed lines as requested by the user, a file which can not be read any
+        // further always stops the optimization
+        let password_hash_entry = match maybe_password_hash_entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                let skipped_lines = context.skipped_lines.fetch_add(1, Ordering::Relaxed) + 1;
+                if error.is_fatal() || !context.error_policy.allows(skipped_lines) {
+                    context.abort.store(true, Ordering::Relaxed);
+                    result.failure = Some(error);
+                    return Ok(result);
+                }
+                result.skipped_lines.push(error);
+                continue;
+            }
+        };
+
+        // all entries of a prefix have to be written at once (otherwise the file is replaced), so
+        // the caller has to sort the password file if it is not ordered by hash
+        let digest = password_hash_entry.get_digest();
+        if let Some(last_digest) = last_digest.filter(|last_digest| digest < *last_digest) {
+            debug!(
+                "The password file is not ordered by hash ({} follows {})",
+                digest, last_digest
+            );
+            context.abort.store(true, Ordering::Relaxed);
+            result.is_unordered = true;
+            return Ok(result);
+        }
+        last_digest = Some(digest);
+
+        // if the hash prefix changed, we have to change the output file into we which are writing
+        // (the prefix itself is just determined if the hash does not start with the last one)
+        let has_same_prefix = !last_prefix.is_empty()
+            && password_hash_entry
+                .get_digest()
+                .starts_with_hex(&last_prefix);
+        if !has_same_prefix {
+            let current_prefix = match context.manifest.get_prefix(&password_hash_entry) {
+                Some(prefix) => prefix,
+                None => {
+                    return Err(OptimizeError::new(
+                        -6,
+                        "Could not determine the prefix of a password entry.".to_string(),
+                    ))
+                }
+            };
+            if let Some(output_file) = current_output_file.take() {
+                finish_output_file(
+                    output_file,
+                    chunk_index,
+                    entry_offset,
+                    resumed_skipped_lines + result.skipped_lines.len() as u64,
+                    context,
+                    &mut result.file_entries,
+                )?;
+            }
+            let output_file_path = context
+                .manifest
+                .get_file_path(context.output_folder, &current_prefix);
+            let temporary_path = get_temporary_path(&output_file_path);
+            let writer = match ShardWriter::create(
+                &temporary_path,
+                password_hash_entry.get_algorithm(),
+                context.manifest.is_binary(),
+            ) {
+                Ok(writer) => writer,
+                Err(_) => {
+                    return Err(OptimizeError::new(
+                        -5,
+                        "Could not open the output file for the optimized data set.".to_string(),
+                    ))
+                }
+            };
+            *current_output_file = Some(OutputFile {
+                prefix: current_prefix.clone(),
+                path: output_file_path,
+                temporary_path,
+                writer,
+                record_count: 0,
+            });
+            last_prefix = current_prefix;
+        }
+
+        // write the current entry to the file
+        if let Some(output_file) = current_output_file.as_mut() {
+            if output_file
+                .writer
+                .write_entry(&password_hash_entry)
+                .is_err()
+            {
+                return Err(OptimizeError::new(
+                    -6,
+                    "Could not write a password entry into the new file.".to_string(),
+                ));
+            }
+            output_file.record_count += 1;
+        }
+    }
+    if !context.abort.load(Ordering::Relaxed) {
+        if let Some(output_file) = current_output_file.take() {
+            finish_output_file(
+                output_file,
+                chunk_index,
+                parser.get_byte_offset(),
+                resumed_skipped_lines + result.skipped_lines.len() as u64,
+                context,
+                &mut result.file_entries,
+            )?;
+        }
+    }
+    Ok(result)
+}
+
+/// Sort the entries of a password file which is not ordered by hash (e.g. ordered by prevalence)
+/// into a new file, by using not much more than the supplied amount of memory (in MiB). The
+/// number of skipped malformed lines is returned.
+fn sort_password_file(
+    mut parser: DatabaseIterator,
+    error_policy: ErrorPolicy,
+    memory_limit: usize,
+    sorted_file_path: &Path,
+) -> Result<u64, OptimizeError> {
+    let mut sorter =
+        match ExternalSorter::new(&sorted_file_path.with_extension("runs"), memory_limit) {
+            Ok(sorter) => sorter,
+            Err(error) => {
+                return Err(OptimizeError::new(
+                    -7,
+                    format!(
+                    "Could not create the folder for sorting the password file. The error was: {}",
+                    error
+                ),
+                ))
+            }
+        };
+
+    let progress_bar = ProgressBar::new(parser.get_file_size().unwrap_or(0));
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    let mut skipped_lines = 0;
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        match maybe_password_hash_entry {
+            Ok(entry) => {
+                if let Err(error) = sorter.add_entry(&entry) {
+                    return Err(OptimizeError::new(
+                        -7,
+                        format!(
+                        "Could not write the sorted entries to a temporary file. The error was: {}",
+                        error
+                    ),
+                    ));
+                }
+            }
+            Err(error) => {
+                skipped_lines += 1;
+                if error.is_fatal() || !error_policy.allows(skipped_lines) {
+                    error!("Could not read the password file. {}", error);
+                    return Err(OptimizeError::new(-8, "The optimized database is incomplete. Please fix the password file or select a different error policy and optimize it again.".to_string()));
+                }
+                warn!("Skipping a malformed entry of the password file. {}", error);
+            }
+        }
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+    progress_bar.finish_and_clear();
+
+    // merge the sorted runs into a single file which can be optimized like a file ordered by hash
+    debug!(
+        "Merging the sorted parts ({} written so far) into {}",
+        sorter.get_number_of_runs(),
+        sorted_file_path.display()
+    );
+    let sorted_file = match File::create(sorted_file_path) {
+        Ok(sorted_file) => sorted_file,
+        Err(error) => {
+            return Err(OptimizeError::new(
+                -7,
+                format!(
+                    "Could not create the sorted password file. The error was: {}",
+                    error
+                ),
+            ))
+        }
+    };
+    if let Err(error) = sorter.write_sorted(sorted_file) {
+        return Err(OptimizeError::new(
+            -7,
+            format!(
+                "Could not write the sorted password file. The error was: {}",
+                error
+            ),
+        ));
+    }
+
+    Ok(skipped_lines)
+}
+
+/// The settings of an optimization run, as they were supplied on the command line.
+struct OptimizeSettings<'a> {
+    password_hash_path: &'a str,
+    output_folder: &'a str,
+    prefix_length: usize,
+    use_nested_layout: bool,
+    use_binary_format: bool,
+    error_policy: ErrorPolicy,
+    number_of_threads: u64,
+    resume: bool,
+    memory_limit: usize,
+}
+
+/// The summary of a completed optimization run.
+struct OptimizeSummary {
+    hash_algorithm: HashAlgorithm,
+    number_of_files: usize,
+    record_count: u64,
+    skipped_lines: u64,
+}
+
+/// Get an instance of the parser for the supplied password file.
+fn open_password_file(path_to_file: &str) -> Result<DatabaseIterator, OptimizeError> {
+    match DatabaseIterator::from_file(path_to_file) {
+        Ok(parser) => Ok(parser),
+        Err(error) => Err(OptimizeError::new(
+            -3,
+            format!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            ),
+        )),
+    }
+}
+
+/// Sort the password file read by the supplied parser (see `sort_password_file`) and get a parser
+/// for the sorted file, together with the number of skipped malformed lines.
+fn sort_and_open(
+    parser: DatabaseIterator,
+    settings: &OptimizeSettings,
+    sorted_file_path: &Path,
+) -> Result<(DatabaseIterator, u64), OptimizeError> {
+    info!(
+        "The password file is not ordered by hash, sorting it with up to {} MiB of memory",
+        settings.memory_limit
+    );
+    let skipped_lines = sort_password_file(
+        parser,
+        settings.error_policy,
+        settings.memory_limit,
+        sorted_file_path,
+    )?;
+    let parser = open_password_file(&sorted_file_path.to_string_lossy())?;
+    Ok((parser, skipped_lines))
+}
+
+/// The files written by a complete pass over a password file ordered by hash.
+struct CompletedPass {
+    file_entries: Vec<ManifestFileEntry>,
+    skipped_lines: u64,
+    source: SourceFile,
+    checkpoint_writer: CheckpointWriter,
+}
+
+/// The outcome of a pass over a password file which seemed to be ordered by hash.
+enum PassOutcome {
+    Completed(CompletedPass),
+    /// The password file turned out not to be ordered by hash, so it has to be sorted first.
+    Unordered,
+}
+
+/// Optimize the password file read by the supplied parser into the output folder, expecting that
+/// it is ordered by hash. If the input is a sorted copy of the password file, the number of lines
+/// which were skipped while sorting it has to be supplied, so it can be recorded in the checkpoint.
+fn optimize_ordered_file(
+    parser: DatabaseIterator,
+    input_path: &str,
+    sorting_skipped_lines: Option<u64>,
+    manifest: &DatabaseManifest,
+    checkpoint: Option<Checkpoint>,
+    settings: &OptimizeSettings,
+) -> Result<PassOutcome, OptimizeError> {
+    let password_hash_path = settings.password_hash_path;
+    let output_folder = settings.output_folder;
+
+    // try to get the size of the whole password file
+    let file_size = match parser.get_file_size() {
+        Some(size) => size,
+        None => {
+            return Err(OptimizeError::new(
+                -4,
+                "Could not determine the size of the original password file.".to_string(),
+            ))
+        }
+    };
+
+    // split the password file into one part for every thread, archives can just be read from the
+    // beginning to the end, so they are always optimized by a single thread. A resumed optimization
+    // has to use the parts of the interrupted one.
+    let is_compressed = parser.get_compression_format().is_compressed();
+    let chunks = match &checkpoint {
+        Some(checkpoint) => checkpoint.get_header().get_chunks().to_vec(),
+        None if is_compressed || settings.number_of_threads == 1 => vec![(0, file_size)],
+        None => match split_into_chunks(
+            Path::new(input_path),
+            settings.number_of_threads,
+            manifest.get_prefix_length(),
+        ) {
+            Ok(chunks) => chunks,
+            Err(error) => {
+                return Err(OptimizeError::new(
+                    -4,
+                    format!(
+                        "Could not split the password file for the threads. The error was: {}",
+                        error
+                    ),
+                ))
+            }
+        },
+    };
+    if chunks.len() > 1 {
+        info!("Optimizing the password file with {} threads", chunks.len());
+    } else {
+        debug!("Optimizing the password file with a single thread");
+    }
+
+    // record the progress, so the optimization can be resumed if it gets interrupted
+    let maybe_checkpoint_header =
+        CheckpointHeader::new(Path::new(input_path), manifest, chunks.clone()).and_then(
+            |mut header| {
+                if let Some(skipped_lines) = sorting_skipped_lines {
+                    header.set_original_file(Path::new(password_hash_path), skipped_lines)?;
+                }
+                Ok(header)
+            },
+        );
+    let checkpoint_header = match maybe_checkpoint_header {
+        Ok(header) => header,
+        Err(error) => {
+            return Err(OptimizeError::new(
+                -4,
+                format!(
+                    "Could not create the checkpoint of the optimization. The error was: {}",
+                    error
+                ),
+            ))
+        }
+    };
+    let maybe_checkpoint_writer = match &checkpoint {
+        Some(checkpoint) => {
+            if !checkpoint
+                .get_header()
+                .is_compatible_with(&checkpoint_header)
+            {
+                return Err(OptimizeError::new(-2, "The checkpoint in the output folder belongs to a different password file or layout. Please optimize the password file without --resume.".to_string()));
+            }
+            info!("Resuming the interrupted optimization");
+            CheckpointWriter::append(Path::new(output_folder))
+        }
+        None => CheckpointWriter::create(Path::new(output_folder), &checkpoint_header),
+    };
+    let checkpoint_writer = match maybe_checkpoint_writer {
+        Ok(writer) => writer,
+        Err(error) => {
+            return Err(OptimizeError::new(
+                -7,
+                format!(
+                    "Could not write the checkpoint of the optimization. The error was: {}",
+                    error
+                ),
+            ))
+        }
+    };
+
+    // every thread reads its own part of the password file, archives have to be read from the
+    // beginning, uncompressed files can be read from the offset at which the interrupted
+    // optimization stopped
+    let mut parser = Some(parser);
+    let mut chunk_parsers = Vec::with_capacity(chunks.len());
+    for (chunk_index, &(start_offset, end_offset)) in chunks.iter().enumerate() {
+        let resume_offset = checkpoint
+            .as_ref()
+            .and_then(|checkpoint| checkpoint.get_resume_offset(chunk_index))
+            .unwrap_or(start_offset);
+        let maybe_parser = match parser.take() {
+            Some(parser) if is_compressed => Ok(parser),
+            _ => DatabaseIterator::from_file_range(input_path, resume_offset, end_offset),
+        };
+        match maybe_parser {
+            Ok(chunk_parser) => chunk_parsers.push((chunk_parser, start_offset, resume_offset)),
+            Err(error) => {
+                return Err(OptimizeError::new(
+                    -3,
+                    format!(
+                        "Could not get an instance of the parser. The error was: {}",
+                        error
+                    ),
+                ))
+            }
+        }
+    }
+
+    // get an instance from  the progress bar to indicate the optimization progress
+    let progress_bar = ProgressBar::new(file_size);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    // the malformed lines which were skipped before the interruption still count for the error
+    // policy and the summary
+    let resumed_skipped_lines: Vec<u64> = (0..chunks.len())
+        .map(|chunk_index| {
+            checkpoint
+                .as_ref()
+                .map_or(0, |checkpoint| checkpoint.get_skipped_lines(chunk_index))
+        })
+        .collect();
+
+    // start processing (and optimizing) the information stored in the password hash file, while
+    // the checksum of the original file is computed in the background. The threads never end the
+    // process, their errors are reported after all of them were joined.
+    let context = OptimizationContext {
+        manifest,
+        output_folder: Path::new(output_folder),
+        error_policy: settings.error_policy,
+        skipped_lines: AtomicU64::new(resumed_skipped_lines.iter().sum()),
+        abort: AtomicBool::new(false),
+        progress_bar: &progress_bar,
+        checkpoint: &checkpoint_writer,
+    };
+    let (maybe_source, maybe_chunk_results) = thread::scope(|scope| {
+        let source_worker = scope.spawn(|| SourceFile::from_file(Path::new(password_hash_path)));
+        let context = &context;
+        let checkpoint = &checkpoint;
+        let resumed_skipped_lines = &resumed_skipped_lines;
+        let workers: Vec<_> = chunk_parsers
+            .into_iter()
+            .enumerate()
+            .map(
+                |(chunk_index, (chunk_parser, start_offset, resume_offset))| {
+                    let parser_offset = chunk_parser.get_byte_offset();
+                    scope.spawn(move || {
+                        let mut chunk_result = optimize_chunk(
+                            chunk_parser,
+                            chunk_index,
+                            start_offset,
+                            resume_offset,
+                            resumed_skipped_lines[chunk_index],
+                            context,
+                        )?;
+
+                        // the files which were written before the interruption are still part of
+                        // the optimized database
+                        if let Some(checkpoint) = checkpoint {
+                            let mut file_entries = checkpoint.get_completed_files(chunk_index);
+                            file_entries.append(&mut chunk_result.file_entries);
+                            chunk_result.file_entries = file_entries;
+                        }
+                        Ok((parser_offset, chunk_result))
+                    })
+                },
+            )
+            .collect();
+        let chunk_results: Vec<Result<(u64, ChunkResult), OptimizeError>> = workers
+            .into_iter()
+            .map(|worker| match worker.join() {
+                Ok(chunk_result) => chunk_result,
+                Err(_) => Err(OptimizeError::new(
+                    -9,
+                    "One of the threads optimizing the password file crashed.".to_string(),
+                )),
+            })
+            .collect();
+        (source_worker.join(), chunk_results)
+    });
+    let chunk_results = maybe_chunk_results
+        .into_iter()
+        .collect::<Result<Vec<(u64, ChunkResult)>, OptimizeError>>()?;
+
+    // the order of the entries is just checked within the chunks, so every prefix has to follow
+    // the prefixes of the chunks in front of it as well
+    let prefixes: Vec<&str> = chunk_results
+        .iter()
+        .flat_map(|(_, chunk_result)| chunk_result.file_entries.iter())
+        .map(|file_entry| file_entry.get_prefix())
+        .collect();
+    let is_unordered = chunk_results
+        .iter()
+        .any(|(_, chunk_result)| chunk_result.is_unordered)
+        || prefixes.windows(2).any(|pair| pair[0] >= pair[1]);
+    if is_unordered {
+        progress_bar.finish_and_clear();
+        return Ok(PassOutcome::Unordered);
+    }
+
+    // report the malformed lines with their line number in the whole file
+    let mut failed = false;
+    let mut skipped_lines =
+        sorting_skipped_lines.unwrap_or(0) + resumed_skipped_lines.iter().sum::<u64>();
+    let mut file_entries = Vec::new();
+    for (start_offset, chunk_result) in chunk_results {
+        let preceding_lines = if start_offset == 0
+            || (chunk_result.skipped_lines.is_empty() && chunk_result.failure.is_none())
+        {
+            0
+        } else {
+            count_lines_before(Path::new(input_path), start_offset).unwrap_or(0)
+        };
+        for mut skipped_line in chunk_result.skipped_lines {
+            skipped_line.add_preceding_lines(preceding_lines);
+            warn!(
+                "Skipping a malformed entry of the password file. {}",
+                skipped_line
+            );
+            skipped_lines += 1;
+        }
+        if let Some(mut failure) = chunk_result.failure {
+            failure.add_preceding_lines(preceding_lines);
+            error!("Could not read the password file. {}", failure);
+            failed = true;
+        }
+        file_entries.extend(chunk_result.file_entries);
+    }
+    if failed {
+        return Err(OptimizeError::new(-8, "The optimized database is incomplete. Please fix the password file and optimize it again, or continue the optimization with --resume and a different error policy.".to_string()));
+    }
+
+    // record where the optimized database came from
+    let source = match maybe_source {
+        Ok(Ok(source)) => source,
+        Ok(Err(error)) => {
+            return Err(OptimizeError::new(
+                -4,
+                format!(
+                "Could not compute the checksum of the original password file. The error was: {}",
+                error
+            ),
+            ))
+        }
+        Err(_) => {
+            return Err(OptimizeError::new(
+                -9,
+                "The thread computing the checksum of the original password file crashed."
+                    .to_string(),
+            ))
+        }
+    };
+    progress_bar.finish_with_message("optimized");
+
+    Ok(PassOutcome::Completed(CompletedPass {
+        file_entries,
+        skipped_lines,
+        source,
+        checkpoint_writer,
+    }))
+}
+
+/// Optimize the password file into the output folder with the supplied settings.
+fn optimize_password_file(settings: &OptimizeSettings) -> Result<OptimizeSummary, OptimizeError> {
+    let password_hash_path = settings.password_hash_path;
+    let output_folder = settings.output_folder;
+
+    // get an instance of the password parser
+    let parser = open_password_file(password_hash_path)?;
+
+    // tell the user which kind of data set we are going to optimize
+    let hash_algorithm = match parser.get_hash_algorithm() {
+        Some(algorithm) => algorithm,
+        None => {
+            return Err(OptimizeError::new(
+                -3,
+                "Could not detect the type of the hashes in the original password file."
+                    .to_string(),
+            ))
+        }
+    };
+    info!("Detected a data set with {} hashes", hash_algorithm);
+
+    // the manifest tells the lookup how the database was split into files
+    let mut manifest = match DatabaseManifest::new(
+        settings.prefix_length,
+        settings.use_nested_layout,
+        settings.use_binary_format,
+        hash_algorithm,
+    ) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            return Err(OptimizeError::new(
+                -2,
+                format!(
+                    "The requested layout can not be used. The error was: {}",
+                    error
+                ),
+            ))
+        }
+    };
+
+    // the checkpoint of an interrupted optimization tells us which parts were already optimized
+    let checkpoint = if settings.resume {
+        match Checkpoint::from_folder(Path::new(output_folder)) {
+            Ok(Some(checkpoint)) => Some(checkpoint),
+            Ok(None) => {
+                warn!("There is no checkpoint of an interrupted optimization in the output folder, starting from the beginning.");
+                None
+            }
+            Err(error) => {
+                return Err(OptimizeError::new(
+                    -2,
+                    format!(
+                    "Could not read the checkpoint of the interrupted optimization. The error was: {}",
+                    error
+                ),
+                ))
+            }
+        }
+    } else {
+        None
+    };
+
+    // the entries of a prefix are written at once, so a password file which is not ordered by
+    // hash (e.g. ordered by prevalence) has to be sorted first. An interrupted optimization of a
+    // sorted password file continues with the sorted file it already wrote.
+    let sorted_file_path = Path::new(output_folder).join(SORTED_FILE_NAME);
+    let sorted_file_name = sorted_file_path.to_string_lossy().to_string();
+    let checkpoint_uses_sorted_file = checkpoint
+        .iter()
+        .any(|checkpoint| checkpoint.get_header().get_source_file_name() == SORTED_FILE_NAME);
+    let (parser, sorting_skipped_lines) =
+        if checkpoint_uses_sorted_file && sorted_file_path.exists() {
+            let skipped_lines = checkpoint
+                .as_ref()
+                .map_or(0, |checkpoint| checkpoint.get_header().get_skipped_lines());
+            debug!("Reusing the sorted password file of the interrupted optimization");
+            (open_password_file(&sorted_file_name)?, Some(skipped_lines))
+        } else if checkpoint_uses_sorted_file || parser.get_entry_order() == EntryOrder::Unordered {
+            let (parser, skipped_lines) = sort_and_open(parser, settings, &sorted_file_path)?;
+            (parser, Some(skipped_lines))
+        } else {
+            (parser, None)
+        };
+    let input_path = match sorting_skipped_lines {
+        Some(_) => sorted_file_name.as_str(),
+        None => password_hash_path,
+    };
+
+    // the order is just detected from the beginning of the file, so the optimization might still
+    // find out that the password file has to be sorted
+    let completed_pass = match optimize_ordered_file(
+        parser,
+        input_path,
+        sorting_skipped_lines,
+        &manifest,
+        checkpoint,
+        settings,
+    )? {
+        PassOutcome::Completed(completed_pass) => completed_pass,
+        PassOutcome::Unordered if sorting_skipped_lines.is_some() => {
+            return Err(OptimizeError::new(
+                -10,
+                "The sorted password file is not ordered by hash.".to_string(),
+            ))
+        }
+        PassOutcome::Unordered => {
+            let (parser, skipped_lines) = sort_and_open(
+                open_password_file(password_hash_path)?,
+                settings,
+                &sorted_file_path,
+            )?;
+            match optimize_ordered_file(
+                parser,
+                &sorted_file_name,
+                Some(skipped_lines),
+                &manifest,
+                None,
+                settings,
+            )? {
+                PassOutcome::Completed(completed_pass) => completed_pass,
+                PassOutcome::Unordered => {
+                    return Err(OptimizeError::new(
+                        -10,
+                        "The sorted password file is not ordered by hash.".to_string(),
+                    ))
+                }
+            }
+        }
+    };
+
+    // record where the optimized database came from and which files were written
+    manifest.set_source(completed_pass.source);
+    let number_of_files = completed_pass.file_entries.len();
+    for file_entry in completed_pass.file_entries {
+        manifest.add_file(file_entry);
+    }
+    if manifest.write_to_folder(Path::new(output_folder)).is_err() {
+        return Err(OptimizeError::new(
+            -7,
+            "Could not write the manifest of the optimized data set.".to_string(),
+        ));
+    }
+
+    // the optimization is complete, so there is nothing to resume anymore
+    if let Err(error) = completed_pass.checkpoint_writer.remove() {
+        warn!(
+            "Could not remove the checkpoint of the optimization. The error was: {}",
+            error
+        );
+    }
+    if sorted_file_path.exists() {
+        if let Err(error) = std::fs::remove_file(&sorted_file_path) {
+            warn!(
+                "Could not remove the sorted password file. The error was: {}",
+                error
+            );
+        }
+    }
+
+    Ok(OptimizeSummary {
+        hash_algorithm,
+        number_of_files,
+        record_count: manifest.get_record_count(),
+        skipped_lines: completed_pass.skipped_lines,
+    })
+}
 
 pub fn run_subcommand(matches: &ArgMatches) {
+    let optimization_start = Instant::now();
+
     // get the path to the password file
     let password_hash_path = match matches.value_of("password-hashes") {
         Some(path) => path,
@@ -38,96 +1104,480 @@ pub fn run_subcommand(matches: &ArgMatches) {
     };
     debug!("Got {} as the output folder", output_folder);
 
-    // get an instance of the password parser
-    let mut parser = match DatabaseIterator::from_file(password_hash_path) {
-        Ok(parser) => parser,
-        Err(error) => {
-            error!(
-                "Could not get an instance of the parser. The error was: {}",
-                error
-            );
-            exit(-3);
+    // get the format in which the result should be written
+    let output_format = OutputFormat::from_name(matches.value_of("format").unwrap_or("text"))
+        .unwrap_or(OutputFormat::Text);
+
+    // get the layout which should be used for splitting the database into files
+    let prefix_length = match matches.value_of("prefix-length") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(length) => length,
+            Err(_) => {
+                error!("The supplied prefix length ('{}') is not a number.", value);
+                exit(-2);
+            }
+        },
+        None => DEFAULT_PREFIX_LENGTH,
+    };
+
+    // get the policy for dealing with malformed lines in the password file
+    let error_policy = match ErrorPolicy::from_arguments(matches) {
+        Ok(error_policy) => error_policy,
+        Err(message) => {
+            error!("{}", message);
+            exit(-2);
         }
     };
 
-    // try to get the size of the whole password file
-    let file_size = match parser.get_file_size() {
-        Some(size) => size,
-        None => {
-            error!("Could not determine the size of the original password file.");
-            exit(-4);
+    // get the number of threads which should optimize the password file
+    let number_of_threads = match matches.value_of("threads") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(threads) if threads > 0 => threads,
+            _ => {
+                error!("The number of threads has to be a positive number.");
+                exit(-2);
+            }
+        },
+        None => thread::available_parallelism().map_or(1, |threads| threads.get() as u64),
+    };
+
+    // get the amount of memory which can be used for sorting a password file which is not ordered
+    let memory_limit = match matches.value_of("memory-limit") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(limit) if limit > 0 => limit,
+            _ => {
+                error!("The memory limit has to be a positive number of megabytes.");
+                exit(-2);
+            }
+        },
+        None => DEFAULT_MEMORY_LIMIT,
+    };
+
+    let settings = OptimizeSettings {
+        password_hash_path,
+        output_folder,
+        prefix_length,
+        use_nested_layout: matches.is_present("nested"),
+        use_binary_format: matches.is_present("binary"),
+        error_policy,
+        number_of_threads,
+        resume: matches.is_present("resume"),
+        memory_limit,
+    };
+    let summary = match optimize_password_file(&settings) {
+        Ok(summary) => summary,
+        Err(error) => {
+            error!("{}", error);
+            exit(error.exit_code);
         }
     };
+    if summary.skipped_lines > 0 {
+        warn!(
+            "Skipped {} malformed lines of the password file",
+            summary.skipped_lines
+        );
+    }
 
-    // get an instance from  the progress bar to indicate the optimization progress
-    let progress_bar = ProgressBar::new(file_size);
-    progress_bar.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
-        .progress_chars("#>-"));
-    progress_bar.set_draw_delta(1024 * 1024 * 8);
+    // report the optimized database in the requested format
+    match output_format {
+        OutputFormat::Text => println!(
+            "Optimized password database and splitted it into {} files ({} entries)",
+            summary.number_of_files, summary.record_count
+        ),
+        format => {
+            let result = OptimizeResult::new(
+                password_hash_path,
+                output_folder,
+                summary.hash_algorithm,
+                summary.number_of_files,
+                summary.record_count,
+                summary.skipped_lines,
+                optimization_start.elapsed(),
+            );
+            if let Err(error) = print_structured_result(&result, format) {
+                error!("Could not format the result. The error was: {}", error);
+                exit(-3);
+            }
+        }
+    }
+}
 
-    // start processing (and optimizing) the information stored in the password hash file
-    let mut processed_bytes = 0;
-    let mut last_prefix = "".to_string();
-    let mut number_of_subfiles = 0;
-    let mut output_file_name = Path::new(output_folder).join("tmp_file.txt");
-    let mut current_output_file: File = OpenOptions::new()
-        .write(true)
-        .append(false)
-        .read(false)
-        .create(true)
-        .open(output_file_name)
-        .unwrap();
-    while processed_bytes < file_size {
-        // get the entry or exit the loop if there is no next entry
-        let password_hash_entry = match parser.next() {
-            Some(entry) => entry,
-            None => break,
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use std::collections::BTreeMap;
 
-        // if the hash prefix changed, we have to change the output file into we which are writing
-        let current_prefix = password_hash_entry.get_prefix();
-        if !last_prefix.eq_ignore_ascii_case(current_prefix.as_str()) {
-            output_file_name = Path::new(output_folder).join(format!("{}.txt", current_prefix));
-            current_output_file = match OpenOptions::new()
-                .write(true)
-                .append(false)
-                .read(false)
-                .create(true)
-                .open(output_file_name)
-            {
-                Ok(file_handle) => file_handle,
-                Err(_) => {
-                    error!("Could not open the output file for the optimized data set.");
-                    exit(-5);
+    /// Get the lines of a password file (ordered by hash) with the supplied number of entries.
+    fn get_sorted_lines(number_of_entries: usize) -> Vec<String> {
+        let mut lines: Vec<String> = (0..number_of_entries)
+            .map(|index| {
+                let entry = PasswordHashEntry::from_password(&format!("password{}", index));
+                format!("{}:{}\r\n", entry.get_hash(), index % 97 + 1)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    fn get_settings<'a>(
+        password_hash_path: &'a str,
+        output_folder: &'a str,
+        number_of_threads: u64,
+    ) -> OptimizeSettings<'a> {
+        OptimizeSettings {
+            password_hash_path,
+            output_folder,
+            prefix_length: 3,
+            use_nested_layout: false,
+            use_binary_format: false,
+            error_policy: ErrorPolicy::Strict,
+            number_of_threads,
+            resume: false,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+
+    /// Get the content of all files in the supplied folder, by their path relative to the folder.
+    fn read_folder(folder: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        let mut folders = vec![folder.to_path_buf()];
+        while let Some(current_folder) = folders.pop() {
+            for directory_entry in std::fs::read_dir(&current_folder).unwrap() {
+                let path = directory_entry.unwrap().path();
+                if path.is_dir() {
+                    folders.push(path);
+                } else {
+                    let relative_path = path.strip_prefix(folder).unwrap().to_path_buf();
+                    files.insert(relative_path, std::fs::read(&path).unwrap());
                 }
-            };
-            number_of_subfiles += 1;
-            last_prefix = current_prefix;
+            }
         }
+        files
+    }
 
-        // write the current entry to the file
-        let _ = match current_output_file.write(password_hash_entry.get_line_to_write().as_bytes())
-        {
-            Ok(count) => count,
-            Err(_) => {
-                error!("Could not write a password entry into the new file.");
-                exit(-6);
+    /// Check that both folders contain the same optimized database (apart from its source).
+    fn assert_same_database(expected_folder: &Path, actual_folder: &Path) {
+        let mut expected_files = read_folder(expected_folder);
+        let mut actual_files = read_folder(actual_folder);
+        expected_files.remove(Path::new("manifest.json"));
+        actual_files.remove(Path::new("manifest.json"));
+        assert_eq!(
+            expected_files.keys().collect::<Vec<_>>(),
+            actual_files.keys().collect::<Vec<_>>()
+        );
+        for (path, content) in &expected_files {
+            assert_eq!(
+                true,
+                *content == actual_files[path],
+                "{} differs",
+                path.display()
+            );
+        }
+
+        let expected_manifest = DatabaseManifest::from_folder(expected_folder)
+            .unwrap()
+            .unwrap();
+        let actual_manifest = DatabaseManifest::from_folder(actual_folder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected_manifest.get_files(), actual_manifest.get_files());
+    }
+
+    #[test]
+    fn chunks_are_aligned_on_prefix_boundaries() {
+        let path_to_file = TemporaryPath::new("chunks.txt");
+        let mut content = String::new();
+        for index in 0..4096 {
+            content.push_str(&format!(
+                "{:03X}{}:{}\r\n",
+                index / 16,
+                "0".repeat(37),
+                index
+            ));
+            if index == 2000 {
+                content.push_str("deadbeef\r\n");
             }
+        }
+        std::fs::write(&path_to_file, &content).unwrap();
+
+        let chunks = split_into_chunks(&path_to_file, 7, 3).unwrap();
+        assert_eq!(7, chunks.len());
+        assert_eq!(0, chunks[0].0);
+        assert_eq!(content.len() as u64, chunks[chunks.len() - 1].1);
+        for (first_chunk, second_chunk) in chunks.iter().zip(chunks.iter().skip(1)) {
+            assert_eq!(first_chunk.1, second_chunk.0);
+
+            // the lines in front of and behind the boundary have different prefixes
+            let boundary = second_chunk.0 as usize;
+            let previous_line = content[..boundary - 2].rsplit("\r\n").next().unwrap();
+            assert_eq!(
+                true,
+                previous_line[..3] != content[boundary..boundary + 3],
+                "{} and {} share a prefix",
+                previous_line,
+                &content[boundary..boundary + 3]
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_lines_do_not_have_a_prefix() {
+        assert_eq!(
+            Some("5BAA".to_string()),
+            get_line_prefix(b"5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8:3\r\n", 4)
+        );
+        assert_eq!(None, get_line_prefix(b"deadbeef\r\n", 4));
+        assert_eq!(None, get_line_prefix(b"\r\n", 4));
+    }
+
+    #[test]
+    fn the_output_does_not_depend_on_the_number_of_threads() {
+        let password_file = TemporaryPath::new("threads.txt");
+        std::fs::write(&password_file, get_sorted_lines(5000).concat()).unwrap();
+        let password_file_name = password_file.to_string_lossy().to_string();
+
+        let mut folders = Vec::new();
+        for number_of_threads in [1, 4] {
+            let output_folder =
+                TemporaryPath::new_folder(&format!("threads-{}", number_of_threads));
+            let output_folder_name = output_folder.to_string_lossy().to_string();
+            let summary = optimize_password_file(&get_settings(
+                &password_file_name,
+                &output_folder_name,
+                number_of_threads,
+            ))
+            .unwrap();
+            assert_eq!(5000, summary.record_count);
+            folders.push(output_folder);
+        }
+
+        let single_thread_files = read_folder(&folders[0]);
+        let multi_thread_files = read_folder(&folders[1]);
+        assert_eq!(
+            true,
+            single_thread_files.contains_key(Path::new("manifest.json"))
+        );
+        assert_eq!(
+            single_thread_files.keys().collect::<Vec<_>>(),
+            multi_thread_files.keys().collect::<Vec<_>>()
+        );
+        for (path, content) in &single_thread_files {
+            assert_eq!(
+                true,
+                *content == multi_thread_files[path],
+                "{} differs",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn a_shuffled_password_file_is_optimized_like_its_sorted_twin() {
+        let sorted_lines = get_sorted_lines(2000);
+        let mut shuffled_lines = sorted_lines.clone();
+        // ordered by prevalence, like the files published by HIBP
+        shuffled_lines
+            .sort_by_key(|line| std::cmp::Reverse(line[41..].trim().parse::<u64>().unwrap()));
+        let mut folders = Vec::new();
+        for (name, lines) in [("sorted", &sorted_lines), ("shuffled", &shuffled_lines)] {
+            let password_file = TemporaryPath::new(&format!("twin-{}.txt", name));
+            std::fs::write(&password_file, lines.concat()).unwrap();
+            let output_folder = TemporaryPath::new_folder(&format!("twin-{}", name));
+            let summary = optimize_password_file(&get_settings(
+                &password_file.to_string_lossy(),
+                &output_folder.to_string_lossy(),
+                2,
+            ))
+            .unwrap();
+            assert_eq!(2000, summary.record_count);
+            assert_eq!(false, output_folder.join(SORTED_FILE_NAME).exists());
+            folders.push(output_folder);
+        }
+
+        assert_same_database(&folders[0], &folders[1]);
+    }
+
+    #[test]
+    fn a_password_file_unordered_behind_the_detected_part_is_sorted() {
+        // the order is detected from the first decompressed buffer of the archive, so the
+        // misplaced entries have to be far behind it
+        let mut lines: Vec<String> = (0..50_000u64)
+            .map(|index| {
+                format!(
+                    "{:08X}{:032X}:{}\r\n",
+                    index * 85_899,
+                    index,
+                    index % 97 + 1
+                )
+            })
+            .collect();
+        let sorted_file = TemporaryPath::new("late-sorted.txt");
+        std::fs::write(&sorted_file, lines.concat()).unwrap();
+        let misplaced_lines: Vec<String> = lines.drain(1000..1100).collect();
+        lines.extend(misplaced_lines);
+        let unordered_file = TemporaryPath::new("late-unordered.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&unordered_file).unwrap(),
+            flate2::Compression::default(),
+        );
+        std::io::Write::write_all(&mut encoder, lines.concat().as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(
+            true,
+            DatabaseIterator::from_file(&unordered_file.to_string_lossy())
+                .unwrap()
+                .get_entry_order()
+                == EntryOrder::ByHash
+        );
+
+        let mut folders = Vec::new();
+        for (name, password_file) in [("sorted", &sorted_file), ("unordered", &unordered_file)] {
+            let output_folder = TemporaryPath::new_folder(&format!("late-{}", name));
+            let summary = optimize_password_file(&get_settings(
+                &password_file.to_string_lossy(),
+                &output_folder.to_string_lossy(),
+                1,
+            ))
+            .unwrap();
+            assert_eq!(50_000, summary.record_count);
+            folders.push(output_folder);
+        }
+
+        assert_same_database(&folders[0], &folders[1]);
+    }
+
+    #[test]
+    fn a_resumed_optimization_reuses_the_sorted_password_file() {
+        let sorted_lines = get_sorted_lines(500);
+        let mut shuffled_lines = sorted_lines.clone();
+        shuffled_lines.reverse();
+        let password_file = TemporaryPath::new("resume-sorted.txt");
+        std::fs::write(&password_file, shuffled_lines.concat()).unwrap();
+        let output_folder = TemporaryPath::new_folder("resume-sorted");
+
+        // the interrupted run sorted the password file, the last entry is left out to tell whether
+        // the password file is sorted again
+        let sorted_file = output_folder.join(SORTED_FILE_NAME);
+        std::fs::write(&sorted_file, sorted_lines[..499].concat()).unwrap();
+        let sorted_file_size = std::fs::metadata(&sorted_file).unwrap().len();
+        let manifest = DatabaseManifest::new(3, false, false, HashAlgorithm::Sha1).unwrap();
+        let mut header =
+            CheckpointHeader::new(&sorted_file, &manifest, vec![(0, sorted_file_size)]).unwrap();
+        header.set_original_file(&password_file, 7).unwrap();
+        CheckpointWriter::create(&output_folder, &header).unwrap();
+
+        let password_file_name = password_file.to_string_lossy();
+        let output_folder_name = output_folder.to_string_lossy();
+        let mut settings = get_settings(&password_file_name, &output_folder_name, 1);
+        settings.resume = true;
+        let summary = optimize_password_file(&settings).unwrap();
+        assert_eq!(499, summary.record_count);
+        assert_eq!(7, summary.skipped_lines);
+        assert_eq!(false, sorted_file.exists());
+    }
+
+    #[test]
+    fn a_resumed_optimization_writes_the_same_database_as_an_uninterrupted_one() {
+        let mut lines = get_sorted_lines(3000);
+        lines.insert(2500, "not a hash\r\n".to_string());
+        lines.insert(100, "deadbeef\r\n".to_string());
+        let password_file = TemporaryPath::new("interrupted.txt");
+        std::fs::write(&password_file, lines.concat()).unwrap();
+        let password_file_name = password_file.to_string_lossy();
+
+        let uninterrupted_folder = TemporaryPath::new_folder("uninterrupted");
+        let uninterrupted_folder_name = uninterrupted_folder.to_string_lossy();
+        let mut settings = get_settings(&password_file_name, &uninterrupted_folder_name, 1);
+        settings.error_policy = ErrorPolicy::Skip;
+        let summary = optimize_password_file(&settings).unwrap();
+        assert_eq!(3000, summary.record_count);
+        assert_eq!(2, summary.skipped_lines);
+
+        // the second malformed line interrupts the optimization
+        let interrupted_folder = TemporaryPath::new_folder("interrupted");
+        let interrupted_folder_name = interrupted_folder.to_string_lossy();
+        let mut settings = get_settings(&password_file_name, &interrupted_folder_name, 1);
+        settings.error_policy = ErrorPolicy::MaxErrors(1);
+        let error = optimize_password_file(&settings).err().unwrap();
+        assert_eq!(-8, error.exit_code);
+        let checkpoint = Checkpoint::from_folder(&interrupted_folder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, checkpoint.get_skipped_lines(0));
+        assert_eq!(true, checkpoint.get_resume_offset(0).is_some());
+
+        settings.error_policy = ErrorPolicy::Skip;
+        settings.resume = true;
+        let summary = optimize_password_file(&settings).unwrap();
+        assert_eq!(3000, summary.record_count);
+        assert_eq!(2, summary.skipped_lines);
+        assert_same_database(&uninterrupted_folder, &interrupted_folder);
+    }
+
+    #[test]
+    fn error_policies_allow_the_configured_number_of_errors() {
+        assert_eq!(false, ErrorPolicy::Strict.allows(1));
+        assert_eq!(true, ErrorPolicy::Skip.allows(1));
+        assert_eq!(true, ErrorPolicy::Skip.allows(u64::MAX));
+        assert_eq!(false, ErrorPolicy::MaxErrors(0).allows(1));
+        assert_eq!(true, ErrorPolicy::MaxErrors(3).allows(3));
+        assert_eq!(false, ErrorPolicy::MaxErrors(3).allows(4));
+    }
+
+    #[test]
+    fn error_policies_are_read_from_the_arguments() {
+        let configuration = clap::load_yaml!("../../bin/cli.yml");
+        let get_error_policy = |arguments: &[&str]| {
+            let mut command_line = vec!["pwned-rs", "optimize", "hashes.txt", "folder"];
+            command_line.extend_from_slice(arguments);
+            let matches = clap::App::from_yaml(configuration)
+                .get_matches_from_safe(command_line)
+                .map_err(|error| error.to_string())?;
+            ErrorPolicy::from_arguments(matches.subcommand_matches("optimize").unwrap())
         };
 
-        // set the new current position for the progress bar
-        let new = min(
-            processed_bytes + password_hash_entry.get_size_in_bytes(),
-            file_size,
+        assert_eq!(Ok(ErrorPolicy::Strict), get_error_policy(&[]));
+        assert_eq!(
+            Ok(ErrorPolicy::Strict),
+            get_error_policy(&["--on-error", "strict"])
+        );
+        assert_eq!(
+            Ok(ErrorPolicy::Skip),
+            get_error_policy(&["--on-error", "skip"])
+        );
+        assert_eq!(
+            Ok(ErrorPolicy::MaxErrors(5)),
+            get_error_policy(&["--max-errors", "5"])
         );
-        processed_bytes = new;
-        progress_bar.set_position(processed_bytes);
+        assert_eq!(
+            Ok(ErrorPolicy::MaxErrors(0)),
+            get_error_policy(&["--on-error", "skip", "--max-errors", "0"])
+        );
+        assert_eq!(true, get_error_policy(&["--max-errors", "five"]).is_err());
+        assert_eq!(true, get_error_policy(&["--max-errors", "-1"]).is_err());
+        assert_eq!(true, get_error_policy(&["--on-error", "ignore"]).is_err());
     }
-    progress_bar.finish_with_message("optimized");
 
-    info!(
-        "Optimized password database and splitted it into {} files",
-        number_of_subfiles
-    );
+    #[test]
+    fn the_maximum_number_of_errors_is_allowed() {
+        let mut lines = get_sorted_lines(200);
+        lines.insert(150, "deadbeef\r\n".to_string());
+        lines.insert(50, "not a hash\r\n".to_string());
+        let password_file = TemporaryPath::new("max-errors.txt");
+        std::fs::write(&password_file, lines.concat()).unwrap();
+        let password_file_name = password_file.to_string_lossy();
+        let output_folder = TemporaryPath::new_folder("max-errors");
+        let output_folder_name = output_folder.to_string_lossy();
+
+        let mut settings = get_settings(&password_file_name, &output_folder_name, 1);
+        settings.error_policy = ErrorPolicy::MaxErrors(1);
+        let error = optimize_password_file(&settings).err().unwrap();
+        assert_eq!(-8, error.exit_code);
+
+        settings.error_policy = ErrorPolicy::MaxErrors(2);
+        let summary = optimize_password_file(&settings).unwrap();
+        assert_eq!(200, summary.record_count);
+        assert_eq!(2, summary.skipped_lines);
+    }
 }
@@ -0,0 +1,241 @@
+use crate::haveibeenpwned::policy::Verdict;
+use crate::HashAlgorithm;
+use serde::Serialize;
+use std::time::Duration;
+
+/// The formats in which the subcommands can report their results on the standard output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// A sentence which is meant to be read by humans.
+    Text,
+    /// A single JSON object per result.
+    Json,
+    /// A header line followed by one line per result.
+    Csv,
+}
+
+impl OutputFormat {
+    /// Get the output format for the name used on the command line.
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// A result which can be written in one of the machine readable output formats.
+pub trait StructuredResult: Serialize {
+    /// Get the names of the CSV columns, separated by commas.
+    fn get_csv_header() -> &'static str;
+
+    /// Get the values of the CSV columns, in the same order as the header.
+    fn get_csv_row(&self) -> String;
+}
+
+/// Write the supplied result in a machine readable format on the standard output. The text
+/// format differs from subcommand to subcommand, so it has to be written by the subcommands
+/// themselves.
+pub fn print_structured_result<R: StructuredResult>(
+    result: &R,
+    format: OutputFormat,
+) -> Result<(), serde_json::Error> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string(result)?),
+        OutputFormat::Csv => {
+            println!("{}", R::get_csv_header());
+            println!("{}", result.get_csv_row());
+        }
+        OutputFormat::Text => {}
+    }
+    Ok(())
+}
+
+/// Get the duration in milliseconds, as it is reported in the results.
+fn get_milliseconds(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+/// The result of looking up a single password.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LookupResult {
+    hash: String,
+    algorithm: HashAlgorithm,
+    found: bool,
+    count: Option<u64>,
+    verdict: &'static str,
+    source: String,
+    duration_ms: f64,
+}
+
+impl LookupResult {
+    pub fn new(
+        hash: &str,
+        algorithm: HashAlgorithm,
+        verdict: Verdict,
+        source: &str,
+        duration: Duration,
+    ) -> LookupResult {
+        LookupResult {
+            hash: hash.to_uppercase(),
+            algorithm,
+            found: verdict.get_occurrences().is_some(),
+            count: verdict.get_occurrences(),
+            verdict: match verdict {
+                Verdict::NotFound => "accept",
+                Verdict::Warn(_) => "warn",
+                Verdict::Reject(_) => "reject",
+            },
+            source: source.to_string(),
+            duration_ms: get_milliseconds(duration),
+        }
+    }
+}
+
+impl StructuredResult for LookupResult {
+    fn get_csv_header() -> &'static str {
+        "hash,algorithm,found,count,verdict,source,duration_ms"
+    }
+
+    fn get_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{:.3}",
+            self.hash,
+            self.algorithm.get_name(),
+            self.found,
+            self.count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            self.verdict,
+            get_csv_field(&self.source),
+            self.duration_ms
+        )
+    }
+}
+
+/// The result of optimizing a password file.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OptimizeResult {
+    source: String,
+    output_folder: String,
+    algorithm: HashAlgorithm,
+    files: usize,
+    entries: u64,
+    skipped_lines: u64,
+    duration_ms: f64,
+}
+
+impl OptimizeResult {
+    pub fn new(
+        source: &str,
+        output_folder: &str,
+        algorithm: HashAlgorithm,
+        files: usize,
+        entries: u64,
+        skipped_lines: u64,
+        duration: Duration,
+    ) -> OptimizeResult {
+        OptimizeResult {
+            source: source.to_string(),
+            output_folder: output_folder.to_string(),
+            algorithm,
+            files,
+            entries,
+            skipped_lines,
+            duration_ms: get_milliseconds(duration),
+        }
+    }
+}
+
+impl StructuredResult for OptimizeResult {
+    fn get_csv_header() -> &'static str {
+        "source,output_folder,algorithm,files,entries,skipped_lines,duration_ms"
+    }
+
+    fn get_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{:.3}",
+            get_csv_field(&self.source),
+            get_csv_field(&self.output_folder),
+            self.algorithm.get_name(),
+            self.files,
+            self.entries,
+            self.skipped_lines,
+            self.duration_ms
+        )
+    }
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+fn get_csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_results_contain_the_verdict() {
+        let result = LookupResult::new(
+            "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8",
+            HashAlgorithm::Sha1,
+            Verdict::Reject(3861493),
+            "/tmp/pwned, \"passwords\".txt",
+            Duration::from_micros(1500),
+        );
+        assert_eq!(
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8,sha1,true,3861493,reject,\"/tmp/pwned, \"\"passwords\"\".txt\",1.500",
+            result.get_csv_row()
+        );
+        assert_eq!(
+            "{\"hash\":\"5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8\",\"algorithm\":\"sha1\",\"found\":true,\"count\":3861493,\"verdict\":\"reject\",\"source\":\"/tmp/pwned, \\\"passwords\\\".txt\",\"duration_ms\":1.5}",
+            serde_json::to_string(&result).unwrap()
+        );
+
+        let result = LookupResult::new(
+            "0000000000000000000000000000000000000000",
+            HashAlgorithm::Sha1,
+            Verdict::NotFound,
+            "passwords.txt",
+            Duration::from_millis(2),
+        );
+        assert_eq!(
+            "0000000000000000000000000000000000000000,sha1,false,,accept,passwords.txt,2.000",
+            result.get_csv_row()
+        );
+        assert_eq!(
+            true,
+            serde_json::to_string(&result)
+                .unwrap()
+                .contains("\"found\":false,\"count\":null")
+        );
+    }
+
+    #[test]
+    fn optimize_results_contain_the_summary() {
+        let result = OptimizeResult::new(
+            "/tmp/pwned-passwords-ntlm.txt",
+            "/tmp/optimized, ntlm",
+            HashAlgorithm::Ntlm,
+            4096,
+            1234567,
+            2,
+            Duration::from_secs(3),
+        );
+        assert_eq!(
+            "/tmp/pwned-passwords-ntlm.txt,\"/tmp/optimized, ntlm\",ntlm,4096,1234567,2,3000.000",
+            result.get_csv_row()
+        );
+        assert_eq!(
+            "{\"source\":\"/tmp/pwned-passwords-ntlm.txt\",\"output_folder\":\"/tmp/optimized, ntlm\",\"algorithm\":\"ntlm\",\"files\":4096,\"entries\":1234567,\"skipped_lines\":2,\"duration_ms\":3000.0}",
+            serde_json::to_string(&result).unwrap()
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use crate::haveibeenpwned::database::PasswordDatabase;
+use crate::haveibeenpwned::optimized::OptimizedDatabase;
+use crate::haveibeenpwned::SortedHashFile;
+use crate::HashAlgorithm;
+use clap::ArgMatches;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::exit;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The length of the hash prefix which has to be supplied for a range request.
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/// The minimum and maximum number of entries of a padded response.
+const MINIMUM_PADDED_ENTRIES: usize = 800;
+const MAXIMUM_PADDED_ENTRIES: usize = 1000;
+
+/// The number of files of an optimized database whose readers are kept open between requests.
+const SHARD_CACHE_SIZE: usize = 256;
+
+/// The database which is used for answering the range requests for one hash algorithm.
+pub(crate) type RangeDatabase = Box<dyn PasswordDatabase + Send + Sync>;
+
+/// The databases which are used for answering the range requests for SHA-1 and NTLM hashes.
+pub(crate) struct RangeDatabases {
+    sha1: Option<RangeDatabase>,
+    ntlm: Option<RangeDatabase>,
+}
+
+/// Build the response for a request to the supplied URL. Returns the status code and the body.
+fn get_range_response(databases: &RangeDatabases, url: &str, add_padding: bool) -> (u16, String) {
+    // split the URL into the path and the query
+    let mut url_parts = url.splitn(2, '?');
+    let path = url_parts.next().unwrap_or("");
+    let query = url_parts.next().unwrap_or("");
+
+    // the only endpoint we are supporting is the range endpoint
+    let prefix = match path.strip_prefix("/range/") {
+        Some(prefix) => prefix,
+        None => return (404, "Not found".to_string()),
+    };
+    if prefix.len() != RANGE_PREFIX_LENGTH || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return (400, "The hash prefix was not in a valid format".to_string());
+    }
+
+    // select the database based on the requested mode
+    let mode = query
+        .split('&')
+        .filter_map(|parameter| parameter.strip_prefix("mode="))
+        .next()
+        .unwrap_or("sha1");
+    let (database, hash_algorithm) = match HashAlgorithm::from_name(mode) {
+        Some(HashAlgorithm::Sha1) => (databases.sha1.as_ref(), HashAlgorithm::Sha1),
+        Some(HashAlgorithm::Ntlm) => (databases.ntlm.as_ref(), HashAlgorithm::Ntlm),
+        None => return (400, "The mode was not in a valid format".to_string()),
+    };
+    let database = match database {
+        Some(database) => database,
+        None => {
+            return (
+                400,
+                format!(
+                    "There is no database for {} hashes available",
+                    hash_algorithm
+                ),
+            )
+        }
+    };
+
+    // get all entries of the requested range
+    let entries = match database.get_range(prefix) {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!(
+                "Could not read the range {} from the database. The error was: {}",
+                prefix, error
+            );
+            return (
+                500,
+                "Could not read the range from the database".to_string(),
+            );
+        }
+    };
+    let mut lines: Vec<(String, u64)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.get_hash()[RANGE_PREFIX_LENGTH..].to_string(),
+                entry.get_occurrences(),
+            )
+        })
+        .collect();
+
+    // if requested, pad the response with random entries which did not occur in any breach
+    if add_padding {
+        let mut random_generator = rand::thread_rng();
+        let suffix_length = hash_algorithm.get_hash_length() - RANGE_PREFIX_LENGTH;
+        let padded_entries =
+            random_generator.gen_range(MINIMUM_PADDED_ENTRIES..=MAXIMUM_PADDED_ENTRIES);
+        let mut known_suffixes: HashSet<String> =
+            lines.iter().map(|(suffix, _)| suffix.clone()).collect();
+        while lines.len() < padded_entries {
+            let suffix: String = (0..suffix_length)
+                .map(|_| {
+                    std::char::from_digit(random_generator.gen_range(0..16), 16)
+                        .unwrap_or('0')
+                        .to_ascii_uppercase()
+                })
+                .collect();
+            if known_suffixes.insert(suffix.clone()) {
+                lines.push((suffix, 0));
+            }
+        }
+        lines.sort();
+    }
+
+    let body = lines
+        .iter()
+        .map(|(suffix, occurrences)| format!("{}:{}", suffix, occurrences))
+        .collect::<Vec<String>>()
+        .join("\r\n");
+    (200, body)
+}
+
+/// Answer a single request of a client.
+fn handle_request(databases: &RangeDatabases, request: Request) {
+    let (status_code, body) = match request.method() {
+        Method::Get => {
+            let add_padding = request.headers().iter().any(|header| {
+                header.field.equiv("Add-Padding")
+                    && header.value.as_str().eq_ignore_ascii_case("true")
+            });
+            get_range_response(databases, request.url(), add_padding)
+        }
+        _ => (405, "Method not allowed".to_string()),
+    };
+    debug!("{} {} -> {}", request.method(), request.url(), status_code);
+
+    let response = Response::from_string(body)
+        .with_status_code(status_code)
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"text/plain"[..])
+                .expect("the content type header is valid"),
+        );
+    if let Err(error) = request.respond(response) {
+        warn!(
+            "Could not send the response to the client. The error was: {}",
+            error
+        );
+    }
+}
+
+/// Answer all requests to the supplied server until it gets unblocked.
+pub(crate) fn serve_requests(server: &Server, databases: &RangeDatabases) {
+    for request in server.incoming_requests() {
+        handle_request(databases, request);
+    }
+}
+
+/// Open the database for a hash algorithm, either from a sorted password file or an optimized folder.
+fn open_range_database(
+    password_database: Option<&str>,
+    optimized_db_folder: Option<&str>,
+) -> Option<RangeDatabase> {
+    if let Some(path) = password_database {
+        return match SortedHashFile::from_file(Path::new(path)) {
+            Ok(sorted_hash_file) => Some(Box::new(sorted_hash_file)),
+            Err(error) => {
+                error!(
+                    "Could not open {} for looking up passwords. The error was: {}",
+                    path, error
+                );
+                exit(-2);
+            }
+        };
+    }
+    if let Some(folder) = optimized_db_folder {
+        return match OptimizedDatabase::from_folder(Path::new(folder)) {
+            Ok(optimized_database) => Some(Box::new(
+                optimized_database.with_shard_cache(SHARD_CACHE_SIZE),
+            )),
+            Err(error) => {
+                error!(
+                    "Could not open {} for looking up passwords. The error was: {}",
+                    folder, error
+                );
+                exit(-2);
+            }
+        };
+    }
+    None
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // open the databases for the different hash types
+    let databases = RangeDatabases {
+        sha1: open_range_database(
+            matches.value_of("password-database"),
+            matches.value_of("optimized-db-folder"),
+        ),
+        ntlm: open_range_database(
+            matches.value_of("ntlm-password-database"),
+            matches.value_of("ntlm-optimized-db-folder"),
+        ),
+    };
+    if databases.sha1.is_none() && databases.ntlm.is_none() {
+        error!("It seems that no password database was provided, please see the help for usage instructions.");
+        exit(-1);
+    }
+
+    // get the number of threads which should answer the requests
+    let number_of_threads = match matches.value_of("threads").unwrap_or("4").parse::<usize>() {
+        Ok(threads) if threads > 0 => threads,
+        _ => {
+            error!("The number of threads has to be a positive number.");
+            exit(-1);
+        }
+    };
+
+    // start the server on the selected address
+    let listen_address = matches.value_of("listen").unwrap_or("127.0.0.1:8080");
+    let server = match Server::http(listen_address) {
+        Ok(server) => Arc::new(server),
+        Err(error) => {
+            error!(
+                "Could not start the server on {}. The error was: {}",
+                listen_address, error
+            );
+            exit(-3);
+        }
+    };
+    info!(
+        "Answering range requests on http://{}/range/{{prefix}}",
+        listen_address
+    );
+
+    // answer the requests with the selected number of threads
+    let databases = Arc::new(databases);
+    let workers: Vec<_> = (0..number_of_threads)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let databases = Arc::clone(&databases);
+            thread::spawn(move || serve_requests(&server, &databases))
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TemporaryPath;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn get_databases(name: &str) -> (TemporaryPath, RangeDatabases) {
+        let file_path = TemporaryPath::new(name);
+        std::fs::write(
+            &file_path,
+            "000000005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n\
+             00000000A8DAE4228F821FB418F59826079BF368:2\r\n\
+             5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n",
+        )
+        .unwrap();
+        let databases = RangeDatabases {
+            sha1: Some(Box::new(SortedHashFile::from_file(&file_path).unwrap())),
+            ntlm: None,
+        };
+        (file_path, databases)
+    }
+
+    fn send_request(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn range_responses_contain_the_suffixes_and_counts() {
+        let (_file_path, databases) = get_databases("serve-range.txt");
+
+        let (status_code, body) = get_range_response(&databases, "/range/00000", false);
+        assert_eq!(200, status_code);
+        assert_eq!(
+            "0005AD76BD555C1D6D771DE417A4B87E4B4:4\r\n000A8DAE4228F821FB418F59826079BF368:2",
+            body
+        );
+
+        let (status_code, body) = get_range_response(&databases, "/range/5baa6", false);
+        assert_eq!(200, status_code);
+        assert_eq!("1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493", body);
+
+        let (status_code, body) = get_range_response(&databases, "/range/FFFFF", false);
+        assert_eq!(200, status_code);
+        assert_eq!("", body);
+    }
+
+    #[test]
+    fn invalid_range_requests_are_rejected() {
+        let (_file_path, databases) = get_databases("serve-invalid.txt");
+
+        assert_eq!(400, get_range_response(&databases, "/range/5BAA", false).0);
+        assert_eq!(400, get_range_response(&databases, "/range/5BAAX", false).0);
+        assert_eq!(
+            400,
+            get_range_response(&databases, "/range/5BAA6?mode=ntlm", false).0
+        );
+        assert_eq!(404, get_range_response(&databases, "/something", false).0);
+    }
+
+    #[test]
+    fn padded_range_responses_contain_additional_entries() {
+        let (_file_path, databases) = get_databases("serve-padding.txt");
+
+        let (status_code, body) = get_range_response(&databases, "/range/00000", true);
+        assert_eq!(200, status_code);
+        let lines: Vec<&str> = body.split("\r\n").collect();
+        assert_eq!(true, lines.len() >= MINIMUM_PADDED_ENTRIES);
+        assert_eq!(true, lines.len() <= MAXIMUM_PADDED_ENTRIES);
+        assert_eq!(
+            true,
+            lines.contains(&"0005AD76BD555C1D6D771DE417A4B87E4B4:4")
+        );
+        assert_eq!(
+            lines.len() - 2,
+            lines.iter().filter(|line| line.ends_with(":0")).count()
+        );
+        assert_eq!(true, lines.iter().all(|line| line.len() == 37));
+    }
+
+    #[test]
+    fn the_server_answers_requests_of_a_local_client() {
+        let (_file_path, databases) = get_databases("serve-http.txt");
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let address = server.server_addr().to_ip().unwrap();
+        let server_thread = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || serve_requests(&server, &databases))
+        };
+
+        let response = send_request(
+            address,
+            "GET /range/5BAA6 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(true, response.starts_with("HTTP/1.1 200"));
+        assert_eq!(
+            true,
+            response.ends_with("\r\n\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493")
+        );
+
+        let response = send_request(
+            address,
+            "GET /range/5BAA6 HTTP/1.1\r\nHost: localhost\r\nAdd-Padding: true\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(true, response.starts_with("HTTP/1.1 200"));
+        assert_eq!(
+            true,
+            response.matches(":0").count() >= MINIMUM_PADDED_ENTRIES - 1
+        );
+
+        let response = send_request(
+            address,
+            "GET /range/5BA HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(true, response.starts_with("HTTP/1.1 400"));
+
+        server.unblock();
+        server_thread.join().unwrap();
+    }
+}
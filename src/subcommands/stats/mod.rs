@@ -0,0 +1,541 @@
+use crate::haveibeenpwned::manifest::{
+    DEFAULT_PREFIX_LENGTH, MAXIMUM_PREFIX_LENGTH, MINIMUM_PREFIX_LENGTH,
+};
+use crate::haveibeenpwned::optimized::OptimizedDatabase;
+use crate::haveibeenpwned::DatabaseIterator;
+use crate::{HashDigest, PasswordHashEntry};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error};
+use serde::Serialize;
+use std::cmp::{min, Reverse};
+use std::collections::BinaryHeap;
+use std::path::Path;
+use std::process::exit;
+
+/// The default number of most common hashes which are reported.
+const DEFAULT_TOP_HASHES: usize = 10;
+
+/// The highest number of most common hashes for which memory is reserved in advance. The list
+/// grows as needed beyond this, since a data set might contain less hashes than requested.
+const MAXIMUM_RESERVED_TOP_HASHES: usize = 10_000;
+
+/// The number of hashes (and their occurrences) whose occurrence count is in a certain range.
+#[derive(Debug, PartialEq, Serialize)]
+struct HistogramBucket {
+    minimum_occurrences: u64,
+    maximum_occurrences: Option<u64>,
+    hashes: u64,
+    occurrences: u64,
+}
+
+impl HistogramBucket {
+    /// Get the range of occurrence counts of the bucket, e.g. `10-99`.
+    fn get_label(&self) -> String {
+        match self.maximum_occurrences {
+            Some(maximum) if maximum == self.minimum_occurrences => maximum.to_string(),
+            Some(maximum) => format!("{}-{}", self.minimum_occurrences, maximum),
+            None => format!("{}+", self.minimum_occurrences),
+        }
+    }
+}
+
+/// A hash which is one of the most common ones in the data set.
+#[derive(Debug, PartialEq, Serialize)]
+struct CommonHash {
+    hash: String,
+    occurrences: u64,
+}
+
+/// The number of hashes which start with a certain prefix.
+#[derive(Debug, PartialEq, Serialize)]
+struct PrefixSize {
+    prefix: String,
+    hashes: u64,
+}
+
+/// The aggregated numbers of a whole data set.
+#[derive(Serialize)]
+struct DatasetStatistics {
+    hashes: u64,
+    occurrences: u64,
+    malformed_lines: u64,
+    histogram: Vec<HistogramBucket>,
+    most_common: Vec<CommonHash>,
+    prefix_length: usize,
+    prefixes: Vec<PrefixSize>,
+}
+
+/// Get the index of the histogram bucket for the supplied occurrence count. The first bucket
+/// contains the hashes which were seen just once, the others one decimal magnitude each
+/// (2-9, 10-99, 100-999, ...).
+fn get_bucket_index(occurrences: u64) -> usize {
+    if occurrences <= 1 {
+        return 0;
+    }
+    let mut bucket_index = 1;
+    let mut upper_bound = 10;
+    while occurrences >= upper_bound {
+        bucket_index += 1;
+        upper_bound = match upper_bound.checked_mul(10) {
+            Some(bound) => bound,
+            None => break,
+        };
+    }
+    bucket_index
+}
+
+/// Get the range of the occurrence counts of the supplied histogram bucket.
+fn get_bucket_range(bucket_index: usize) -> (u64, Option<u64>) {
+    if bucket_index == 0 {
+        return (1, Some(1));
+    }
+    let minimum = if bucket_index == 1 {
+        2
+    } else {
+        10_u64.pow(bucket_index as u32 - 1)
+    };
+    let maximum = 10_u64
+        .checked_pow(bucket_index as u32)
+        .map(|bound| bound - 1);
+    (minimum, maximum)
+}
+
+/// This class collects the statistics of all entries of a data set.
+struct StatisticsCollector {
+    hashes: u64,
+    occurrences: u64,
+    malformed_lines: u64,
+    histogram: Vec<(u64, u64)>,
+    most_common: BinaryHeap<Reverse<(u64, HashDigest)>>,
+    top_hashes: usize,
+    prefix_length: usize,
+    prefix_sizes: Vec<u64>,
+}
+
+impl StatisticsCollector {
+    fn new(top_hashes: usize, prefix_length: usize) -> StatisticsCollector {
+        StatisticsCollector {
+            hashes: 0,
+            occurrences: 0,
+            malformed_lines: 0,
+            histogram: Vec::new(),
+            most_common: BinaryHeap::with_capacity(
+                min(top_hashes, MAXIMUM_RESERVED_TOP_HASHES) + 1,
+            ),
+            top_hashes,
+            prefix_length,
+            prefix_sizes: vec![0; 1 << (4 * prefix_length)],
+        }
+    }
+
+    fn add_entry(&mut self, entry: &PasswordHashEntry) {
+        let occurrences = entry.get_occurrences();
+        self.hashes += 1;
+        self.occurrences = self.occurrences.saturating_add(occurrences);
+
+        let bucket_index = get_bucket_index(occurrences);
+        if self.histogram.len() <= bucket_index {
+            self.histogram.resize(bucket_index + 1, (0, 0));
+        }
+        self.histogram[bucket_index].0 += 1;
+        self.histogram[bucket_index].1 = self.histogram[bucket_index].1.saturating_add(occurrences);
+
+        // just keep the most common hashes, the least common of them is always on top of the heap
+        if self.top_hashes > 0 {
+            self.most_common
+                .push(Reverse((occurrences, entry.get_digest())));
+            if self.most_common.len() > self.top_hashes {
+                self.most_common.pop();
+            }
+        }
+
+        // the entries are validated by the parser, so the prefix is always hexadecimal
+        let prefix_index = entry
+            .get_dynamic_prefix(self.prefix_length)
+            .and_then(|prefix| usize::from_str_radix(&prefix, 16).ok());
+        if let Some(prefix_index) = prefix_index {
+            self.prefix_sizes[prefix_index] += 1;
+        }
+    }
+
+    fn add_malformed_line(&mut self) {
+        self.malformed_lines += 1;
+    }
+
+    fn finish(self) -> DatasetStatistics {
+        let histogram = self
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(bucket_index, &(hashes, occurrences))| {
+                let (minimum_occurrences, maximum_occurrences) = get_bucket_range(bucket_index);
+                HistogramBucket {
+                    minimum_occurrences,
+                    maximum_occurrences,
+                    hashes,
+                    occurrences,
+                }
+            })
+            .collect();
+        let most_common = self
+            .most_common
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((occurrences, hash))| CommonHash {
+                hash: hash.to_string(),
+                occurrences,
+            })
+            .collect();
+        let prefix_length = self.prefix_length;
+        let prefixes = self
+            .prefix_sizes
+            .iter()
+            .enumerate()
+            .map(|(prefix_index, &hashes)| PrefixSize {
+                prefix: format!("{:0width$X}", prefix_index, width = prefix_length),
+                hashes,
+            })
+            .collect();
+
+        DatasetStatistics {
+            hashes: self.hashes,
+            occurrences: self.occurrences,
+            malformed_lines: self.malformed_lines,
+            histogram,
+            most_common,
+            prefix_length,
+            prefixes,
+        }
+    }
+}
+
+/// Get the share of the supplied number in the total in percent.
+fn get_percentage(value: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    value as f64 * 100.0 / total as f64
+}
+
+fn print_human_readable(statistics: &DatasetStatistics) {
+    println!("Hashes:          {}", statistics.hashes);
+    println!("Occurrences:     {}", statistics.occurrences);
+    if statistics.malformed_lines > 0 {
+        println!("Malformed lines: {}", statistics.malformed_lines);
+    }
+
+    println!();
+    println!("Occurrence histogram:");
+    for bucket in &statistics.histogram {
+        println!(
+            "  {:>24} {:>12} hashes ({:>6.2} %) {:>16} occurrences ({:>6.2} %)",
+            bucket.get_label(),
+            bucket.hashes,
+            get_percentage(bucket.hashes, statistics.hashes),
+            bucket.occurrences,
+            get_percentage(bucket.occurrences, statistics.occurrences)
+        );
+    }
+
+    println!();
+    println!("Most common hashes:");
+    for (rank, common_hash) in statistics.most_common.iter().enumerate() {
+        println!(
+            "  {:>4}. {} {:>12}",
+            rank + 1,
+            common_hash.hash,
+            common_hash.occurrences
+        );
+    }
+
+    // the sizes of all prefixes are just listed in the machine readable formats
+    println!();
+    println!(
+        "Hashes per prefix ({} characters):",
+        statistics.prefix_length
+    );
+    let smallest_prefix = statistics
+        .prefixes
+        .iter()
+        .min_by_key(|prefix_size| prefix_size.hashes);
+    let largest_prefix = statistics
+        .prefixes
+        .iter()
+        .max_by_key(|prefix_size| prefix_size.hashes);
+    if let (Some(smallest_prefix), Some(largest_prefix)) = (smallest_prefix, largest_prefix) {
+        println!(
+            "  smallest {} ({}), average {:.1}, largest {} ({}), {} without any hash",
+            smallest_prefix.hashes,
+            smallest_prefix.prefix,
+            statistics.hashes as f64 / statistics.prefixes.len() as f64,
+            largest_prefix.hashes,
+            largest_prefix.prefix,
+            statistics
+                .prefixes
+                .iter()
+                .filter(|prefix_size| prefix_size.hashes == 0)
+                .count()
+        );
+    }
+}
+
+/// Write the statistics as CSV with the columns `section,key,hashes,occurrences`.
+fn format_csv(statistics: &DatasetStatistics) -> String {
+    let mut lines = vec!["section,key,hashes,occurrences".to_string()];
+    lines.push(format!(
+        "summary,total,{},{}",
+        statistics.hashes, statistics.occurrences
+    ));
+    lines.push(format!(
+        "summary,malformed_lines,{},",
+        statistics.malformed_lines
+    ));
+    for bucket in &statistics.histogram {
+        lines.push(format!(
+            "histogram,{},{},{}",
+            bucket.get_label(),
+            bucket.hashes,
+            bucket.occurrences
+        ));
+    }
+    for common_hash in &statistics.most_common {
+        lines.push(format!(
+            "most_common,{},1,{}",
+            common_hash.hash, common_hash.occurrences
+        ));
+    }
+    for prefix_size in &statistics.prefixes {
+        lines.push(format!(
+            "prefix,{},{},",
+            prefix_size.prefix, prefix_size.hashes
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Collect the statistics of all entries of the supplied password file.
+fn collect_from_file(password_hash_path: &str, collector: &mut StatisticsCollector) {
+    let mut parser = match DatabaseIterator::from_file(password_hash_path) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            exit(-2);
+        }
+    };
+
+    let progress_bar = ProgressBar::new(parser.get_file_size().unwrap_or(0));
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        match maybe_password_hash_entry {
+            Ok(entry) => collector.add_entry(&entry),
+            Err(error) if error.is_fatal() => {
+                error!("Could not read the password file. {}", error);
+                exit(-2);
+            }
+            Err(_) => collector.add_malformed_line(),
+        }
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+    progress_bar.finish_and_clear();
+}
+
+/// Collect the statistics of all entries of the supplied optimized database.
+fn collect_from_folder(folder: &str, collector: &mut StatisticsCollector) {
+    let database = match OptimizedDatabase::from_folder(Path::new(folder)) {
+        Ok(database) => database,
+        Err(error) => {
+            error!(
+                "Could not open the optimized database in {}. The error was: {}",
+                folder, error
+            );
+            exit(-2);
+        }
+    };
+
+    let prefix_length = database.get_prefix_length();
+    let number_of_files = 1_u64 << (4 * prefix_length);
+    let progress_bar = ProgressBar::new(number_of_files);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({eta_precise})")
+            .progress_chars("#>-"),
+    );
+
+    for prefix_index in 0..number_of_files {
+        let prefix = format!("{:0width$X}", prefix_index, width = prefix_length);
+        match database.get_range(&prefix) {
+            Ok(entries) => entries.iter().for_each(|entry| collector.add_entry(entry)),
+            Err(error) => {
+                error!(
+                    "Could not read the file for the prefix {}. The error was: {}",
+                    prefix, error
+                );
+                exit(-2);
+            }
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the number of most common hashes and the prefix length for the shard sizes
+    let top_hashes = match matches.value_of("top") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(top_hashes) => top_hashes,
+            Err(_) => {
+                error!(
+                    "The supplied number of hashes ('{}') is not a number.",
+                    value
+                );
+                exit(-1);
+            }
+        },
+        None => DEFAULT_TOP_HASHES,
+    };
+    let prefix_length = match matches.value_of("prefix-length") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(length) if (MINIMUM_PREFIX_LENGTH..=MAXIMUM_PREFIX_LENGTH).contains(&length) => {
+                length
+            }
+            _ => {
+                error!(
+                    "The prefix length has to be a number between {} and {}.",
+                    MINIMUM_PREFIX_LENGTH, MAXIMUM_PREFIX_LENGTH
+                );
+                exit(-1);
+            }
+        },
+        None => DEFAULT_PREFIX_LENGTH,
+    };
+
+    // read all entries either from the password file or from the optimized database
+    let mut collector = StatisticsCollector::new(top_hashes, prefix_length);
+    match (
+        matches.value_of("password-hashes"),
+        matches.value_of("optimized-db-folder"),
+    ) {
+        (Some(path), _) => {
+            debug!("Collecting the statistics of the password file {}", path);
+            collect_from_file(path, &mut collector);
+        }
+        (None, Some(folder)) => {
+            debug!(
+                "Collecting the statistics of the optimized database {}",
+                folder
+            );
+            collect_from_folder(folder, &mut collector);
+        }
+        (None, None) => {
+            error!("Either a password file or an optimized database has to be provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    }
+    let statistics = collector.finish();
+
+    // print the statistics in the requested format
+    match matches.value_of("format") {
+        Some("json") => match serde_json::to_string_pretty(&statistics) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                error!("Could not format the statistics. The error was: {}", error);
+                exit(-3);
+            }
+        },
+        Some("csv") => println!("{}", format_csv(&statistics)),
+        _ => print_human_readable(&statistics),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_entry(hash: &str, occurrences: u64) -> PasswordHashEntry {
+        let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+        entry.occurrences = occurrences;
+        entry
+    }
+
+    #[test]
+    fn occurrences_are_grouped_by_their_magnitude() {
+        assert_eq!(0, get_bucket_index(1));
+        assert_eq!(1, get_bucket_index(2));
+        assert_eq!(1, get_bucket_index(9));
+        assert_eq!(2, get_bucket_index(10));
+        assert_eq!(7, get_bucket_index(3861493));
+        assert_eq!(20, get_bucket_index(u64::MAX));
+
+        assert_eq!((1, Some(1)), get_bucket_range(0));
+        assert_eq!((2, Some(9)), get_bucket_range(1));
+        assert_eq!((1000000, Some(9999999)), get_bucket_range(7));
+        assert_eq!((10_u64.pow(19), None), get_bucket_range(20));
+    }
+
+    #[test]
+    fn the_number_of_most_common_hashes_is_not_limited() {
+        let mut collector = StatisticsCollector::new(usize::MAX, 1);
+        collector.add_entry(&get_entry("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4));
+        collector.add_entry(&get_entry(
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8",
+            3861493,
+        ));
+        assert_eq!(2, collector.most_common.len());
+    }
+
+    #[test]
+    fn statistics_of_all_entries_are_collected() {
+        let mut collector = StatisticsCollector::new(2, 1);
+        collector.add_entry(&get_entry("000000005AD76BD555C1D6D771DE417A4B87E4B4", 4));
+        collector.add_entry(&get_entry("00000000A8DAE4228F821FB418F59826079BF368", 1));
+        collector.add_entry(&get_entry(
+            "5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8",
+            3861493,
+        ));
+        collector.add_entry(&get_entry(
+            "7C4A8D09CA3762AF61E59520943DC26494F8941B",
+            24230577,
+        ));
+        collector.add_malformed_line();
+        let statistics = collector.finish();
+
+        assert_eq!(4, statistics.hashes);
+        assert_eq!(28092075, statistics.occurrences);
+        assert_eq!(1, statistics.malformed_lines);
+        assert_eq!(9, statistics.histogram.len());
+        assert_eq!(1, statistics.histogram[0].hashes);
+        assert_eq!(4, statistics.histogram[1].occurrences);
+        assert_eq!("10000000-99999999", statistics.histogram[8].get_label());
+        assert_eq!(
+            vec![
+                CommonHash {
+                    hash: "7C4A8D09CA3762AF61E59520943DC26494F8941B".to_string(),
+                    occurrences: 24230577
+                },
+                CommonHash {
+                    hash: "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8".to_string(),
+                    occurrences: 3861493
+                }
+            ],
+            statistics.most_common
+        );
+        assert_eq!(16, statistics.prefixes.len());
+        assert_eq!(2, statistics.prefixes[0].hashes);
+        assert_eq!("5", statistics.prefixes[5].prefix);
+        assert_eq!(1, statistics.prefixes[5].hashes);
+
+        let csv = format_csv(&statistics);
+        assert_eq!(Some("section,key,hashes,occurrences"), csv.lines().next());
+        assert_eq!(true, csv.contains("\nsummary,total,4,28092075\n"));
+        assert_eq!(true, csv.contains("\nhistogram,1,1,1\n"));
+        assert_eq!(true, csv.contains("\nprefix,0,2,\n"));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::haveibeenpwned::manifest::{
+    AppliedDelta, DatabaseManifest, ManifestFileEntry, SourceFile,
+};
+use crate::haveibeenpwned::optimized::{get_temporary_path, ShardReader, ShardWriter};
+use crate::haveibeenpwned::{CreateInstanceError, DatabaseIterator, EntryOrder};
+use crate::{HashDigest, PasswordHashEntry};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error};
+use std::cmp::Ordering;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::exit;
+
+/// Merge the entries of the delta into the (ordered) entries of a file of the optimized database.
+/// The occurrence counts of the delta replace the ones of the existing entries with the same hash.
+fn merge_entries(
+    existing_entries: Vec<PasswordHashEntry>,
+    delta_entries: Vec<PasswordHashEntry>,
+) -> Vec<PasswordHashEntry> {
+    let mut merged_entries = Vec::with_capacity(existing_entries.len() + delta_entries.len());
+    let mut existing_entries = existing_entries.into_iter().peekable();
+    let mut delta_entries = delta_entries.into_iter().peekable();
+    loop {
+        let ordering = match (existing_entries.peek(), delta_entries.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(existing_entry), Some(delta_entry)) => existing_entry.cmp(delta_entry),
+        };
+
+        match ordering {
+            Ordering::Less => merged_entries.extend(existing_entries.next()),
+            Ordering::Greater => merged_entries.extend(delta_entries.next()),
+            Ordering::Equal => {
+                existing_entries.next();
+                merged_entries.extend(delta_entries.next());
+            }
+        }
+    }
+    merged_entries
+}
+
+/// Rewrite the file of the supplied prefix with the entries of the delta. The new file is written
+/// under a temporary name and renamed afterwards, so the lookups either see the old or the new
+/// file, but never a half-written one.
+fn update_file(
+    folder: &Path,
+    manifest: &DatabaseManifest,
+    prefix: &str,
+    delta_entries: Vec<PasswordHashEntry>,
+) -> ManifestFileEntry {
+    let path_to_file = manifest.get_file_path(folder, prefix);
+    let existing_entries = match ShardReader::from_file(&path_to_file) {
+        Ok(reader) => reader.get_range(prefix),
+        Err(CreateInstanceError::Io(ref error)) if error.kind() == ErrorKind::NotFound => {
+            Vec::new()
+        }
+        Err(error) => {
+            error!(
+                "Could not read {}. The error was: {}",
+                path_to_file.display(),
+                error
+            );
+            exit(-2);
+        }
+    };
+    let merged_entries = merge_entries(existing_entries, delta_entries);
+
+    let temporary_path = get_temporary_path(&path_to_file);
+    let write_result = ShardWriter::create(
+        &temporary_path,
+        manifest.get_hash_algorithm(),
+        manifest.is_binary(),
+    )
+    .and_then(|mut writer| {
+        for entry in &merged_entries {
+            writer.write_entry(entry)?;
+        }
+        writer.finish()
+    })
+    .and_then(|_| std::fs::rename(&temporary_path, &path_to_file))
+    .and_then(|_| ManifestFileEntry::from_file(prefix, &path_to_file, merged_entries.len() as u64));
+    match write_result {
+        Ok(file_entry) => file_entry,
+        Err(error) => {
+            error!(
+                "Could not write the updated file {}. The error was: {}",
+                path_to_file.display(),
+                error
+            );
+            exit(-7);
+        }
+    }
+}
+
+pub fn run_subcommand(matches: &ArgMatches) {
+    // get the path to the delta and to the optimized database which should be updated
+    let delta_path = match matches.value_of("delta") {
+        Some(path) => path,
+        None => {
+            error!("It seems that the path to the delta file was not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+    let password_hash_folder = match matches.value_of("optimized-db-folder") {
+        Some(path) => Path::new(path),
+        None => {
+            error!("It seems that the path to the folder for the optimized password hash files was not provided, please see the help for usage instructions.");
+            exit(-1);
+        }
+    };
+
+    // the layout of the files is taken from the manifest of the database
+    let mut manifest = match DatabaseManifest::from_folder(password_hash_folder) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            error!("The folder does not contain a manifest, so it can not be updated. Please optimize the password file again.");
+            exit(-2);
+        }
+        Err(error) => {
+            error!("Could not read the manifest. The error was: {}", error);
+            exit(-2);
+        }
+    };
+
+    // the same delta should not be applied twice (it would not change anything, but it would be
+    // recorded twice)
+    let delta_source = match SourceFile::from_file(Path::new(delta_path)) {
+        Ok(source) => source,
+        Err(error) => {
+            error!("Could not read the delta file. The error was: {}", error);
+            exit(-2);
+        }
+    };
+    let delta_version = matches
+        .value_of("delta-version")
+        .unwrap_or_else(|| delta_source.get_file_name())
+        .to_string();
+    if let Some(applied_delta) = manifest
+        .get_applied_deltas()
+        .iter()
+        .find(|applied_delta| applied_delta.get_source().get_sha256() == delta_source.get_sha256())
+    {
+        error!(
+            "The delta was already applied to the database as version {}",
+            applied_delta.get_version()
+        );
+        exit(-2);
+    }
+    debug!(
+        "Applying the delta {} (version {}) to {}",
+        delta_path,
+        delta_version,
+        password_hash_folder.display()
+    );
+
+    // get an instance of the password parser for the delta
+    let mut parser = match DatabaseIterator::from_file(delta_path) {
+        Ok(parser) => parser,
+        Err(error) => {
+            error!(
+                "Could not get an instance of the parser. The error was: {}",
+                error
+            );
+            exit(-2);
+        }
+    };
+    if parser.get_entry_order() == EntryOrder::Unordered {
+        error!("The delta file is not ordered by hash. Please use the delta written by the diff subcommand.");
+        exit(-2);
+    }
+
+    // get an instance from  the progress bar to indicate the update progress
+    let progress_bar = ProgressBar::new(parser.get_file_size().unwrap_or(0));
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta_precise})")
+        .progress_chars("#>-"));
+    progress_bar.set_draw_delta(1024 * 1024 * 8);
+
+    // collect the entries of every prefix and rewrite the corresponding file as soon as the
+    // next prefix starts
+    let mut current_prefix: Option<String> = None;
+    let mut current_entries = Vec::new();
+    let mut last_hash: Option<HashDigest> = None;
+    let mut delta_records = 0;
+    let mut updated_files = 0;
+    while let Some(maybe_password_hash_entry) = parser.next() {
+        let entry = match maybe_password_hash_entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                error!("Could not read the delta file. {}", error);
+                exit(-3);
+            }
+        };
+        if entry.get_algorithm() != manifest.get_hash_algorithm() {
+            error!(
+                "Line {} of the delta contains a {} hash, but the database stores {} hashes",
+                parser.get_line_count(),
+                entry.get_algorithm(),
+                manifest.get_hash_algorithm()
+            );
+            exit(-3);
+        }
+        let hash = entry.get_digest();
+        if let Some(last_hash) = &last_hash {
+            if hash <= *last_hash {
+                error!(
+                    "Line {} of the delta is not ordered by hash ({} follows {})",
+                    parser.get_line_count(),
+                    entry.get_hash(),
+                    last_hash
+                );
+                exit(-3);
+            }
+        }
+        last_hash = Some(hash);
+
+        let prefix = match manifest.get_prefix(&entry) {
+            Some(prefix) => prefix,
+            None => {
+                error!("Could not get the prefix of {}", entry.get_hash());
+                exit(-3);
+            }
+        };
+        if current_prefix.as_ref() != Some(&prefix) {
+            if let Some(current_prefix) = current_prefix.take() {
+                let file_entry = update_file(
+                    password_hash_folder,
+                    &manifest,
+                    &current_prefix,
+                    current_entries.split_off(0),
+                );
+                manifest.set_file(file_entry);
+                updated_files += 1;
+            }
+            current_prefix = Some(prefix);
+        }
+        current_entries.push(entry);
+        delta_records += 1;
+        progress_bar.set_position(parser.get_processed_bytes());
+    }
+    if let Some(current_prefix) = current_prefix {
+        let file_entry = update_file(
+            password_hash_folder,
+            &manifest,
+            &current_prefix,
+            current_entries,
+        );
+        manifest.set_file(file_entry);
+        updated_files += 1;
+    }
+    progress_bar.finish_with_message("updated");
+
+    // the manifest is written last, so an interrupted update can just be started again
+    manifest.add_applied_delta(AppliedDelta::new(
+        &delta_version,
+        delta_source,
+        delta_records,
+    ));
+    if let Err(error) = manifest.write_to_folder(password_hash_folder) {
+        error!(
+            "Could not write the manifest of the optimized database. The error was: {}",
+            error
+        );
+        exit(-7);
+    }
+    println!(
+        "Applied version {} of the delta ({} entries) to {} files of the database",
+        delta_version, delta_records, updated_files
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::optimized::OptimizedDatabase;
+    use crate::testing::TemporaryPath;
+    use crate::HashAlgorithm;
+
+    fn get_entries(entries: &[(&str, u64)]) -> Vec<PasswordHashEntry> {
+        entries
+            .iter()
+            .map(|&(hash, occurrences)| {
+                let mut entry = PasswordHashEntry::from_hash(hash).unwrap();
+                entry.occurrences = occurrences;
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_delta_replaces_and_adds_entries() {
+        let merged_entries = merge_entries(
+            get_entries(&[
+                ("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493),
+                ("5BAA8000000000000000000000000000000000FF", 2),
+            ]),
+            get_entries(&[
+                ("5BAA0000000000000000000000000000000000FF", 1),
+                ("5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8", 3861494),
+            ]),
+        );
+        let merged_entries: Vec<(String, u64)> = merged_entries
+            .iter()
+            .map(|entry| (entry.get_hash(), entry.get_occurrences()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("5BAA0000000000000000000000000000000000FF".to_string(), 1),
+                (
+                    "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8".to_string(),
+                    3861494
+                ),
+                ("5BAA8000000000000000000000000000000000FF".to_string(), 2),
+            ],
+            merged_entries
+        );
+    }
+
+    #[test]
+    fn updated_files_can_be_looked_up() {
+        let folder = TemporaryPath::new_folder("update-files");
+        let mut manifest = DatabaseManifest::new(3, false, true, HashAlgorithm::Sha1).unwrap();
+        manifest.set_file(update_file(
+            &folder,
+            &manifest,
+            "5BA",
+            get_entries(&[("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861493)]),
+        ));
+        manifest.set_file(update_file(
+            &folder,
+            &manifest,
+            "5BA",
+            get_entries(&[("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8", 3861494)]),
+        ));
+        manifest.write_to_folder(&folder).unwrap();
+
+        let database = OptimizedDatabase::from_folder(&folder).unwrap();
+        assert_eq!(
+            Some(3861494),
+            database
+                .get_password_count(&PasswordHashEntry::from_password("password"))
+                .unwrap()
+        );
+        assert_eq!(1, manifest.get_record_count());
+        assert_eq!(false, get_temporary_path(&folder.join("5BA.bin")).exists());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::haveibeenpwned::manifest::{DatabaseManifest, FileVerification};
+use crate::subcommands::exitcode::SubcommandError;
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, warn};
+use std::path::Path;
+
+/// Check every file which is listed in the manifest and look for files in the folder which are
+/// not listed in it. Returns the description of every problem which was found.
+fn find_problems(
+    folder: &Path,
+    manifest: &DatabaseManifest,
+    progress_bar: &ProgressBar,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for file_entry in manifest.get_files() {
+        let problem = match manifest.verify_file(folder, file_entry) {
+            Ok(FileVerification::Valid) => None,
+            Ok(FileVerification::Missing) => Some("is missing".to_string()),
+            Ok(FileVerification::Truncated) => Some("is truncated".to_string()),
+            Ok(FileVerification::Corrupted) => Some("is corrupted".to_string()),
+            Err(error) => Some(format!("could not be read ({})", error)),
+        };
+        if let Some(problem) = problem {
+            problems.push(format!(
+                "The file for the prefix {} {}",
+                file_entry.get_prefix(),
+                problem
+            ));
+        }
+        progress_bar.inc(1);
+    }
+
+    // files which are not listed (e.g. left behind by an interrupted optimization) are never
+    // used for lookups, but they might indicate that the database is not complete
+    match manifest.get_unlisted_files(folder) {
+        Ok(unlisted_files) => {
+            for unlisted_file in unlisted_files {
+                problems.push(format!(
+                    "The file {} is not listed in the manifest",
+                    unlisted_file.display()
+                ));
+            }
+        }
+        Err(error) => problems.push(format!(
+            "The folder could not be searched for files which are not listed in the manifest ({})",
+            error
+        )),
+    }
+    problems
+}
+
+pub fn run_subcommand(matches: &ArgMatches) -> Result<(), SubcommandError> {
+    // get the path to the optimized password database
+    let password_hash_folder = match matches.value_of("optimized-db-folder") {
+        Some(path) => Path::new(path),
+        None => {
+            error!("It seems that the path to the folder for the optimized password hash files was not provided, please see the help for usage instructions.");
+            return Err(SubcommandError::Usage);
+        }
+    };
+
+    // read the manifest which describes the content of the database
+    let manifest = match DatabaseManifest::from_folder(password_hash_folder) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            error!("The folder does not contain a manifest, so it can not be verified. Please optimize the password file again.");
+            return Err(SubcommandError::Database);
+        }
+        Err(error) => {
+            error!("Could not read the manifest. The error was: {}", error);
+            return Err(SubcommandError::from(&error));
+        }
+    };
+
+    // tell the user what we are going to verify
+    if let Some(version) = manifest.get_tool_version() {
+        debug!(
+            "The database was written by version {} of the tool",
+            version
+        );
+    }
+    if let Some(source) = manifest.get_source() {
+        println!(
+            "The database was created from {} ({} bytes, SHA-256 {}) with {} hashes",
+            source.get_file_name(),
+            source.get_file_size(),
+            source.get_sha256(),
+            manifest.get_hash_algorithm()
+        );
+    }
+    for applied_delta in manifest.get_applied_deltas() {
+        println!(
+            "Version {} of a delta ({}, {} entries) was applied to the database",
+            applied_delta.get_version(),
+            applied_delta.get_source().get_file_name(),
+            applied_delta.get_record_count()
+        );
+    }
+    if manifest.get_files().is_empty() {
+        warn!("The manifest does not list any files, it might be written by an older version of the tool.");
+    }
+
+    // get an instance from  the progress bar to indicate the verification progress
+    let progress_bar = ProgressBar::new(manifest.get_files().len() as u64);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({eta_precise})")
+            .progress_chars("#>-"),
+    );
+
+    // check every file of the folder against the manifest
+    let problems = find_problems(password_hash_folder, &manifest, &progress_bar);
+    progress_bar.finish_with_message("verified");
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        println!(
+            "Verified {} files: found {} missing, truncated, corrupted or unlisted files",
+            manifest.get_files().len(),
+            problems.len()
+        );
+        return Err(SubcommandError::CorruptData);
+    }
+    println!(
+        "Verified {} files with {} entries: the database is intact",
+        manifest.get_files().len(),
+        manifest.get_record_count()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haveibeenpwned::manifest::ManifestFileEntry;
+    use crate::testing::TemporaryPath;
+    use crate::HashAlgorithm;
+
+    fn get_database(name: &str, nested: bool) -> (TemporaryPath, DatabaseManifest) {
+        let folder = TemporaryPath::new_folder(name);
+        let mut manifest = DatabaseManifest::new(3, nested, false, HashAlgorithm::Sha1).unwrap();
+        for prefix in &["5BA", "7C4", "FFF"] {
+            let path_to_file = manifest.get_file_path(&folder, prefix);
+            std::fs::create_dir_all(path_to_file.parent().unwrap()).unwrap();
+            std::fs::write(
+                &path_to_file,
+                format!("{}00000000000000000000000000000000000:1\n", prefix),
+            )
+            .unwrap();
+            manifest.add_file(ManifestFileEntry::from_file(prefix, &path_to_file, 1).unwrap());
+        }
+        manifest.write_to_folder(&folder).unwrap();
+        (folder, manifest)
+    }
+
+    #[test]
+    fn an_intact_database_has_no_problems() {
+        let (folder, manifest) = get_database("verify-intact", true);
+        assert_eq!(
+            Vec::<String>::new(),
+            find_problems(&folder, &manifest, &ProgressBar::hidden())
+        );
+    }
+
+    #[test]
+    fn missing_and_corrupted_files_are_reported() {
+        let (folder, manifest) = get_database("verify-modified", false);
+        std::fs::remove_file(manifest.get_file_path(&folder, "5BA")).unwrap();
+        std::fs::write(
+            manifest.get_file_path(&folder, "FFF"),
+            "FFF00000000000000000000000000000000000:2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "The file for the prefix 5BA is missing".to_string(),
+                "The file for the prefix FFF is corrupted".to_string(),
+            ],
+            find_problems(&folder, &manifest, &ProgressBar::hidden())
+        );
+    }
+
+    #[test]
+    fn files_which_are_not_listed_in_the_manifest_are_reported() {
+        let (folder, manifest) = get_database("verify-unlisted", true);
+        std::fs::write(folder.join("sorted-password-file.txt"), "").unwrap();
+        std::fs::write(folder.join("7C").join("4.txt.tmp"), "").unwrap();
+
+        assert_eq!(
+            vec![
+                format!(
+                    "The file {} is not listed in the manifest",
+                    folder.join("7C").join("4.txt.tmp").display()
+                ),
+                format!(
+                    "The file {} is not listed in the manifest",
+                    folder.join("sorted-password-file.txt").display()
+                ),
+            ],
+            find_problems(&folder, &manifest, &ProgressBar::hidden())
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs::{create_dir_all, remove_dir_all, remove_file};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A file or a folder in the temporary folder of the system which is used by a test. It is
+/// removed (with all of its content) as soon as it is dropped, so nothing is left behind even if
+/// the test fails.
+pub(crate) struct TemporaryPath {
+    path: PathBuf,
+}
+
+impl TemporaryPath {
+    /// Get the path for a file with the supplied name. The name has to be unique within all
+    /// tests, since they are running at the same time.
+    pub(crate) fn new(name: &str) -> TemporaryPath {
+        let temporary_path = TemporaryPath {
+            path: std::env::temp_dir().join(format!("pwned-rs-{}-{}", std::process::id(), name)),
+        };
+        temporary_path.remove();
+        temporary_path
+    }
+
+    /// Create an empty folder with the supplied name.
+    pub(crate) fn new_folder(name: &str) -> TemporaryPath {
+        let temporary_path = TemporaryPath::new(name);
+        create_dir_all(&temporary_path.path).unwrap();
+        temporary_path
+    }
+
+    fn remove(&self) {
+        if self.path.is_dir() {
+            let _ = remove_dir_all(&self.path);
+        } else {
+            let _ = remove_file(&self.path);
+        }
+    }
+}
+
+impl Deref for TemporaryPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TemporaryPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TemporaryPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}